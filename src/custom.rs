@@ -0,0 +1,113 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Types allowing to run custom, LSPS-style protocols over the LSPS0 transport.
+//!
+//! Custom protocols are registered via [`LiquidityManager::register_custom_protocol_handler`].
+//! Their messages are exchanged using the same JSON-RPC framing and Lightning message type as the
+//! built-in protocols.
+//!
+//! [`LiquidityManager::register_custom_protocol_handler`]: crate::LiquidityManager::register_custom_protocol_handler
+
+use crate::lsps0::ser::{LSPSMessage, RequestId, ResponseError};
+use crate::prelude::{HashMap, String, Vec};
+use crate::sync::Arc;
+
+use lightning::ln::msgs::LightningError;
+
+use bitcoin::secp256k1::PublicKey;
+
+use core::convert::TryFrom;
+
+/// A handler for a custom protocol that is run over the LSPS0 transport.
+///
+/// Once registered via [`LiquidityManager::register_custom_protocol_handler`], any request for one
+/// of the handler's [`method_names`] will be passed to [`handle_request`], and any response to a
+/// request sent via [`LiquidityManager::send_custom_request`] will be passed to
+/// [`handle_response`].
+///
+/// [`LiquidityManager::register_custom_protocol_handler`]: crate::LiquidityManager::register_custom_protocol_handler
+/// [`LiquidityManager::send_custom_request`]: crate::LiquidityManager::send_custom_request
+/// [`method_names`]: Self::method_names
+/// [`handle_request`]: Self::handle_request
+/// [`handle_response`]: Self::handle_response
+pub trait CustomProtocolHandler {
+	/// The protocol number that will be advertised via LSPS0's `list_protocols`.
+	fn protocol_number(&self) -> u16;
+
+	/// The JSON-RPC method names handled by this protocol, e.g., `lsps42.get_quote`.
+	fn method_names(&self) -> Vec<String>;
+
+	/// Handles a request received from the given counterparty.
+	///
+	/// The returned result or error will be sent back to the counterparty as the JSON-RPC
+	/// response to the request.
+	fn handle_request(
+		&self, request_id: &RequestId, method: &str, params: serde_json::Value,
+		counterparty_node_id: &PublicKey,
+	) -> Result<serde_json::Value, ResponseError>;
+
+	/// Handles a response to a request we previously sent via
+	/// [`LiquidityManager::send_custom_request`].
+	///
+	/// [`LiquidityManager::send_custom_request`]: crate::LiquidityManager::send_custom_request
+	fn handle_response(
+		&self, request_id: RequestId, method: &str,
+		result: Result<serde_json::Value, ResponseError>, counterparty_node_id: &PublicKey,
+	) -> Result<(), LightningError>;
+}
+
+/// The registered custom protocol handlers, indexed by the method names they handle.
+pub(crate) type CustomProtocolHandlers =
+	HashMap<String, Arc<dyn CustomProtocolHandler + Send + Sync>>;
+
+/// A request of a custom protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomRequest {
+	/// The JSON-RPC method name.
+	pub method: String,
+	/// The JSON-RPC params.
+	pub params: serde_json::Value,
+}
+
+/// A response of a custom protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomResponse {
+	/// The JSON-RPC method name of the request this response corresponds to.
+	pub method: String,
+	/// The JSON-RPC result or error.
+	pub result: Result<serde_json::Value, ResponseError>,
+}
+
+/// A message of a custom protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CustomMessage {
+	/// A request variant.
+	Request(RequestId, CustomRequest),
+	/// A response variant.
+	Response(RequestId, CustomResponse),
+}
+
+impl TryFrom<LSPSMessage> for CustomMessage {
+	type Error = ();
+
+	fn try_from(message: LSPSMessage) -> Result<Self, Self::Error> {
+		if let LSPSMessage::Custom(message) = message {
+			return Ok(message);
+		}
+
+		Err(())
+	}
+}
+
+impl From<CustomMessage> for LSPSMessage {
+	fn from(message: CustomMessage) -> Self {
+		LSPSMessage::Custom(message)
+	}
+}
@@ -19,6 +19,7 @@ use crate::lsps0;
 #[cfg(lsps1)]
 use crate::lsps1;
 use crate::lsps2;
+use crate::lsps5;
 use crate::prelude::{Vec, VecDeque};
 use crate::sync::{Arc, Mutex};
 
@@ -107,6 +108,10 @@ pub enum Event {
 	LSPS2Client(lsps2::event::LSPS2ClientEvent),
 	/// An LSPS2 (JIT Channel) server event.
 	LSPS2Service(lsps2::event::LSPS2ServiceEvent),
+	/// An LSPS5 (Webhook Registration) client event.
+	LSPS5Client(lsps5::event::LSPS5ClientEvent),
+	/// An LSPS5 (Webhook Registration) server event.
+	LSPS5Service(lsps5::event::LSPS5ServiceEvent),
 }
 
 struct EventFuture {
@@ -0,0 +1,171 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Entry points for fuzzing the handling of untrusted peer input.
+//!
+//! Only available when building with `--cfg fuzzing`, and driven by the targets in the `fuzz`
+//! crate. Each entry point panics if it finds an invariant to be violated.
+
+use crate::lsps0::ser::{
+	DeserializationMode, LSPSMessage, LSPSMethod, RawLSPSMessage, RequestId, MAX_LSPS_MESSAGE_SIZE,
+};
+use crate::prelude::{HashMap, ToString};
+
+use lightning::io::Cursor;
+use lightning::util::ser::{Readable, Writeable};
+
+const METHODS: [LSPSMethod; 6] = [
+	LSPSMethod::LSPS0ListProtocols,
+	LSPSMethod::LSPS2GetInfo,
+	LSPSMethod::LSPS2Buy,
+	LSPSMethod::LSPS5SetWebhook,
+	LSPSMethod::LSPS5ListWebhooks,
+	LSPSMethod::LSPS5RemoveWebhook,
+];
+
+/// Reads the given fuzz input as an LSPS message from the wire, parsing any successfully read
+/// payload.
+pub fn raw_message_read(data: &[u8]) {
+	let mut reader = Cursor::new(data);
+	if let Ok(msg) = RawLSPSMessage::read(&mut reader) {
+		assert!(msg.payload.len() <= MAX_LSPS_MESSAGE_SIZE);
+		assert_eq!(msg.encode(), data);
+
+		for mode in [DeserializationMode::Permissive, DeserializationMode::Strict] {
+			check_parse(&msg.payload, &HashMap::new(), mode);
+		}
+	}
+}
+
+/// Parses the given fuzz input as an LSPS message, given a request-id map and a
+/// [`DeserializationMode`] read from its first bytes.
+pub fn message_parse(data: &[u8]) {
+	let mut reader = FuzzReader::new(data);
+	let mode = match reader.read_u8() {
+		Some(b) if b & 1 == 1 => DeserializationMode::Strict,
+		Some(_) => DeserializationMode::Permissive,
+		None => return,
+	};
+
+	let mut request_id_to_method_map = HashMap::new();
+	let num_entries = reader.read_u8().unwrap_or(0) % 8;
+	for _ in 0..num_entries {
+		match (reader.read_u8(), reader.read_u8()) {
+			(Some(id), Some(method)) => {
+				let method = METHODS[method as usize % METHODS.len()].clone();
+				request_id_to_method_map.insert(RequestId(id.to_string()), method);
+			},
+			_ => return,
+		}
+	}
+
+	if let Ok(json) = core::str::from_utf8(reader.remaining()) {
+		check_parse(json, &request_id_to_method_map, mode);
+	}
+}
+
+/// Drives a JIT channel through a sequence of intercepted HTLCs, channel-ready, failed-HTLC,
+/// forwarded-payment, and timer-tick events read from the given fuzz input.
+pub fn jit_channel_state(data: &[u8]) {
+	crate::lsps2::service::do_jit_channel_state_test(data)
+}
+
+fn check_parse(
+	json: &str, request_id_to_method_map: &HashMap<RequestId, LSPSMethod>,
+	mode: DeserializationMode,
+) {
+	let mut map = request_id_to_method_map.clone();
+	if let Ok(msg) = LSPSMessage::from_str_with_id_map(json, &mut map, &HashMap::new(), mode) {
+		// Our own serialization of any message we accept needs to be spec-compliant.
+		let payload = serde_json::to_string(&msg).unwrap();
+		let mut map = request_id_to_method_map.clone();
+		LSPSMessage::from_str_with_id_map(
+			&payload,
+			&mut map,
+			&HashMap::new(),
+			DeserializationMode::Strict,
+		)
+		.unwrap();
+	}
+}
+
+/// Allows to consume fuzz input piece by piece.
+pub(crate) struct FuzzReader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> FuzzReader<'a> {
+	pub(crate) fn new(data: &'a [u8]) -> Self {
+		Self { data }
+	}
+
+	pub(crate) fn read_u8(&mut self) -> Option<u8> {
+		let (byte, rest) = self.data.split_first()?;
+		self.data = rest;
+		Some(*byte)
+	}
+
+	pub(crate) fn read_u32(&mut self) -> Option<u32> {
+		if self.data.len() < 4 {
+			return None;
+		}
+		let (bytes, rest) = self.data.split_at(4);
+		self.data = rest;
+		Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	pub(crate) fn remaining(self) -> &'a [u8] {
+		self.data
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::prelude::Vec;
+
+	// Runs each target on pseudo-random inputs, making sure they don't bitrot.
+	#[test]
+	fn fuzz_targets_smoke_test() {
+		let mut state = 0x2545_f491_4f6c_dd1d_u64;
+		let mut next_byte = || {
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			state as u8
+		};
+
+		for len in 0..512 {
+			let data = (0..len).map(|_| next_byte()).collect::<Vec<_>>();
+			raw_message_read(&data);
+			message_parse(&data);
+			jit_channel_state(&data);
+		}
+
+		let json = r#"{"jsonrpc":"2.0","id":"7","result":{"protocols":[1,2]}}"#;
+		let mut data = vec![1, 1, 7, 0];
+		data.extend_from_slice(json.as_bytes());
+		message_parse(&data);
+		raw_message_read(json.as_bytes());
+	}
+
+	#[test]
+	fn jit_channel_state_scenario() {
+		// A fee of 1 sat for payments of 1 to 1000 sat, and an expected payment size of 100 sat.
+		let mut data = Vec::new();
+		for param in [1u32, 0, 1, 1000, 100] {
+			data.extend_from_slice(&param.to_be_bytes());
+		}
+		// Two HTLCs of payment A trigger the channel open, after which it becomes ready.
+		data.extend_from_slice(&[0, 0, 0, 0, 0, 60, 0, 0, 0, 0, 0, 40, 1]);
+		// Payment A fails, payment B pays the fee and succeeds, and payment C is forwarded as is.
+		data.extend_from_slice(&[2, 0, 1, 0, 0, 0, 100, 3, 0, 2, 0, 0, 0, 5]);
+		jit_channel_state(&data);
+	}
+}
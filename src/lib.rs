@@ -31,7 +31,13 @@ mod prelude {
 
 	#[cfg(feature = "hashbrown")]
 	pub use self::hashbrown::{hash_map, HashMap, HashSet};
-	pub use alloc::{boxed::Box, collections::VecDeque, string::String, vec, vec::Vec};
+	pub use alloc::{
+		boxed::Box,
+		collections::{BTreeMap, VecDeque},
+		string::String,
+		vec,
+		vec::Vec,
+	};
 	#[cfg(not(feature = "hashbrown"))]
 	pub use std::collections::{hash_map, HashMap, HashSet};
 
@@ -39,13 +45,17 @@ mod prelude {
 	pub use alloc::string::ToString;
 }
 
+pub mod custom;
 pub mod events;
+#[cfg(any(test, fuzzing))]
+pub mod fuzz;
 pub mod lsps0;
 #[cfg(lsps1)]
 // TODO: disallow warnings once the implementation is finished
 #[allow(warnings)]
 pub mod lsps1;
 pub mod lsps2;
+pub mod lsps5;
 mod manager;
 pub mod message_queue;
 mod sync;
@@ -53,4 +63,6 @@ mod sync;
 mod tests;
 mod utils;
 
-pub use manager::{LiquidityClientConfig, LiquidityManager, LiquidityServiceConfig};
+pub use manager::{
+	LiquidityClientConfig, LiquidityConfigError, LiquidityManager, LiquidityServiceConfig,
+};
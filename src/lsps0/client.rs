@@ -9,7 +9,7 @@ use crate::lsps0::event::LSPS0ClientEvent;
 use crate::lsps0::msgs::{
 	LSPS0Message, LSPS0Request, LSPS0Response, ListProtocolsRequest, ListProtocolsResponse,
 };
-use crate::lsps0::ser::{ProtocolMessageHandler, ResponseError};
+use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
 use crate::message_queue::MessageQueue;
 use crate::sync::Arc;
 use crate::utils;
@@ -48,13 +48,18 @@ where
 	/// Please refer to the [LSPS0
 	/// specifcation](https://github.com/BitcoinAndLightningLayerSpecs/lsp/tree/main/LSPS0#lsps-specification-support-query)
 	/// for more information.
-	pub fn list_protocols(&self, counterparty_node_id: &PublicKey) {
+	///
+	/// Returns the identifier of the issued request.
+	pub fn list_protocols(&self, counterparty_node_id: &PublicKey) -> RequestId {
+		let request_id = utils::generate_request_id(&self.entropy_source);
 		let msg = LSPS0Message::Request(
-			utils::generate_request_id(&self.entropy_source),
+			request_id.clone(),
 			LSPS0Request::ListProtocols(ListProtocolsRequest {}),
 		);
 
 		self.pending_messages.enqueue(counterparty_node_id, msg.into());
+
+		request_id
 	}
 
 	fn handle_response(
@@ -114,7 +119,7 @@ mod tests {
 	use alloc::string::ToString;
 	use alloc::sync::Arc;
 
-	use crate::lsps0::ser::{LSPSMessage, RequestId};
+	use crate::lsps0::ser::LSPSMessage;
 	use crate::tests::utils::{self, TestEntropy};
 
 	use super::*;
@@ -0,0 +1,258 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Types and utilities allowing clients to discover LSPs.
+//!
+//! LSPs signal support for the LSPS protocols by setting feature bit 729 in their node
+//! announcements. [`find_lsps`] lists such nodes in a [`NetworkGraph`], while
+//! [`LiquidityManager::discover_lsps`] additionally queries them for their supported protocols
+//! and fees, gathering the results in an [`LSPDirectory`].
+//!
+//! Note that only LSPS2 offers can be ranked via [`LSPDirectory::ranked_lsps2_quotes`]. LSPS1
+//! options don't include any fees, which are only quoted once an order is placed, so LSPs offering
+//! LSPS1 channels can merely be filtered based on the options stored in their
+//! [`LSPDirectoryEntry`].
+//!
+//! [`LiquidityManager::discover_lsps`]: crate::LiquidityManager::discover_lsps
+
+use crate::lsps2::msgs::OpeningFeeParams;
+use crate::lsps2::utils::compute_applicable_opening_fee;
+use crate::prelude::{HashMap, Vec};
+
+#[cfg(lsps1)]
+use crate::lsps1::msgs::OptionsSupported;
+
+use lightning::ln::features::NodeFeatures;
+use lightning::ln::msgs::SocketAddress;
+use lightning::routing::gossip::{NetworkGraph, NodeAlias};
+use lightning::util::logger::Logger;
+use lightning::util::ser::Writeable;
+
+use bitcoin::secp256k1::PublicKey;
+
+use core::ops::Deref;
+
+/// The feature bit LSPs set in their node announcements to signal support for the LSPS protocols.
+pub const LSPS_FEATURE_BIT: usize = 729;
+
+/// An LSP found in the [`NetworkGraph`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredLSP {
+	/// The node id of the LSP.
+	pub node_id: PublicKey,
+	/// The alias the LSP announced.
+	///
+	/// May be invalid or malicious (e.g., contain control characters), and hence should be
+	/// sanitized before being shown to the user.
+	pub alias: NodeAlias,
+	/// The addresses the LSP announced, via which we may connect to it.
+	pub addresses: Vec<SocketAddress>,
+}
+
+/// Returns whether the given features signal support for the LSPS protocols.
+pub fn supports_lsps(features: &NodeFeatures) -> bool {
+	// The flags are serialized big-endian, following a two-byte length prefix.
+	let mut flags = features.encode().split_off(2);
+	flags.reverse();
+	let is_set = |bit: usize| flags.get(bit / 8).copied().unwrap_or(0) & (1 << (bit % 8)) != 0;
+	is_set(LSPS_FEATURE_BIT) || is_set(LSPS_FEATURE_BIT - 1)
+}
+
+/// Lists all nodes in the given [`NetworkGraph`] that announced support for the LSPS protocols,
+/// ordered by their node id.
+pub fn find_lsps<L: Deref>(network_graph: &NetworkGraph<L>) -> Vec<DiscoveredLSP>
+where
+	L::Target: Logger,
+{
+	let read_only_graph = network_graph.read_only();
+	let mut lsps = read_only_graph
+		.nodes()
+		.unordered_iter()
+		.filter_map(|(node_id, node_info)| {
+			let announcement_info = node_info.announcement_info.as_ref()?;
+			if !supports_lsps(&announcement_info.features) {
+				return None;
+			}
+			Some(DiscoveredLSP {
+				node_id: node_id.as_pubkey().ok()?,
+				alias: announcement_info.alias,
+				addresses: announcement_info.addresses().to_vec(),
+			})
+		})
+		.collect::<Vec<_>>();
+	lsps.sort_by_key(|lsp| lsp.node_id);
+	lsps
+}
+
+/// What we learned about an LSP we queried.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LSPDirectoryEntry {
+	/// The node id of the LSP.
+	pub node_id: PublicKey,
+	/// The LSPS protocols the LSP supports, if it answered our `list_protocols` request yet.
+	pub protocols: Option<Vec<u16>>,
+	/// The LSPS1 options the LSP supports, if it answered our LSPS1 `get_info` request yet.
+	#[cfg(lsps1)]
+	pub lsps1_options: Option<OptionsSupported>,
+	/// The LSPS2 opening fee parameters the LSP offered, if it answered our LSPS2 `get_info`
+	/// request yet.
+	pub lsps2_opening_fee_params_menu: Option<Vec<OpeningFeeParams>>,
+}
+
+impl LSPDirectoryEntry {
+	pub(crate) fn new(node_id: PublicKey) -> Self {
+		Self {
+			node_id,
+			protocols: None,
+			#[cfg(lsps1)]
+			lsps1_options: None,
+			lsps2_opening_fee_params_menu: None,
+		}
+	}
+}
+
+/// An LSPS2 JIT channel offer for a specific payment size.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LSPS2Quote {
+	/// The node id of the LSP.
+	pub node_id: PublicKey,
+	/// The opening fee parameters the LSP offered.
+	pub opening_fee_params: OpeningFeeParams,
+	/// The opening fee the LSP would charge for the payment size the quote was requested for.
+	pub opening_fee_msat: u64,
+}
+
+/// A directory of the LSPs we queried, as returned by [`LiquidityManager::lsp_directory`].
+///
+/// [`LiquidityManager::lsp_directory`]: crate::LiquidityManager::lsp_directory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LSPDirectory {
+	entries: HashMap<PublicKey, LSPDirectoryEntry>,
+}
+
+impl LSPDirectory {
+	/// Returns the entries of all LSPs we queried, ordered by their node id.
+	pub fn entries(&self) -> Vec<&LSPDirectoryEntry> {
+		let mut entries = self.entries.values().collect::<Vec<_>>();
+		entries.sort_by_key(|entry| entry.node_id);
+		entries
+	}
+
+	/// Returns the entry for the given LSP, if we queried it.
+	pub fn entry(&self, node_id: &PublicKey) -> Option<&LSPDirectoryEntry> {
+		self.entries.get(node_id)
+	}
+
+	/// Returns the LSPS2 offers applicable to a payment of the given size, ranked by their opening
+	/// fee, cheapest first.
+	///
+	/// LSPs only supporting LSPS1 aren't included, as their fees are unknown before placing an
+	/// order.
+	///
+	/// Offers that expired at `now_secs`, given in seconds since the unix epoch, don't cover the
+	/// payment size, or whose opening fee wouldn't be smaller than the payment itself are excluded,
+	/// see [`compute_applicable_opening_fee`]. In no-std builds, the time of the latest block may be
+	/// given as `now_secs`.
+	pub fn ranked_lsps2_quotes(&self, payment_size_msat: u64, now_secs: u64) -> Vec<LSPS2Quote> {
+		let mut quotes = self
+			.entries
+			.values()
+			.flat_map(|entry| {
+				entry.lsps2_opening_fee_params_menu.iter().flatten().filter_map(move |params| {
+					let opening_fee_msat =
+						compute_applicable_opening_fee(params, payment_size_msat, now_secs)?;
+					Some(LSPS2Quote {
+						node_id: entry.node_id,
+						opening_fee_params: params.clone(),
+						opening_fee_msat,
+					})
+				})
+			})
+			.collect::<Vec<_>>();
+		quotes.sort_by_key(|quote| (quote.opening_fee_msat, quote.node_id));
+		quotes
+	}
+
+	pub(crate) fn insert(&mut self, node_id: PublicKey) {
+		self.entries.entry(node_id).or_insert_with(|| LSPDirectoryEntry::new(node_id));
+	}
+
+	pub(crate) fn entry_mut(&mut self, node_id: &PublicKey) -> Option<&mut LSPDirectoryEntry> {
+		self.entries.get_mut(node_id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::prelude::ToString;
+
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+	use chrono::{TimeZone, Utc};
+
+	fn opening_fee_params(min_fee_msat: u64, proportional: u32) -> OpeningFeeParams {
+		OpeningFeeParams {
+			min_fee_msat,
+			proportional,
+			valid_until: Utc.timestamp_opt(4_000_000_000, 0).unwrap(),
+			min_lifetime: 144,
+			max_client_to_self_delay: 128,
+			min_payment_size_msat: 1_000,
+			max_payment_size_msat: 100_000_000,
+			promise: "ignore".to_string(),
+		}
+	}
+
+	#[test]
+	fn detects_lsps_feature_bit() {
+		let mut features = NodeFeatures::empty();
+		assert!(!supports_lsps(&features));
+		features.set_optional_custom_bit(LSPS_FEATURE_BIT).unwrap();
+		assert!(supports_lsps(&features));
+
+		let mut features = NodeFeatures::empty();
+		features.set_required_custom_bit(LSPS_FEATURE_BIT).unwrap();
+		assert!(supports_lsps(&features));
+	}
+
+	#[test]
+	fn ranks_lsps2_quotes_by_fee() {
+		let secp_ctx = Secp256k1::new();
+		let node_a =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[1; 32]).unwrap());
+		let node_b =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[2; 32]).unwrap());
+
+		let mut directory = LSPDirectory::default();
+		directory.insert(node_a);
+		directory.insert(node_b);
+		directory.entry_mut(&node_a).unwrap().lsps2_opening_fee_params_menu =
+			Some(vec![opening_fee_params(2_000, 10_000), opening_fee_params(5_000, 1_000)]);
+		directory.entry_mut(&node_b).unwrap().lsps2_opening_fee_params_menu =
+			Some(vec![opening_fee_params(1_000, 5_000)]);
+
+		let now_secs = 1_700_000_000;
+
+		// For small payments the minimum fee dominates.
+		let quotes = directory.ranked_lsps2_quotes(100_000, now_secs);
+		let fees = quotes.iter().map(|q| (q.node_id, q.opening_fee_msat)).collect::<Vec<_>>();
+		assert_eq!(fees, vec![(node_b, 1_000), (node_a, 2_000), (node_a, 5_000)]);
+
+		// For large payments the proportional fee dominates.
+		let quotes = directory.ranked_lsps2_quotes(10_000_000, now_secs);
+		let fees = quotes.iter().map(|q| (q.node_id, q.opening_fee_msat)).collect::<Vec<_>>();
+		assert_eq!(fees, vec![(node_a, 10_000), (node_b, 50_000), (node_a, 100_000)]);
+
+		// Payments outside the offered range are excluded.
+		assert!(directory.ranked_lsps2_quotes(500, now_secs).is_empty());
+
+		// Expired offers are excluded.
+		assert!(directory.ranked_lsps2_quotes(100_000, 4_000_000_001).is_empty());
+	}
+}
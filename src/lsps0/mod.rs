@@ -10,6 +10,7 @@
 //! Types and primitives that implement the LSPS0: Transport Layer specification.
 
 pub mod client;
+pub mod discovery;
 pub mod event;
 pub mod msgs;
 pub mod ser;
@@ -75,10 +75,13 @@ impl TryFrom<LSPSMessage> for LSPS0Message {
 	fn try_from(message: LSPSMessage) -> Result<Self, Self::Error> {
 		match message {
 			LSPSMessage::Invalid(_) => Err(()),
+			LSPSMessage::RequestError(..) => Err(()),
 			LSPSMessage::LSPS0(message) => Ok(message),
 			#[cfg(lsps1)]
 			LSPSMessage::LSPS1(_) => Err(()),
 			LSPSMessage::LSPS2(_) => Err(()),
+			LSPSMessage::LSPS5(_) => Err(()),
+			LSPSMessage::Custom(_) => Err(()),
 		}
 	}
 }
@@ -92,9 +95,16 @@ impl From<LSPS0Message> for LSPSMessage {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use crate::lsps0::ser::LSPSMethod;
+	use crate::lsps0::ser::{
+		DeserializationMode, LSPSMethod, LSPSParseError, MessageTooLargeError, RawLSPSMessage,
+		MAX_JSON_NESTING_DEPTH, MAX_LSPS_MESSAGE_SIZE, MAX_REQUEST_ID_LENGTH,
+	};
 	use crate::prelude::{HashMap, ToString};
 
+	use lightning::io::Cursor;
+	use lightning::ln::msgs::DecodeError;
+	use lightning::util::ser::Readable;
+
 	#[test]
 	fn deserializes_request() {
 		let json = r#"{
@@ -105,7 +115,12 @@ mod tests {
 
 		let mut request_id_method_map = HashMap::new();
 
-		let msg = LSPSMessage::from_str_with_id_map(json, &mut request_id_method_map);
+		let msg = LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		);
 		assert!(msg.is_ok());
 		let msg = msg.unwrap();
 		assert_eq!(
@@ -143,8 +158,13 @@ mod tests {
 		request_id_to_method_map
 			.insert(RequestId("request:id:xyz123".to_string()), LSPSMethod::LSPS0ListProtocols);
 
-		let response =
-			LSPSMessage::from_str_with_id_map(json, &mut request_id_to_method_map).unwrap();
+		let response = LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		)
+		.unwrap();
 
 		assert_eq!(
 			response,
@@ -169,8 +189,13 @@ mod tests {
 		request_id_to_method_map
 			.insert(RequestId("request:id:xyz123".to_string()), LSPSMethod::LSPS0ListProtocols);
 
-		let response =
-			LSPSMessage::from_str_with_id_map(json, &mut request_id_to_method_map).unwrap();
+		let response = LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		)
+		.unwrap();
 
 		assert_eq!(
 			response,
@@ -198,10 +223,75 @@ mod tests {
 		request_id_to_method_map
 			.insert(RequestId("request:id:xyz123".to_string()), LSPSMethod::LSPS0ListProtocols);
 
-		let response = LSPSMessage::from_str_with_id_map(json, &mut request_id_to_method_map);
+		let response = LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		);
 		assert!(response.is_err());
 	}
 
+	#[test]
+	fn deserialize_fails_with_request_error() {
+		let mut request_id_to_method_map = HashMap::new();
+
+		// Unknown methods are answered with `-32601`, echoing the request id.
+		let json = r#"{"jsonrpc":"2.0","id":"request:id:xyz123","method":"lsps99.unknown"}"#;
+		match LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		) {
+			Err(LSPSParseError::InvalidRequest(request_id, error)) => {
+				assert_eq!(request_id, RequestId("request:id:xyz123".to_string()));
+				assert_eq!(error.code, -32601);
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		// Invalid params are answered with `-32602`, echoing the request id.
+		let json = r#"{"jsonrpc":"2.0","id":"request:id:xyz123","method":"lsps2.buy","params":{"payment_size_msat":"abc"}}"#;
+		match LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		) {
+			Err(LSPSParseError::InvalidRequest(request_id, error)) => {
+				assert_eq!(request_id, RequestId("request:id:xyz123".to_string()));
+				assert_eq!(error.code, -32602);
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		// Anything else is an invalid message.
+		let json = r#"{"jsonrpc":"2.0","id":"request:id:xyz123","method":5}"#;
+		match LSPSMessage::from_str_with_id_map(
+			json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		) {
+			Err(LSPSParseError::InvalidMessage(_)) => {},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn serializes_request_error() {
+		let response = LSPSMessage::RequestError(
+			RequestId("request:id:xyz123".to_string()),
+			ResponseError { code: -32601, message: "Method not found".to_string(), data: None },
+		);
+		let json = serde_json::to_string(&response).unwrap();
+		assert_eq!(
+			json,
+			r#"{"jsonrpc":"2.0","id":"request:id:xyz123","error":{"code":-32601,"message":"Method not found","data":null}}"#
+		);
+	}
+
 	#[test]
 	fn serializes_response() {
 		let response = LSPSMessage::LSPS0(LSPS0Message::Response(
@@ -214,4 +304,149 @@ mod tests {
 			r#"{"jsonrpc":"2.0","id":"request:id:xyz123","result":{"protocols":[1,2,3]}}"#
 		);
 	}
+
+	#[test]
+	fn deserialize_fails_with_excessive_nesting() {
+		let mut request_id_to_method_map = HashMap::new();
+
+		let depth = MAX_JSON_NESTING_DEPTH + 1;
+		let json = format!(
+			r#"{{"jsonrpc":"2.0","id":"request:id:xyz123","method":"lsps0.list_protocols","params":{}{}}}"#,
+			"[".repeat(depth),
+			"]".repeat(depth)
+		);
+		match LSPSMessage::from_str_with_id_map(
+			&json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		) {
+			Err(LSPSParseError::InvalidMessage(_)) => {},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		// Brackets within strings don't count towards the nesting depth.
+		let json = format!(
+			r#"{{"jsonrpc":"2.0","id":"{}","method":"lsps0.list_protocols","params":{{}}}}"#,
+			"[".repeat(depth)
+		);
+		assert!(LSPSMessage::from_str_with_id_map(
+			&json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive
+		)
+		.is_ok());
+	}
+
+	#[test]
+	fn deserialize_fails_with_overlong_request_id() {
+		let mut request_id_to_method_map = HashMap::new();
+
+		// Requests for unknown methods are usually answered, but not if we'd need to echo an
+		// overlong id.
+		let json = format!(
+			r#"{{"jsonrpc":"2.0","id":"{}","method":"unknown","params":{{}}}}"#,
+			"x".repeat(MAX_REQUEST_ID_LENGTH + 1)
+		);
+		match LSPSMessage::from_str_with_id_map(
+			&json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		) {
+			Err(LSPSParseError::InvalidMessage(_)) => {},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		let json = format!(
+			r#"{{"jsonrpc":"2.0","id":"{}","method":"lsps0.list_protocols","params":{{}}}}"#,
+			"x".repeat(MAX_REQUEST_ID_LENGTH)
+		);
+		assert!(LSPSMessage::from_str_with_id_map(
+			&json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive
+		)
+		.is_ok());
+	}
+
+	#[test]
+	fn enforces_message_size_limit() {
+		let request_id = RequestId("x".repeat(MAX_LSPS_MESSAGE_SIZE));
+		let request = LSPSMessage::LSPS0(LSPS0Message::Request(
+			request_id,
+			LSPS0Request::ListProtocols(ListProtocolsRequest {}),
+		));
+		match request.to_payload() {
+			Err(MessageTooLargeError { size }) => assert!(size > MAX_LSPS_MESSAGE_SIZE),
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		let payload = "x".repeat(MAX_LSPS_MESSAGE_SIZE + 1);
+		let mut reader = Cursor::new(payload.as_bytes());
+		assert_eq!(RawLSPSMessage::read(&mut reader), Err(DecodeError::InvalidValue));
+
+		let payload = "x".repeat(MAX_LSPS_MESSAGE_SIZE);
+		let mut reader = Cursor::new(payload.as_bytes());
+		assert_eq!(RawLSPSMessage::read(&mut reader).unwrap().payload, payload);
+	}
+
+	#[test]
+	fn strict_mode_rejects_spec_violations() {
+		let mut request_id_to_method_map = HashMap::new();
+
+		let parse = |json: &str, map: &mut HashMap<RequestId, LSPSMethod>, mode| {
+			LSPSMessage::from_str_with_id_map(json, map, &HashMap::new(), mode)
+		};
+
+		// Requests are answered with `-32600`, detailing the violation.
+		let requests = [
+			(r#"{"id":"a","method":"lsps0.list_protocols"}"#, "missing field `jsonrpc`"),
+			(
+				r#"{"jsonrpc":"1.0","id":"a","method":"lsps0.list_protocols"}"#,
+				r#"`jsonrpc` must be "2.0", got "1.0""#,
+			),
+			(
+				r#"{"jsonrpc":"2.0","id":"a","method":"lsps0.list_protocols","foo":1}"#,
+				"unknown field `foo`",
+			),
+			(
+				r#"{"jsonrpc":"2.0","id":"a","id":"b","method":"lsps0.list_protocols"}"#,
+				"duplicate field `id`",
+			),
+			(
+				r#"{"jsonrpc":"2.0","id":"a","method":"lsps0.list_protocols","result":{}}"#,
+				"both `method` and `result` are present",
+			),
+		];
+		for (json, reason) in requests {
+			assert!(
+				parse(json, &mut request_id_to_method_map, DeserializationMode::Permissive).is_ok()
+			);
+			match parse(json, &mut request_id_to_method_map, DeserializationMode::Strict) {
+				Err(LSPSParseError::InvalidRequest(_, error)) => {
+					assert_eq!(error.code, -32600);
+					assert_eq!(error.data, Some(serde_json::json!(reason)));
+				},
+				res => panic!("Unexpected result for {}: {:?}", json, res),
+			}
+		}
+
+		// Responses are rejected as invalid messages.
+		let json = r#"{"jsonrpc":"2.0","id":"a","result":{"protocols":[]},"error":{"code":-1,"message":"x"}}"#;
+		request_id_to_method_map.insert(RequestId("a".to_string()), LSPSMethod::LSPS0ListProtocols);
+		match parse(json, &mut request_id_to_method_map, DeserializationMode::Strict) {
+			Err(LSPSParseError::InvalidMessage(e)) => {
+				assert!(e.to_string().contains("both `result` and `error` are present"));
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		// Compliant messages are accepted.
+		let json = r#"{"jsonrpc":"2.0","id":"a","result":{"protocols":[]}}"#;
+		request_id_to_method_map.insert(RequestId("a".to_string()), LSPSMethod::LSPS0ListProtocols);
+		assert!(parse(json, &mut request_id_to_method_map, DeserializationMode::Strict).is_ok());
+	}
 }
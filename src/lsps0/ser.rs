@@ -2,6 +2,7 @@
 //!
 //! Please refer to the [LSPS0 specification](https://github.com/BitcoinAndLightningLayerSpecs/lsp/tree/main/LSPS0) for more information.
 
+use crate::custom::{CustomMessage, CustomProtocolHandlers, CustomRequest, CustomResponse};
 use crate::lsps0::msgs::{
 	LSPS0Message, LSPS0Request, LSPS0Response, ListProtocolsRequest,
 	LSPS0_LISTPROTOCOLS_METHOD_NAME,
@@ -15,10 +16,15 @@ use crate::lsps1::msgs::{
 use crate::lsps2::msgs::{
 	LSPS2Message, LSPS2Request, LSPS2Response, LSPS2_BUY_METHOD_NAME, LSPS2_GET_INFO_METHOD_NAME,
 };
-use crate::prelude::{HashMap, String, ToString};
+use crate::lsps5::msgs::{
+	LSPS5Message, LSPS5Request, LSPS5Response, LSPS5_LIST_WEBHOOKS_METHOD_NAME,
+	LSPS5_REMOVE_WEBHOOK_METHOD_NAME, LSPS5_SET_WEBHOOK_METHOD_NAME,
+};
+use crate::prelude::{HashMap, String, ToString, Vec};
 
 use lightning::ln::msgs::LightningError;
 use lightning::ln::wire;
+use lightning::util::errors::APIError;
 use lightning::util::ser::WithoutLength;
 
 use bitcoin::secp256k1::PublicKey;
@@ -26,7 +32,7 @@ use bitcoin::secp256k1::PublicKey;
 use core::fmt::{self, Display};
 use core::str::FromStr;
 
-use serde::de::{self, MapAccess, Visitor};
+use serde::de::{self, DeserializeOwned, MapAccess, Visitor};
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::json;
@@ -41,10 +47,18 @@ pub(crate) const JSONRPC_RESULT_FIELD_KEY: &str = "result";
 pub(crate) const JSONRPC_ERROR_FIELD_KEY: &str = "error";
 pub(crate) const JSONRPC_INVALID_MESSAGE_ERROR_CODE: i32 = -32700;
 pub(crate) const JSONRPC_INVALID_MESSAGE_ERROR_MESSAGE: &str = "parse error";
+pub(crate) const JSONRPC_INVALID_REQUEST_ERROR_CODE: i32 = -32600;
+pub(crate) const JSONRPC_INVALID_REQUEST_ERROR_MESSAGE: &str = "Invalid Request";
+pub(crate) const JSONRPC_METHOD_NOT_FOUND_ERROR_CODE: i32 = -32601;
+pub(crate) const JSONRPC_METHOD_NOT_FOUND_ERROR_MESSAGE: &str = "Method not found";
+pub(crate) const JSONRPC_INVALID_PARAMS_ERROR_CODE: i32 = -32602;
+pub(crate) const JSONRPC_INVALID_PARAMS_ERROR_MESSAGE: &str = "Invalid params";
+pub(crate) const JSONRPC_INTERNAL_ERROR_ERROR_CODE: i32 = -32603;
+pub(crate) const JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE: &str = "Internal error";
 
 pub(crate) const _LSPS0_CLIENT_REJECTED_ERROR_CODE: i32 = 001;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum LSPSMethod {
 	LSPS0ListProtocols,
 	#[cfg(lsps1)]
@@ -55,6 +69,10 @@ pub(crate) enum LSPSMethod {
 	LSPS1CreateOrder,
 	LSPS2GetInfo,
 	LSPS2Buy,
+	LSPS5SetWebhook,
+	LSPS5ListWebhooks,
+	LSPS5RemoveWebhook,
+	Custom(String),
 }
 
 impl FromStr for LSPSMethod {
@@ -70,6 +88,9 @@ impl FromStr for LSPSMethod {
 			LSPS1_GET_ORDER_METHOD_NAME => Ok(Self::LSPS1GetOrder),
 			LSPS2_GET_INFO_METHOD_NAME => Ok(Self::LSPS2GetInfo),
 			LSPS2_BUY_METHOD_NAME => Ok(Self::LSPS2Buy),
+			LSPS5_SET_WEBHOOK_METHOD_NAME => Ok(Self::LSPS5SetWebhook),
+			LSPS5_LIST_WEBHOOKS_METHOD_NAME => Ok(Self::LSPS5ListWebhooks),
+			LSPS5_REMOVE_WEBHOOK_METHOD_NAME => Ok(Self::LSPS5RemoveWebhook),
 			_ => Err(&"Unknown method name"),
 		}
 	}
@@ -87,6 +108,10 @@ impl Display for LSPSMethod {
 			Self::LSPS1GetOrder => LSPS1_GET_ORDER_METHOD_NAME,
 			Self::LSPS2GetInfo => LSPS2_GET_INFO_METHOD_NAME,
 			Self::LSPS2Buy => LSPS2_BUY_METHOD_NAME,
+			Self::LSPS5SetWebhook => LSPS5_SET_WEBHOOK_METHOD_NAME,
+			Self::LSPS5ListWebhooks => LSPS5_LIST_WEBHOOKS_METHOD_NAME,
+			Self::LSPS5RemoveWebhook => LSPS5_REMOVE_WEBHOOK_METHOD_NAME,
+			Self::Custom(method) => method,
 		};
 		write!(f, "{}", s)
 	}
@@ -120,13 +145,23 @@ impl From<&LSPS2Request> for LSPSMethod {
 	}
 }
 
+impl From<&LSPS5Request> for LSPSMethod {
+	fn from(value: &LSPS5Request) -> Self {
+		match value {
+			LSPS5Request::SetWebhook(_) => Self::LSPS5SetWebhook,
+			LSPS5Request::ListWebhooks(_) => Self::LSPS5ListWebhooks,
+			LSPS5Request::RemoveWebhook(_) => Self::LSPS5RemoveWebhook,
+		}
+	}
+}
+
 impl<'de> Deserialize<'de> for LSPSMethod {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where
 		D: Deserializer<'de>,
 	{
 		let s = <&str>::deserialize(deserializer)?;
-		FromStr::from_str(&s).map_err(de::Error::custom)
+		FromStr::from_str(s).map_err(de::Error::custom)
 	}
 }
 
@@ -142,6 +177,71 @@ impl Serialize for LSPSMethod {
 /// The Lightning message type id for LSPS messages.
 pub const LSPS_MESSAGE_TYPE_ID: u16 = 37913;
 
+/// The maximum size of an LSPS message's payload in bytes, as mandated by LSPS0.
+pub const MAX_LSPS_MESSAGE_SIZE: usize = 65531;
+
+/// The maximum nesting depth of JSON objects and arrays we accept in received LSPS messages.
+pub const MAX_JSON_NESTING_DEPTH: usize = 32;
+
+/// The maximum length of a [`RequestId`] we accept in received LSPS messages.
+///
+/// As we echo the id in our responses, this keeps them well below [`MAX_LSPS_MESSAGE_SIZE`].
+pub const MAX_REQUEST_ID_LENGTH: usize = 256;
+
+/// An error returned when an LSPS message would exceed [`MAX_LSPS_MESSAGE_SIZE`] once serialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageTooLargeError {
+	/// The size of the serialized message in bytes.
+	pub size: usize,
+}
+
+impl Display for MessageTooLargeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"LSPS message of {} bytes exceeds the maximum size of {} bytes",
+			self.size, MAX_LSPS_MESSAGE_SIZE
+		)
+	}
+}
+
+impl From<MessageTooLargeError> for APIError {
+	fn from(e: MessageTooLargeError) -> Self {
+		APIError::APIMisuseError { err: e.to_string() }
+	}
+}
+
+/// Returns whether the given JSON string nests objects or arrays deeper than `max_depth`.
+fn exceeds_nesting_depth(json_str: &str, max_depth: usize) -> bool {
+	let mut depth = 0usize;
+	let mut in_string = false;
+	let mut escaped = false;
+	for c in json_str.bytes() {
+		if in_string {
+			match c {
+				_ if escaped => escaped = false,
+				b'\\' => escaped = true,
+				b'"' => in_string = false,
+				_ => {},
+			}
+			continue;
+		}
+
+		match c {
+			b'"' => in_string = true,
+			b'{' | b'[' => {
+				depth += 1;
+				if depth > max_depth {
+					return true;
+				}
+			},
+			b'}' | b']' => depth = depth.saturating_sub(1),
+			_ => {},
+		}
+	}
+	false
+}
+
 /// A trait used to implement a specific LSPS protocol.
 ///
 /// The messages the protocol uses need to be able to be mapped
@@ -175,7 +275,10 @@ impl lightning::util::ser::Writeable for RawLSPSMessage {
 
 impl lightning::util::ser::Readable for RawLSPSMessage {
 	fn read<R: lightning::io::Read>(r: &mut R) -> Result<Self, lightning::ln::msgs::DecodeError> {
-		let payload_without_length = WithoutLength::read(r)?;
+		let payload_without_length: WithoutLength<String> = WithoutLength::read(r)?;
+		if payload_without_length.0.len() > MAX_LSPS_MESSAGE_SIZE {
+			return Err(lightning::ln::msgs::DecodeError::InvalidValue);
+		}
 		Ok(Self { payload: payload_without_length.0 })
 	}
 }
@@ -205,7 +308,25 @@ pub struct ResponseError {
 	/// A string providing a short description of the error.
 	pub message: String,
 	/// A primitive or structured value that contains additional information about the error.
-	pub data: Option<String>,
+	pub data: Option<serde_json::Value>,
+}
+
+/// Controls how strictly received messages are checked against the JSON-RPC 2.0 and LSPS0
+/// specifications.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeserializationMode {
+	/// Accepts any message we're able to make sense of.
+	///
+	/// Unknown top-level fields are ignored, the `jsonrpc` field isn't checked, and a `result`
+	/// takes precedence over a `method`, while an `error` takes precedence over a `result`.
+	#[default]
+	Permissive,
+	/// Rejects any message deviating from the specifications, e.g., as the `jsonrpc` field is missing
+	/// or not `"2.0"`, an unknown or duplicate top-level field is present, or a message carries
+	/// more than one of `method`, `result`, and `error`.
+	///
+	/// Mostly useful for compliance testing against other implementations.
+	Strict,
 }
 
 /// A (de-)serializable LSPS message allowing to be sent over the wire.
@@ -213,6 +334,9 @@ pub struct ResponseError {
 pub enum LSPSMessage {
 	/// An invalid variant.
 	Invalid(ResponseError),
+	/// An error response to a request that couldn't be processed, e.g., as its method is unknown
+	/// or its params are invalid.
+	RequestError(RequestId, ResponseError),
 	/// An LSPS0 message.
 	LSPS0(LSPS0Message),
 	/// An LSPS1 message.
@@ -220,19 +344,52 @@ pub enum LSPSMessage {
 	LSPS1(LSPS1Message),
 	/// An LSPS2 message.
 	LSPS2(LSPS2Message),
+	/// An LSPS5 message.
+	LSPS5(LSPS5Message),
+	/// A message of a custom protocol.
+	Custom(CustomMessage),
 }
 
 impl LSPSMessage {
 	/// A constructor returning an `LSPSMessage` from a raw JSON string.
 	///
 	/// The given `request_id_to_method` associates request ids with method names, as response objects
-	/// don't carry the latter.
+	/// don't carry the latter. Requests for methods of the given `custom_protocol_handlers` are
+	/// parsed as [`LSPSMessage::Custom`]. The message is checked according to the given `mode`.
 	pub(crate) fn from_str_with_id_map(
 		json_str: &str, request_id_to_method_map: &mut HashMap<RequestId, LSPSMethod>,
-	) -> Result<Self, serde_json::Error> {
+		custom_protocol_handlers: &CustomProtocolHandlers, mode: DeserializationMode,
+	) -> Result<Self, LSPSParseError> {
+		if exceeds_nesting_depth(json_str, MAX_JSON_NESTING_DEPTH) {
+			let e = de::Error::custom(format!(
+				"JSON nesting depth exceeds maximum of {}",
+				MAX_JSON_NESTING_DEPTH
+			));
+			return Err(LSPSParseError::InvalidMessage(e));
+		}
+
+		let mut request_error = None;
 		let deserializer = &mut serde_json::Deserializer::from_str(json_str);
-		let visitor = LSPSMessageVisitor { request_id_to_method_map };
-		deserializer.deserialize_any(visitor)
+		let visitor = LSPSMessageVisitor {
+			request_id_to_method_map,
+			custom_protocol_handlers,
+			request_error: &mut request_error,
+			mode,
+		};
+		deserializer.deserialize_any(visitor).map_err(|e| match request_error {
+			Some((request_id, error)) => LSPSParseError::InvalidRequest(request_id, error),
+			None => LSPSParseError::InvalidMessage(e),
+		})
+	}
+
+	/// Serializes the message, checking it doesn't exceed [`MAX_LSPS_MESSAGE_SIZE`].
+	pub(crate) fn to_payload(&self) -> Result<String, MessageTooLargeError> {
+		// Serializing an `LSPSMessage` never fails, as all maps are keyed by strings.
+		let payload = serde_json::to_string(self).unwrap_or_default();
+		if payload.len() > MAX_LSPS_MESSAGE_SIZE {
+			return Err(MessageTooLargeError { size: payload.len() });
+		}
+		Ok(payload)
 	}
 
 	/// Returns the request id and the method.
@@ -248,6 +405,12 @@ impl LSPSMessage {
 			LSPSMessage::LSPS2(LSPS2Message::Request(request_id, request)) => {
 				Some((RequestId(request_id.0.clone()), request.into()))
 			},
+			LSPSMessage::LSPS5(LSPS5Message::Request(request_id, request)) => {
+				Some((RequestId(request_id.0.clone()), request.into()))
+			},
+			LSPSMessage::Custom(CustomMessage::Request(request_id, request)) => {
+				Some((RequestId(request_id.0.clone()), LSPSMethod::Custom(request.method.clone())))
+			},
 			_ => None,
 		}
 	}
@@ -362,18 +525,122 @@ impl Serialize for LSPSMessage {
 					},
 				}
 			},
+			LSPSMessage::LSPS5(LSPS5Message::Request(request_id, request)) => {
+				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &request_id.0)?;
+				jsonrpc_object
+					.serialize_field(JSONRPC_METHOD_FIELD_KEY, &LSPSMethod::from(request))?;
+
+				match request {
+					LSPS5Request::SetWebhook(params) => {
+						jsonrpc_object.serialize_field(JSONRPC_PARAMS_FIELD_KEY, params)?
+					},
+					LSPS5Request::ListWebhooks(params) => {
+						jsonrpc_object.serialize_field(JSONRPC_PARAMS_FIELD_KEY, params)?
+					},
+					LSPS5Request::RemoveWebhook(params) => {
+						jsonrpc_object.serialize_field(JSONRPC_PARAMS_FIELD_KEY, params)?
+					},
+				}
+			},
+			LSPSMessage::LSPS5(LSPS5Message::Response(request_id, response)) => {
+				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &request_id.0)?;
+
+				match response {
+					LSPS5Response::SetWebhook(result) => {
+						jsonrpc_object.serialize_field(JSONRPC_RESULT_FIELD_KEY, result)?
+					},
+					LSPS5Response::SetWebhookError(error) => {
+						jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, error)?
+					},
+					LSPS5Response::ListWebhooks(result) => {
+						jsonrpc_object.serialize_field(JSONRPC_RESULT_FIELD_KEY, result)?
+					},
+					LSPS5Response::ListWebhooksError(error) => {
+						jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, error)?
+					},
+					LSPS5Response::RemoveWebhook(result) => {
+						jsonrpc_object.serialize_field(JSONRPC_RESULT_FIELD_KEY, result)?
+					},
+					LSPS5Response::RemoveWebhookError(error) => {
+						jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, error)?
+					},
+				}
+			},
+			LSPSMessage::Custom(CustomMessage::Request(request_id, request)) => {
+				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &request_id.0)?;
+				jsonrpc_object.serialize_field(JSONRPC_METHOD_FIELD_KEY, &request.method)?;
+				jsonrpc_object.serialize_field(JSONRPC_PARAMS_FIELD_KEY, &request.params)?;
+			},
+			LSPSMessage::Custom(CustomMessage::Response(request_id, response)) => {
+				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &request_id.0)?;
+
+				match &response.result {
+					Ok(result) => {
+						jsonrpc_object.serialize_field(JSONRPC_RESULT_FIELD_KEY, result)?
+					},
+					Err(error) => jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, error)?,
+				}
+			},
 			LSPSMessage::Invalid(error) => {
 				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &serde_json::Value::Null)?;
 				jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, &error)?;
 			},
+			LSPSMessage::RequestError(request_id, error) => {
+				jsonrpc_object.serialize_field(JSONRPC_ID_FIELD_KEY, &request_id.0)?;
+				jsonrpc_object.serialize_field(JSONRPC_ERROR_FIELD_KEY, &error)?;
+			},
 		}
 
 		jsonrpc_object.end()
 	}
 }
 
+/// An error returned when an [`LSPSMessage`] couldn't be parsed.
+#[derive(Debug)]
+pub(crate) enum LSPSParseError {
+	/// The message isn't a valid JSON-RPC object, or not a response to any of our requests.
+	InvalidMessage(serde_json::Error),
+	/// The message is a well-formed request we can't process, which is to be answered with the
+	/// given error.
+	InvalidRequest(RequestId, ResponseError),
+}
+
 struct LSPSMessageVisitor<'a> {
 	request_id_to_method_map: &'a mut HashMap<RequestId, LSPSMethod>,
+	custom_protocol_handlers: &'a CustomProtocolHandlers,
+	request_error: &'a mut Option<(RequestId, ResponseError)>,
+	mode: DeserializationMode,
+}
+
+impl<'a> LSPSMessageVisitor<'a> {
+	fn parse_params<T: DeserializeOwned, E: de::Error>(
+		&mut self, id: &RequestId, params: Option<serde_json::Value>,
+	) -> Result<T, E> {
+		serde_json::from_value(params.unwrap_or(json!({}))).map_err(|e| {
+			let error = ResponseError {
+				code: JSONRPC_INVALID_PARAMS_ERROR_CODE,
+				message: JSONRPC_INVALID_PARAMS_ERROR_MESSAGE.to_string(),
+				data: Some(json!(e.to_string())),
+			};
+			*self.request_error = Some((id.clone(), error));
+			de::Error::custom(e)
+		})
+	}
+
+	/// Rejects the message for violating the specifications, answering requests with `-32600`.
+	fn reject<E: de::Error>(
+		&mut self, id: Option<&RequestId>, is_request: bool, reason: &str,
+	) -> E {
+		if let (Some(id), true) = (id, is_request) {
+			let error = ResponseError {
+				code: JSONRPC_INVALID_REQUEST_ERROR_CODE,
+				message: JSONRPC_INVALID_REQUEST_ERROR_MESSAGE.to_string(),
+				data: Some(json!(reason)),
+			};
+			*self.request_error = Some((id.clone(), error));
+		}
+		de::Error::custom(format!("Received invalid JSON-RPC object: {}", reason))
+	}
 }
 
 impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
@@ -383,39 +650,87 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 		formatter.write_str("JSON-RPC object")
 	}
 
-	fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+	fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
 	where
 		A: MapAccess<'de>,
 	{
 		let mut id: Option<RequestId> = None;
-		let mut method: Option<LSPSMethod> = None;
+		let mut method: Option<String> = None;
 		let mut params = None;
 		let mut result = None;
 		let mut error: Option<ResponseError> = None;
+		let mut jsonrpc: Option<serde_json::Value> = None;
+		let mut violation: Option<String> = None;
+		let mut seen_keys: Vec<String> = Vec::new();
+
+		while let Some(key) = map.next_key::<String>()? {
+			if self.mode == DeserializationMode::Strict && violation.is_none() {
+				if seen_keys.contains(&key) {
+					violation = Some(format!("duplicate field `{}`", key));
+				}
+				seen_keys.push(key.clone());
+			}
 
-		while let Some(key) = map.next_key()? {
-			match key {
-				"id" => {
+			match key.as_str() {
+				JSONRPC_FIELD_KEY => {
+					jsonrpc = Some(map.next_value()?);
+				},
+				JSONRPC_ID_FIELD_KEY => {
 					id = map.next_value()?;
 				},
-				"method" => {
+				JSONRPC_METHOD_FIELD_KEY => {
 					method = Some(map.next_value()?);
 				},
-				"params" => {
+				JSONRPC_PARAMS_FIELD_KEY => {
 					params = Some(map.next_value()?);
 				},
-				"result" => {
+				JSONRPC_RESULT_FIELD_KEY => {
 					result = Some(map.next_value()?);
 				},
-				"error" => {
+				JSONRPC_ERROR_FIELD_KEY => {
 					error = Some(map.next_value()?);
 				},
 				_ => {
 					let _: serde_json::Value = map.next_value()?;
+					if self.mode == DeserializationMode::Strict && violation.is_none() {
+						violation = Some(format!("unknown field `{}`", key));
+					}
 				},
 			}
 		}
 
+		if let Some(id) = &id {
+			if id.0.len() > MAX_REQUEST_ID_LENGTH {
+				return Err(de::Error::custom(format!(
+					"Request id exceeds maximum length of {}",
+					MAX_REQUEST_ID_LENGTH
+				)));
+			}
+		}
+
+		if self.mode == DeserializationMode::Strict {
+			let violation = violation.or_else(|| match &jsonrpc {
+				Some(version) if version.as_str() == Some(JSONRPC_FIELD_VALUE) => None,
+				Some(version) => Some(format!(
+					"`{}` must be \"{}\", got {}",
+					JSONRPC_FIELD_KEY, JSONRPC_FIELD_VALUE, version
+				)),
+				None => Some(format!("missing field `{}`", JSONRPC_FIELD_KEY)),
+			});
+			let violation = violation.or_else(|| match (&method, &result, &error) {
+				(Some(_), Some(_), _) => Some("both `method` and `result` are present".to_string()),
+				(Some(_), _, Some(_)) => Some("both `method` and `error` are present".to_string()),
+				(_, Some(_), Some(_)) => Some("both `result` and `error` are present".to_string()),
+				(None, _, _) if params.is_some() => {
+					Some("`params` is present without a `method`".to_string())
+				},
+				_ => None,
+			});
+			if let Some(violation) = violation {
+				return Err(self.reject(id.as_ref(), method.is_some(), &violation));
+			}
+		}
+
 		let id = match id {
 			Some(id) => id,
 			None => {
@@ -436,6 +751,25 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 			},
 		};
 
+		let method = match method {
+			Some(method) => match LSPSMethod::from_str(&method) {
+				Ok(method) => Some(method),
+				Err(_) if self.custom_protocol_handlers.contains_key(&method) => {
+					Some(LSPSMethod::Custom(method))
+				},
+				Err(e) => {
+					let error = ResponseError {
+						code: JSONRPC_METHOD_NOT_FOUND_ERROR_CODE,
+						message: JSONRPC_METHOD_NOT_FOUND_ERROR_MESSAGE.to_string(),
+						data: None,
+					};
+					*self.request_error = Some((id, error));
+					return Err(de::Error::custom(e));
+				},
+			},
+			None => None,
+		};
+
 		match method {
 			Some(method) => match method {
 				LSPSMethod::LSPS0ListProtocols => Ok(LSPSMessage::LSPS0(LSPS0Message::Request(
@@ -444,8 +778,7 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 				))),
 				#[cfg(lsps1)]
 				LSPSMethod::LSPS1GetInfo => {
-					let request = serde_json::from_value(params.unwrap_or(json!({})))
-						.map_err(de::Error::custom)?;
+					let request = self.parse_params(&id, params)?;
 					Ok(LSPSMessage::LSPS1(LSPS1Message::Request(
 						id,
 						LSPS1Request::GetInfo(request),
@@ -453,8 +786,7 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 				},
 				#[cfg(lsps1)]
 				LSPSMethod::LSPS1CreateOrder => {
-					let request = serde_json::from_value(params.unwrap_or(json!({})))
-						.map_err(de::Error::custom)?;
+					let request = self.parse_params(&id, params)?;
 					Ok(LSPSMessage::LSPS1(LSPS1Message::Request(
 						id,
 						LSPS1Request::CreateOrder(request),
@@ -462,26 +794,51 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 				},
 				#[cfg(lsps1)]
 				LSPSMethod::LSPS1GetOrder => {
-					let request = serde_json::from_value(params.unwrap_or(json!({})))
-						.map_err(de::Error::custom)?;
+					let request = self.parse_params(&id, params)?;
 					Ok(LSPSMessage::LSPS1(LSPS1Message::Request(
 						id,
 						LSPS1Request::GetOrder(request),
 					)))
 				},
 				LSPSMethod::LSPS2GetInfo => {
-					let request = serde_json::from_value(params.unwrap_or(json!({})))
-						.map_err(de::Error::custom)?;
+					let request = self.parse_params(&id, params)?;
 					Ok(LSPSMessage::LSPS2(LSPS2Message::Request(
 						id,
 						LSPS2Request::GetInfo(request),
 					)))
 				},
 				LSPSMethod::LSPS2Buy => {
-					let request = serde_json::from_value(params.unwrap_or(json!({})))
-						.map_err(de::Error::custom)?;
+					let request = self.parse_params(&id, params)?;
 					Ok(LSPSMessage::LSPS2(LSPS2Message::Request(id, LSPS2Request::Buy(request))))
 				},
+				LSPSMethod::LSPS5SetWebhook => {
+					let request = self.parse_params(&id, params)?;
+					Ok(LSPSMessage::LSPS5(LSPS5Message::Request(
+						id,
+						LSPS5Request::SetWebhook(request),
+					)))
+				},
+				LSPSMethod::LSPS5ListWebhooks => {
+					let request = self.parse_params(&id, params)?;
+					Ok(LSPSMessage::LSPS5(LSPS5Message::Request(
+						id,
+						LSPS5Request::ListWebhooks(request),
+					)))
+				},
+				LSPSMethod::LSPS5RemoveWebhook => {
+					let request = self.parse_params(&id, params)?;
+					Ok(LSPSMessage::LSPS5(LSPS5Message::Request(
+						id,
+						LSPS5Request::RemoveWebhook(request),
+					)))
+				},
+				LSPSMethod::Custom(method) => {
+					let params = params.unwrap_or(json!({}));
+					Ok(LSPSMessage::Custom(CustomMessage::Request(
+						id,
+						CustomRequest { method, params },
+					)))
+				},
 			},
 			None => match self.request_id_to_method_map.remove(&id) {
 				Some(method) => match method {
@@ -590,6 +947,70 @@ impl<'de, 'a> Visitor<'de> for LSPSMessageVisitor<'a> {
 							Err(de::Error::custom("Received invalid JSON-RPC object: one of method, result, or error required"))
 						}
 					},
+					LSPSMethod::LSPS5SetWebhook => {
+						if let Some(error) = error {
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::SetWebhookError(error),
+							)))
+						} else if let Some(result) = result {
+							let response =
+								serde_json::from_value(result).map_err(de::Error::custom)?;
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::SetWebhook(response),
+							)))
+						} else {
+							Err(de::Error::custom("Received invalid JSON-RPC object: one of method, result, or error required"))
+						}
+					},
+					LSPSMethod::LSPS5ListWebhooks => {
+						if let Some(error) = error {
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::ListWebhooksError(error),
+							)))
+						} else if let Some(result) = result {
+							let response =
+								serde_json::from_value(result).map_err(de::Error::custom)?;
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::ListWebhooks(response),
+							)))
+						} else {
+							Err(de::Error::custom("Received invalid JSON-RPC object: one of method, result, or error required"))
+						}
+					},
+					LSPSMethod::LSPS5RemoveWebhook => {
+						if let Some(error) = error {
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::RemoveWebhookError(error),
+							)))
+						} else if let Some(result) = result {
+							let response =
+								serde_json::from_value(result).map_err(de::Error::custom)?;
+							Ok(LSPSMessage::LSPS5(LSPS5Message::Response(
+								id,
+								LSPS5Response::RemoveWebhook(response),
+							)))
+						} else {
+							Err(de::Error::custom("Received invalid JSON-RPC object: one of method, result, or error required"))
+						}
+					},
+					LSPSMethod::Custom(method) => {
+						let result = if let Some(error) = error {
+							Err(error)
+						} else if let Some(result) = result {
+							Ok(result)
+						} else {
+							return Err(de::Error::custom("Received invalid JSON-RPC object: one of method, result, or error required"));
+						};
+						Ok(LSPSMessage::Custom(CustomMessage::Response(
+							id,
+							CustomResponse { method, result },
+						)))
+					},
 				},
 				None => Err(de::Error::custom(format!(
 					"Received response for unknown request id: {}",
@@ -17,7 +17,7 @@ use crate::lsps0::msgs::{LSPS0Message, LSPS0Request, LSPS0Response, ListProtocol
 use crate::lsps0::ser::{ProtocolMessageHandler, RequestId};
 use crate::message_queue::MessageQueue;
 use crate::prelude::Vec;
-use crate::sync::Arc;
+use crate::sync::{Arc, RwLock};
 
 use lightning::ln::msgs::{ErrorAction, LightningError};
 use lightning::util::logger::Level;
@@ -27,13 +27,21 @@ use bitcoin::secp256k1::PublicKey;
 /// The main server-side object allowing to send and receive LSPS0 messages.
 pub struct LSPS0ServiceHandler {
 	pending_messages: Arc<MessageQueue>,
-	protocols: Vec<u16>,
+	protocols: RwLock<Vec<u16>>,
 }
 
 impl LSPS0ServiceHandler {
 	/// Returns a new instance of [`LSPS0ServiceHandler`].
 	pub(crate) fn new(protocols: Vec<u16>, pending_messages: Arc<MessageQueue>) -> Self {
-		Self { protocols, pending_messages }
+		Self { protocols: RwLock::new(protocols), pending_messages }
+	}
+
+	/// Adds the given protocol to the ones advertised via `list_protocols`.
+	pub(crate) fn add_protocol(&self, protocol_number: u16) {
+		let mut protocols = self.protocols.write().unwrap();
+		if !protocols.contains(&protocol_number) {
+			protocols.push(protocol_number);
+		}
 	}
 
 	fn handle_request(
@@ -44,7 +52,7 @@ impl LSPS0ServiceHandler {
 				let msg = LSPS0Message::Response(
 					request_id,
 					LSPS0Response::ListProtocols(ListProtocolsResponse {
-						protocols: self.protocols.clone(),
+						protocols: self.protocols.read().unwrap().clone(),
 					}),
 				);
 				self.pending_messages.enqueue(counterparty_node_id, msg.into());
@@ -14,11 +14,11 @@ use super::msgs::{
 	CreateOrderRequest, CreateOrderResponse, GetInfoRequest, GetInfoResponse, GetOrderRequest,
 	LSPS1Message, LSPS1Request, LSPS1Response, OptionsSupported, OrderId, OrderParams,
 };
-use super::utils::is_valid;
+use super::utils::validate_order;
 use crate::message_queue::MessageQueue;
 
 use crate::events::{Event, EventQueue};
-use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
+use crate::lsps0::ser::{LSPSMessage, ProtocolMessageHandler, RequestId, ResponseError};
 use crate::prelude::{HashMap, String, ToString, Vec};
 use crate::sync::{Arc, Mutex, RwLock};
 
@@ -40,6 +40,50 @@ pub struct LSPS1ClientConfig {
 	pub max_channel_fees_msat: Option<u64>,
 }
 
+/// Details about a channel request we track, as returned by
+/// [`LSPS1ClientHandler::list_channel_requests`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelRequestDetails {
+	/// The node id of the LSP the channel is requested from.
+	pub counterparty_node_id: PublicKey,
+	/// The `user_channel_id` given to [`LSPS1ClientHandler::send_get_info_request`].
+	pub user_channel_id: u128,
+	/// The state the channel request is in.
+	pub state: ChannelRequestState,
+	/// The identifiers of the requests we didn't receive a response to yet.
+	pub pending_request_ids: Vec<RequestId>,
+	/// The number of seconds since the channel request was initiated, or [`Option::None`] in
+	/// no-std builds.
+	pub age_secs: Option<u64>,
+}
+
+/// The state of a channel request, see [`ChannelRequestDetails::state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelRequestState {
+	/// We are awaiting the LSP's response to the `get_info` request.
+	InfoRequested,
+	/// We received the options supported by the LSP, and may place an order.
+	OptionsReceived {
+		/// The options supported by the LSP.
+		options_supported: OptionsSupported,
+	},
+	/// We are awaiting the LSP's response to the `create_order` request.
+	OrderRequested {
+		/// The order we placed.
+		order: OrderParams,
+	},
+	/// The LSP created the order, and is awaiting its payment.
+	PendingPayment {
+		/// The identifier of the order.
+		order_id: OrderId,
+	},
+	/// We are awaiting the LSP to confirm the payment and open the channel.
+	AwaitingConfirmation {
+		/// The identifier of the order.
+		order_id: OrderId,
+	},
+}
+
 struct ChannelStateError(String);
 
 impl From<ChannelStateError> for LightningError {
@@ -73,14 +117,15 @@ impl InboundRequestState {
 	fn order_requested(&self, order: OrderParams) -> Result<Self, ChannelStateError> {
 		match self {
 			InboundRequestState::OptionsSupport { options_supported } => {
-				if is_valid(&order, options_supported) {
-					Ok(InboundRequestState::OrderRequested { order })
-				} else {
+				if let Err(violations) = validate_order(&order, options_supported) {
+					let violations: Vec<String> =
+						violations.iter().map(|violation| violation.to_string()).collect();
 					return Err(ChannelStateError(format!(
-						"The order created does not match options supported by LSP. Options Supported by LSP are {:?}. The order created was {:?}",
-						options_supported, order
+						"The order created does not match options supported by LSP: {}",
+						violations.join(", ")
 					)));
 				}
+				Ok(InboundRequestState::OrderRequested { order })
 			},
 			state => Err(ChannelStateError(format!(
 				"Received create order request for wrong channel. Channel was in state: {:?}",
@@ -129,11 +174,45 @@ impl InboundRequestState {
 struct InboundCRChannel {
 	user_channel_id: u128,
 	state: InboundRequestState,
+	created_at: Option<u64>,
 }
 
 impl InboundCRChannel {
 	fn new(user_channel_id: u128) -> Self {
-		Self { user_channel_id, state: InboundRequestState::InfoRequested }
+		Self {
+			user_channel_id,
+			state: InboundRequestState::InfoRequested,
+			created_at: crate::utils::seconds_since_epoch(),
+		}
+	}
+
+	fn details(
+		&self, counterparty_node_id: PublicKey, pending_request_ids: Vec<RequestId>,
+	) -> ChannelRequestDetails {
+		let state = match &self.state {
+			InboundRequestState::InfoRequested => ChannelRequestState::InfoRequested,
+			InboundRequestState::OptionsSupport { options_supported } => {
+				ChannelRequestState::OptionsReceived {
+					options_supported: options_supported.clone(),
+				}
+			},
+			InboundRequestState::OrderRequested { order } => {
+				ChannelRequestState::OrderRequested { order: order.clone() }
+			},
+			InboundRequestState::PendingPayment { order_id } => {
+				ChannelRequestState::PendingPayment { order_id: order_id.clone() }
+			},
+			InboundRequestState::AwaitingConfirmation { order_id, .. } => {
+				ChannelRequestState::AwaitingConfirmation { order_id: order_id.clone() }
+			},
+		};
+		ChannelRequestDetails {
+			counterparty_node_id,
+			user_channel_id: self.user_channel_id,
+			state,
+			pending_request_ids,
+			age_secs: crate::utils::seconds_elapsed_since(self.created_at),
+		}
 	}
 
 	fn info_received(&mut self, options: OptionsSupported) -> Result<(), LightningError> {
@@ -194,6 +273,21 @@ impl PeerState {
 	fn remove_inbound_channel(&mut self, user_channel_id: u128) {
 		self.inbound_channels_by_id.remove(&user_channel_id);
 	}
+
+	fn channel_requests(&self, counterparty_node_id: PublicKey) -> Vec<ChannelRequestDetails> {
+		self.inbound_channels_by_id
+			.values()
+			.map(|channel| {
+				let pending_request_ids = self
+					.request_to_cid
+					.iter()
+					.filter(|(_, user_channel_id)| **user_channel_id == channel.user_channel_id)
+					.map(|(request_id, _)| request_id.clone())
+					.collect();
+				channel.details(counterparty_node_id, pending_request_ids)
+			})
+			.collect()
+	}
 }
 
 /// The main object allowing to send and receive LSPS1 messages.
@@ -240,7 +334,11 @@ where
 	/// `counterparty_node_id` is the node_id of the LSP you would like to use.
 	///
 	/// `user_channel_id` is the id used to uniquely identify the channel with counterparty node.
-	pub fn send_get_info_request(&self, counterparty_node_id: PublicKey, user_channel_id: u128) {
+	///
+	/// Returns the identifier of the issued request.
+	pub fn send_get_info_request(
+		&self, counterparty_node_id: PublicKey, user_channel_id: u128,
+	) -> RequestId {
 		let channel = InboundCRChannel::new(user_channel_id);
 
 		let request_id = crate::utils::generate_request_id(&self.entropy_source);
@@ -256,8 +354,58 @@ where
 		}
 
 		let request = LSPS1Request::GetInfo(GetInfoRequest {});
-		let msg = LSPS1Message::Request(request_id, request).into();
+		let msg = LSPS1Message::Request(request_id.clone(), request).into();
 		self.pending_messages.enqueue(&counterparty_node_id, msg);
+
+		request_id
+	}
+
+	/// Returns the channel requests we track with any LSP.
+	pub fn list_channel_requests(&self) -> Vec<ChannelRequestDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		outer_state_lock
+			.iter()
+			.flat_map(|(counterparty_node_id, inner_state_lock)| {
+				inner_state_lock.lock().unwrap().channel_requests(*counterparty_node_id)
+			})
+			.collect()
+	}
+
+	/// Returns the channel requests we track with the given LSP.
+	pub fn list_channel_requests_with_counterparty(
+		&self, counterparty_node_id: &PublicKey,
+	) -> Vec<ChannelRequestDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				inner_state_lock.lock().unwrap().channel_requests(*counterparty_node_id)
+			},
+			None => Vec::new(),
+		}
+	}
+
+	/// Stops tracking the channel request with the given `user_channel_id`, e.g., if the LSP
+	/// doesn't respond to it.
+	///
+	/// Responses the LSP sends later on to any of the channel request's pending requests will be
+	/// ignored.
+	pub fn cancel_channel_request(
+		&self, counterparty_node_id: &PublicKey, user_channel_id: u128,
+	) -> Result<(), APIError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let inner_state_lock =
+			outer_state_lock.get(counterparty_node_id).ok_or_else(|| APIError::APIMisuseError {
+				err: format!("No existing state with counterparty {}", counterparty_node_id),
+			})?;
+		let mut peer_state_lock = inner_state_lock.lock().unwrap();
+
+		if peer_state_lock.inbound_channels_by_id.remove(&user_channel_id).is_none() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel with user_channel_id {} not found", user_channel_id),
+			});
+		}
+		peer_state_lock.request_to_cid.retain(|_, cid| *cid != user_channel_id);
+		Ok(())
 	}
 
 	fn handle_get_info_response(
@@ -356,6 +504,9 @@ where
 	///
 	/// Should be called in response to receiving a [`LSPS1ClientEvent::GetInfoResponse`] event.
 	///
+	/// Returns an error if the order's `token` is too long for the request to fit into an LSPS
+	/// message.
+	///
 	/// [`LSPS1ClientEvent::GetInfoResponse`]: crate::lsps1::event::LSPS1ClientEvent::GetInfoResponse
 	pub fn place_order(
 		&self, user_channel_id: u128, counterparty_node_id: &PublicKey, order: OrderParams,
@@ -377,7 +528,16 @@ where
 							),
 						})?;
 
-					match inbound_channel.order_requested(order.clone()) {
+					// The order's `token` is user-provided, so we make sure the request fits into a
+					// message before updating the channel request's state.
+					let request_id = crate::utils::generate_request_id(&self.entropy_source);
+					let request =
+						LSPS1Request::CreateOrder(CreateOrderRequest { order: order.clone() });
+					let msg: LSPSMessage =
+						LSPS1Message::Request(request_id.clone(), request).into();
+					msg.to_payload()?;
+
+					match inbound_channel.order_requested(order) {
 						Ok(()) => (),
 						Err(e) => {
 							peer_state_lock.remove_inbound_channel(user_channel_id);
@@ -385,9 +545,6 @@ where
 						},
 					};
 
-					let request_id = crate::utils::generate_request_id(&self.entropy_source);
-					let request = LSPS1Request::CreateOrder(CreateOrderRequest { order });
-					let msg = LSPS1Message::Request(request_id.clone(), request).into();
 					peer_state_lock.insert_request(request_id, user_channel_id);
 
 					(Ok(()), Some(msg))
@@ -536,17 +693,20 @@ where
 					if let Some(inbound_channel) =
 						peer_state_lock.inbound_channels_by_id.get_mut(&user_channel_id)
 					{
+						// The `order_id` is provided by the LSP, so we make sure the request fits
+						// into a message before updating the channel request's state.
+						let request_id = crate::utils::generate_request_id(&self.entropy_source);
+						let request = LSPS1Request::GetOrder(GetOrderRequest { order_id });
+						let msg: LSPSMessage =
+							LSPS1Message::Request(request_id.clone(), request).into();
+						msg.to_payload()?;
+
 						if let Err(e) = inbound_channel.pay_for_channel(user_channel_id) {
 							peer_state_lock.remove_inbound_channel(user_channel_id);
 							return Err(APIError::APIMisuseError { err: e.err });
 						}
 
-						let request_id = crate::utils::generate_request_id(&self.entropy_source);
-						peer_state_lock.insert_request(request_id.clone(), user_channel_id);
-
-						let request =
-							LSPS1Request::GetOrder(GetOrderRequest { order_id: order_id.clone() });
-						let msg = LSPS1Message::Request(request_id, request).into();
+						peer_state_lock.insert_request(request_id, user_channel_id);
 						(Ok(()), Some(msg))
 					} else {
 						(
@@ -701,3 +861,74 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::tests::utils::{lsps1_options_supported as options_supported, TestEntropy};
+
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+	use lightning::ln::functional_test_utils::{
+		create_chanmon_cfgs, create_network, create_node_cfgs, create_node_chanmgrs,
+	};
+
+	#[test]
+	fn lists_and_cancels_channel_requests() {
+		let chanmon_cfgs = create_chanmon_cfgs(1);
+		let node_cfgs = create_node_cfgs(1, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(1, &node_cfgs, &[None]);
+		let nodes = create_network(1, &node_cfgs, &node_chanmgrs);
+
+		let pending_messages = Arc::new(MessageQueue::new());
+		let pending_events = Arc::new(EventQueue::new());
+		let client_handler = LSPS1ClientHandler::new(
+			Arc::new(TestEntropy {}),
+			Arc::clone(&pending_messages),
+			Arc::clone(&pending_events),
+			nodes[0].node,
+			None::<Arc<dyn Filter + Send + Sync>>,
+			LSPS1ClientConfig { max_channel_fees_msat: None },
+		);
+
+		let secp_ctx = Secp256k1::new();
+		let node_id = |byte| {
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap())
+		};
+		let (node_a, node_b, node_c) = (node_id(1), node_id(2), node_id(3));
+
+		let request_id_a = client_handler.send_get_info_request(node_a, 1);
+		let request_id_b = client_handler.send_get_info_request(node_b, 2);
+		pending_messages.get_and_clear_pending_msgs();
+
+		assert_eq!(client_handler.list_channel_requests().len(), 2);
+		let channel_requests = client_handler.list_channel_requests_with_counterparty(&node_b);
+		assert_eq!(
+			channel_requests,
+			vec![ChannelRequestDetails {
+				counterparty_node_id: node_b,
+				user_channel_id: 2,
+				state: ChannelRequestState::InfoRequested,
+				pending_request_ids: vec![request_id_b],
+				age_secs: channel_requests[0].age_secs,
+			}]
+		);
+		#[cfg(feature = "std")]
+		assert!(channel_requests[0].age_secs.is_some());
+		assert!(client_handler.list_channel_requests_with_counterparty(&node_c).is_empty());
+
+		assert!(client_handler.cancel_channel_request(&node_c, 1).is_err());
+		assert!(client_handler.cancel_channel_request(&node_a, 2).is_err());
+
+		// Late responses to cancelled requests are ignored.
+		client_handler.cancel_channel_request(&node_a, 1).unwrap();
+		assert!(client_handler.cancel_channel_request(&node_a, 1).is_err());
+		assert!(client_handler.list_channel_requests_with_counterparty(&node_a).is_empty());
+		assert_eq!(client_handler.list_channel_requests().len(), 1);
+
+		let response = LSPS1Response::GetInfo(GetInfoResponse { options: options_supported() });
+		let msg = LSPS1Message::Response(request_id_a, response);
+		assert!(client_handler.handle_message(msg, &node_a).is_err());
+		assert!(pending_events.get_and_clear_pending_events().is_empty());
+	}
+}
@@ -105,7 +105,30 @@ pub enum LSPS1ServiceEvent {
 		/// The order id of order with pending payment.
 		order_id: OrderId,
 	},
+	/// An order expired without having been paid for in time.
+	///
+	/// The order will be reported as failed to the client. If the client already made a payment,
+	/// you should fail it back in case of a held Lightning payment, or call
+	/// [`LSPS1ServiceHandler::refund_order`] for a confirmed on-chain payment. Payments arriving
+	/// after expiry will be refused by [`LSPS1ServiceHandler::order_paid`] and need to be refunded
+	/// likewise.
+	///
+	/// [`LSPS1ServiceHandler::refund_order`]: crate::lsps1::service::LSPS1ServiceHandler::refund_order
+	/// [`LSPS1ServiceHandler::order_paid`]: crate::lsps1::service::LSPS1ServiceHandler::order_paid
+	OrderExpired {
+		/// The node id of the client that placed the order.
+		counterparty_node_id: PublicKey,
+		/// The order id of the expired order.
+		order_id: OrderId,
+		/// The payment details of the order at the time it expired.
+		payment: PaymentInfo,
+	},
 	/// If error is encountered, refund the amount if paid by the client.
+	///
+	/// You may call [`LSPS1ServiceHandler::refund_order`] to refund the payment to the client's
+	/// refund address.
+	///
+	/// [`LSPS1ServiceHandler::refund_order`]: crate::lsps1::service::LSPS1ServiceHandler::refund_order
 	Refund {
 		/// An identifier.
 		request_id: RequestId,
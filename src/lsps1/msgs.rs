@@ -16,13 +16,13 @@ use serde::{Deserialize, Serialize};
 use chrono::Utc;
 
 use core::convert::TryFrom;
+use core::fmt;
 
 pub(crate) const LSPS1_GET_INFO_METHOD_NAME: &str = "lsps1.get_info";
 pub(crate) const LSPS1_CREATE_ORDER_METHOD_NAME: &str = "lsps1.create_order";
 pub(crate) const LSPS1_GET_ORDER_METHOD_NAME: &str = "lsps1.get_order";
 
 pub(crate) const LSPS1_CREATE_ORDER_REQUEST_INVALID_PARAMS_ERROR_CODE: i32 = -32602;
-pub(crate) const LSPS1_CREATE_ORDER_REQUEST_ORDER_MISMATCH_ERROR_CODE: i32 = 100;
 
 /// The identifier of an order.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
@@ -117,6 +117,24 @@ pub struct OrderParams {
 	pub announce_channel: bool,
 }
 
+/// A field of an [`OrderParams`] that doesn't match the [`OptionsSupported`] by the LSP.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OrderParamsViolation {
+	/// The name of the violating property of [`OrderParams`], or `channel_balance_sat` if the
+	/// channel size, i.e., the sum of `client_balance_sat` and `lsp_balance_sat`, violates
+	/// [`OptionsSupported::min_channel_balance_sat`] or
+	/// [`OptionsSupported::max_channel_balance_sat`].
+	pub property: String,
+	/// A human-readable description of what needs to change.
+	pub message: String,
+}
+
+impl fmt::Display for OrderParamsViolation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.property, self.message)
+	}
+}
+
 /// A response to a [`CreateOrderRequest`].
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct CreateOrderResponse {
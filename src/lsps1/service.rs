@@ -13,36 +13,103 @@ use super::event::LSPS1ServiceEvent;
 use super::msgs::{
 	ChannelInfo, CreateOrderRequest, CreateOrderResponse, GetInfoResponse, GetOrderRequest,
 	LSPS1Message, LSPS1Request, LSPS1Response, OptionsSupported, OrderId, OrderParams, OrderState,
-	PaymentInfo, LSPS1_CREATE_ORDER_REQUEST_ORDER_MISMATCH_ERROR_CODE,
+	PaymentInfo, PaymentState, LSPS1_CREATE_ORDER_REQUEST_INVALID_PARAMS_ERROR_CODE,
+};
+use super::utils::{
+	refundable_amount_sat, validate_options_supported, validate_order, validate_payment_info,
 };
-use super::utils::is_valid;
 use crate::message_queue::MessageQueue;
 
 use crate::events::{Event, EventQueue};
-use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
-use crate::prelude::{HashMap, String, ToString};
+use crate::lsps0::ser::{
+	LSPSMessage, ProtocolMessageHandler, RequestId, ResponseError,
+	JSONRPC_INTERNAL_ERROR_ERROR_CODE, JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE,
+};
+use crate::prelude::{HashMap, String, ToString, Vec};
 use crate::sync::{Arc, Mutex, RwLock};
 use crate::utils;
 
 use lightning::chain::Filter;
-use lightning::ln::channelmanager::AChannelManager;
+use lightning::ln::channelmanager::{AChannelManager, ChannelDetails};
 use lightning::ln::msgs::{ErrorAction, LightningError};
 use lightning::sign::EntropySource;
+use lightning::util::config::UserConfig;
 use lightning::util::errors::APIError;
 use lightning::util::logger::Level;
 
+use bitcoin::address::{Address, NetworkUnchecked};
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::{FeeRate, Txid};
 
-use chrono::Utc;
+use chrono::{TimeZone, Utc};
 use core::ops::Deref;
 
+/// The average time it takes to mine a block, used to convert block-based durations into
+/// datetimes.
+const AVERAGE_BLOCK_INTERVAL_SECS: i64 = 600;
+
+/// The time we keep failed orders around after they expired, allowing clients to learn about
+/// their final state and LSPs to refund them, before we prune them.
+const FAILED_ORDER_RETENTION_SECS: i64 = 60 * 60 * 24 * 7;
+
 /// Server-side configuration options for LSPS1 channel requests.
 #[derive(Clone, Debug)]
 pub struct LSPS1ServiceConfig {
 	/// A token to be send with each channel request.
 	pub token: Option<String>,
-	/// The options supported by the LSP.
+	/// The options initially supported by the LSP.
+	///
+	/// Needs to be set when the service is enabled. The options can later be updated via
+	/// [`LSPS1ServiceHandler::update_options_supported`], which won't be reflected here.
 	pub options_supported: Option<OptionsSupported>,
+	/// If set, the service handler will open the ordered channel by itself once the order was
+	/// marked as paid via [`LSPS1ServiceHandler::order_paid`].
+	///
+	/// The given [`UserConfig`] is used as the override config for the channel, with
+	/// `announced_channel` being set according to the order. The funding of the channel is
+	/// tracked and the order's [`ChannelInfo`] will be filled in automatically.
+	pub channel_open_config: Option<UserConfig>,
+}
+
+/// Determines who pays the on-chain fee of a refund transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefundFeePolicy {
+	/// The fee is deducted from the refunded amount, i.e., it is paid by the client.
+	DeductFromRefund {
+		/// The fee rate the refund transaction should be paying.
+		fee_rate: FeeRate,
+	},
+	/// The fee is paid by the LSP on top of the refunded amount.
+	PaidByLsp {
+		/// The fee rate the refund transaction should be paying.
+		fee_rate: FeeRate,
+	},
+}
+
+/// A request to refund the payment of an order on-chain, as handed to a [`RefundWallet`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefundRequest {
+	/// The node id of the client that placed the order.
+	pub counterparty_node_id: PublicKey,
+	/// The id of the refunded order.
+	pub order_id: OrderId,
+	/// The client's refund address, as given via [`OrderParams::refund_onchain_address`].
+	///
+	/// Note that it is the wallet's responsibility to check the address is valid for the
+	/// network it operates on.
+	pub address: Address<NetworkUnchecked>,
+	/// The amount to refund in satoshis.
+	pub amount_sat: u64,
+	/// Determines who pays the on-chain fee of the refund transaction.
+	pub fee_policy: RefundFeePolicy,
+}
+
+/// A wallet able to pay out refunds for LSPS1 orders.
+pub trait RefundWallet {
+	/// Builds, signs, and broadcasts a transaction paying out the given [`RefundRequest`].
+	///
+	/// Returns the [`Txid`] of the broadcasted transaction.
+	fn send_refund(&self, request: RefundRequest) -> Result<Txid, ()>;
 }
 
 struct ChannelStateError(String);
@@ -57,18 +124,116 @@ impl From<ChannelStateError> for LightningError {
 enum OutboundRequestState {
 	OrderCreated { order_id: OrderId },
 	WaitingPayment { order_id: OrderId },
+	ChannelOpening { order_id: OrderId, user_channel_id: u128 },
 	Ready,
+	Expired { order_id: OrderId },
+	Refunding { order_id: OrderId, expired: bool },
+	Refunded { order_id: OrderId, txid: Txid },
 }
 
 impl OutboundRequestState {
 	fn awaiting_payment(&self) -> Result<Self, ChannelStateError> {
 		match self {
-			OutboundRequestState::OrderCreated { order_id } => {
+			OutboundRequestState::OrderCreated { order_id }
+			| OutboundRequestState::WaitingPayment { order_id } => {
 				Ok(OutboundRequestState::WaitingPayment { order_id: order_id.clone() })
 			},
 			state => Err(ChannelStateError(format!("TODO. JIT Channel was in state: {:?}", state))),
 		}
 	}
+
+	fn expired(&self) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::OrderCreated { order_id }
+			| OutboundRequestState::WaitingPayment { order_id } => {
+				Ok(OutboundRequestState::Expired { order_id: order_id.clone() })
+			},
+			state => {
+				Err(ChannelStateError(format!("Tried to expire an order in state: {:?}", state)))
+			},
+		}
+	}
+
+	fn refunding(&self) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::OrderCreated { order_id }
+			| OutboundRequestState::WaitingPayment { order_id } => {
+				Ok(OutboundRequestState::Refunding { order_id: order_id.clone(), expired: false })
+			},
+			OutboundRequestState::Expired { order_id } => {
+				Ok(OutboundRequestState::Refunding { order_id: order_id.clone(), expired: true })
+			},
+			state => {
+				Err(ChannelStateError(format!("Tried to refund an order in state: {:?}", state)))
+			},
+		}
+	}
+
+	fn refund_failed(&self) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::Refunding { order_id, expired: false } => {
+				Ok(OutboundRequestState::WaitingPayment { order_id: order_id.clone() })
+			},
+			OutboundRequestState::Refunding { order_id, expired: true } => {
+				Ok(OutboundRequestState::Expired { order_id: order_id.clone() })
+			},
+			state => {
+				Err(ChannelStateError(format!("Refund failed for an order in state: {:?}", state)))
+			},
+		}
+	}
+
+	fn refunded(&self, txid: Txid) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::Refunding { order_id, .. } => {
+				Ok(OutboundRequestState::Refunded { order_id: order_id.clone(), txid })
+			},
+			state => Err(ChannelStateError(format!(
+				"Refund completed for an order in state: {:?}",
+				state
+			))),
+		}
+	}
+
+	fn channel_opening(&self, user_channel_id: u128) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::OrderCreated { order_id }
+			| OutboundRequestState::WaitingPayment { order_id } => {
+				Ok(OutboundRequestState::ChannelOpening {
+					order_id: order_id.clone(),
+					user_channel_id,
+				})
+			},
+			state => Err(ChannelStateError(format!(
+				"Tried to open a channel for an order in state: {:?}",
+				state
+			))),
+		}
+	}
+
+	fn channel_open_failed(&self, user_channel_id: u128) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::ChannelOpening { order_id, user_channel_id: id }
+				if *id == user_channel_id =>
+			{
+				Ok(OutboundRequestState::WaitingPayment { order_id: order_id.clone() })
+			},
+			state => Err(ChannelStateError(format!(
+				"Failed to open a channel for an order in state: {:?}",
+				state
+			))),
+		}
+	}
+
+	fn channel_funded(&self) -> Result<Self, ChannelStateError> {
+		match self {
+			OutboundRequestState::ChannelOpening { .. } => Ok(OutboundRequestState::Ready),
+			state => Err(ChannelStateError(format!(
+				"Channel funding confirmed for an order in state: {:?}",
+				state
+			))),
+		}
+	}
 }
 
 struct OutboundLSPS1Config {
@@ -81,6 +246,7 @@ struct OutboundLSPS1Config {
 struct OutboundCRChannel {
 	state: OutboundRequestState,
 	config: OutboundLSPS1Config,
+	channel: Option<ChannelInfo>,
 }
 
 impl OutboundCRChannel {
@@ -91,6 +257,7 @@ impl OutboundCRChannel {
 		Self {
 			state: OutboundRequestState::OrderCreated { order_id },
 			config: OutboundLSPS1Config { order, created_at, expires_at, payment },
+			channel: None,
 		}
 	}
 	fn awaiting_payment(&mut self) -> Result<(), LightningError> {
@@ -98,10 +265,113 @@ impl OutboundCRChannel {
 		Ok(())
 	}
 
+	fn channel_opening(&mut self, user_channel_id: u128) -> Result<(), LightningError> {
+		self.state = self.state.channel_opening(user_channel_id)?;
+		Ok(())
+	}
+
+	fn channel_open_failed(&mut self, user_channel_id: u128) -> Result<(), LightningError> {
+		self.state = self.state.channel_open_failed(user_channel_id)?;
+		Ok(())
+	}
+
+	fn channel_funded(&mut self, channel: ChannelInfo) -> Result<(), LightningError> {
+		self.state = self.state.channel_funded()?;
+		self.channel = Some(channel);
+		Ok(())
+	}
+
+	fn refunding(&mut self) -> Result<(), LightningError> {
+		self.state = self.state.refunding()?;
+		Ok(())
+	}
+
+	fn refund_failed(&mut self) -> Result<(), LightningError> {
+		self.state = self.state.refund_failed()?;
+		Ok(())
+	}
+
+	fn refunded(&mut self, txid: Txid) -> Result<(), LightningError> {
+		self.state = self.state.refunded(txid)?;
+		self.config.payment.state = PaymentState::Refunded;
+		Ok(())
+	}
+
+	/// Expires the order if it's past its expiry time without having been paid for, returning
+	/// whether it did so.
+	fn expire_if_needed(&mut self, now: chrono::DateTime<Utc>) -> bool {
+		if now <= self.config.expires_at || self.config.payment.state == PaymentState::Paid {
+			return false;
+		}
+
+		match self.state.expired() {
+			Ok(state) => {
+				self.state = state;
+				true
+			},
+			Err(_) => false,
+		}
+	}
+
+	fn is_expired(&self) -> bool {
+		matches!(self.state, OutboundRequestState::Expired { .. })
+	}
+
+	/// Returns whether the order failed and can be pruned at the given time.
+	fn is_prunable(&self, now: chrono::DateTime<Utc>) -> bool {
+		let failed = match self.state {
+			OutboundRequestState::Expired { .. } | OutboundRequestState::Refunded { .. } => true,
+			_ => false,
+		};
+		failed
+			&& refundable_amount_sat(&self.config.payment).is_none()
+			&& now > self.config.expires_at + chrono::Duration::seconds(FAILED_ORDER_RETENTION_SECS)
+	}
+
+	/// Returns whether the channel for this order is opened and tracked by the service handler.
+	fn is_channel_managed(&self) -> bool {
+		match self.state {
+			OutboundRequestState::ChannelOpening { .. } | OutboundRequestState::Ready => true,
+			_ => false,
+		}
+	}
+
+	/// Returns whether the order has been settled, i.e., its state won't change anymore without
+	/// the service handler knowing about it.
+	fn is_settled(&self) -> bool {
+		self.is_channel_managed()
+			|| matches!(
+				self.state,
+				OutboundRequestState::Expired { .. }
+					| OutboundRequestState::Refunding { .. }
+					| OutboundRequestState::Refunded { .. }
+			)
+	}
+
+	fn order_response(&self, order_id: OrderId) -> CreateOrderResponse {
+		let order_state = match self.state {
+			OutboundRequestState::Ready => OrderState::Completed,
+			OutboundRequestState::Expired { .. }
+			| OutboundRequestState::Refunding { .. }
+			| OutboundRequestState::Refunded { .. } => OrderState::Failed,
+			_ => OrderState::Created,
+		};
+
+		CreateOrderResponse {
+			order_id,
+			order: self.config.order.clone(),
+			order_state,
+			created_at: self.config.created_at,
+			expires_at: self.config.expires_at,
+			payment: self.config.payment.clone(),
+			channel: self.channel.clone(),
+		}
+	}
+
 	fn check_order_validity(&self, options_supported: &OptionsSupported) -> bool {
 		let order = &self.config.order;
 
-		is_valid(order, options_supported)
+		validate_order(order, options_supported).is_ok()
 	}
 }
 
@@ -139,7 +409,9 @@ where
 	pending_messages: Arc<MessageQueue>,
 	pending_events: Arc<EventQueue>,
 	per_peer_state: RwLock<HashMap<PublicKey, Mutex<PeerState>>>,
-	config: LSPS1ServiceConfig,
+	options_supported: RwLock<OptionsSupported>,
+	latest_block_time: RwLock<Option<chrono::DateTime<Utc>>>,
+	channel_open_config: Option<UserConfig>,
 }
 
 impl<ES: Deref, CM: Deref + Clone, C: Deref> LSPS1ServiceHandler<ES, CM, C>
@@ -150,36 +422,76 @@ where
 	ES::Target: EntropySource,
 {
 	/// Constructs a `LSPS1ServiceHandler`.
+	///
+	/// Returns an error if the given configuration is invalid.
 	pub(crate) fn new(
 		entropy_source: ES, pending_messages: Arc<MessageQueue>, pending_events: Arc<EventQueue>,
 		channel_manager: CM, chain_source: Option<C>, config: LSPS1ServiceConfig,
-	) -> Self {
-		Self {
+	) -> Result<Self, String> {
+		let options_supported =
+			config.options_supported.ok_or("options_supported must be set".to_string())?;
+		validate_options_supported(&options_supported)?;
+		Ok(Self {
 			entropy_source,
 			channel_manager,
 			chain_source,
 			pending_messages,
 			pending_events,
 			per_peer_state: RwLock::new(HashMap::new()),
-			config,
+			options_supported: RwLock::new(options_supported),
+			latest_block_time: RwLock::new(None),
+			channel_open_config: config.channel_open_config,
+		})
+	}
+
+	/// Returns the current time, falling back to the time of the latest block we've seen in
+	/// no-std builds.
+	fn current_time(&self) -> Option<chrono::DateTime<Utc>> {
+		match utils::seconds_since_epoch() {
+			Some(seconds_since_epoch) => Utc.timestamp_opt(seconds_since_epoch as i64, 0).single(),
+			None => *self.latest_block_time.read().unwrap(),
+		}
+	}
+
+	/// Expires the given order if needed, notifying the user if it did so.
+	fn expire_order_if_needed(
+		&self, counterparty_node_id: &PublicKey, order_id: &OrderId,
+		outbound_channel: &mut OutboundCRChannel, now: Option<chrono::DateTime<Utc>>,
+	) {
+		let now = match now {
+			Some(now) => now,
+			None => return,
+		};
+
+		if outbound_channel.expire_if_needed(now) {
+			self.pending_events.enqueue(Event::LSPS1Service(LSPS1ServiceEvent::OrderExpired {
+				counterparty_node_id: *counterparty_node_id,
+				order_id: order_id.clone(),
+				payment: outbound_channel.config.payment.clone(),
+			}));
 		}
 	}
 
+	/// Updates the options supported by the LSP.
+	///
+	/// Subsequent `get_info` and `create_order` requests will be answered based on the given
+	/// options. The options are validated the same way as [`LSPS1ServiceConfig::options_supported`]
+	/// is when the service is created, and an error is returned if they are inconsistent.
+	pub fn update_options_supported(
+		&self, options_supported: OptionsSupported,
+	) -> Result<(), APIError> {
+		validate_options_supported(&options_supported)
+			.map_err(|err| APIError::APIMisuseError { err })?;
+
+		*self.options_supported.write().unwrap() = options_supported;
+		Ok(())
+	}
+
 	fn handle_get_info_request(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
 	) -> Result<(), LightningError> {
-		let response = LSPS1Response::GetInfo(GetInfoResponse {
-			options: self
-				.config
-				.options_supported
-				.clone()
-				.ok_or(LightningError {
-					err: format!("Configuration for LSP server not set."),
-					action: ErrorAction::IgnoreAndLog(Level::Info),
-				})
-				.unwrap(),
-		});
-
+		let options = self.options_supported.read().unwrap().clone();
+		let response = LSPS1Response::GetInfo(GetInfoResponse { options });
 		let msg = LSPS1Message::Response(request_id, response).into();
 		self.pending_messages.enqueue(counterparty_node_id, msg);
 		Ok(())
@@ -188,22 +500,25 @@ where
 	fn handle_create_order_request(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey, params: CreateOrderRequest,
 	) -> Result<(), LightningError> {
-		if !is_valid(&params.order, &self.config.options_supported.as_ref().unwrap()) {
+		let options_supported = self.options_supported.read().unwrap().clone();
+
+		if let Err(violations) = validate_order(&params.order, &options_supported) {
+			// As per spec, `data` describes a single violating property. We report the first one
+			// there and additionally include all of them so clients can fix their order at once.
+			let data = serde_json::json!({
+				"property": violations[0].property,
+				"message": violations[0].message,
+				"violations": violations,
+			});
 			let response = LSPS1Response::CreateOrderError(ResponseError {
-				code: LSPS1_CREATE_ORDER_REQUEST_ORDER_MISMATCH_ERROR_CODE,
-				message: format!("Order does not match options supported by LSP server"),
-				data: Some(format!(
-					"Supported options are {:?}",
-					&self.config.options_supported.as_ref().unwrap()
-				)),
+				code: LSPS1_CREATE_ORDER_REQUEST_INVALID_PARAMS_ERROR_CODE,
+				message: format!("Invalid params"),
+				data: Some(data),
 			});
 			let msg = LSPS1Message::Response(request_id, response).into();
 			self.pending_messages.enqueue(counterparty_node_id, msg);
 			return Err(LightningError {
-				err: format!(
-					"Client order does not match any supported options: {:?}",
-					params.order
-				),
+				err: format!("Client order does not match supported options: {:?}", violations),
 				action: ErrorAction::IgnoreAndLog(Level::Info),
 			});
 		}
@@ -236,19 +551,26 @@ where
 	///
 	/// Should be called in response to receiving a [`LSPS1ServiceEvent::RequestForPaymentDetails`] event.
 	///
+	/// Returns an error if the [`PaymentInfo::order_total_sat`] falls short of the
+	/// [`OptionsSupported::min_onchain_payment_size_sat`], as the client is always offered to pay
+	/// the order on-chain.
+	///
 	/// [`LSPS1ServiceEvent::RequestForPaymentDetails`]: crate::lsps1::event::LSPS1ServiceEvent::RequestForPaymentDetails
 	pub fn send_payment_details(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey, payment: PaymentInfo,
 		created_at: chrono::DateTime<Utc>, expires_at: chrono::DateTime<Utc>,
 	) -> Result<(), APIError> {
-		let (result, response) = {
+		validate_payment_info(&payment, &self.options_supported.read().unwrap())
+			.map_err(|err| APIError::APIMisuseError { err })?;
+
+		let (result, msg) = {
 			let outer_state_lock = self.per_peer_state.read().unwrap();
 
 			match outer_state_lock.get(counterparty_node_id) {
 				Some(inner_state_lock) => {
 					let mut peer_state_lock = inner_state_lock.lock().unwrap();
 
-					match peer_state_lock.pending_requests.remove(&request_id) {
+					match peer_state_lock.pending_requests.get(&request_id) {
 						Some(LSPS1Request::CreateOrder(params)) => {
 							let order_id = self.generate_order_id();
 							let channel = OutboundCRChannel::new(
@@ -259,19 +581,28 @@ where
 								payment.clone(),
 							);
 
-							peer_state_lock.insert_outbound_channel(order_id.clone(), channel);
-
 							let response = LSPS1Response::CreateOrder(CreateOrderResponse {
-								order: params.order,
-								order_id,
+								order: params.order.clone(),
+								order_id: order_id.clone(),
 								order_state: OrderState::Created,
 								created_at,
 								expires_at,
 								payment,
 								channel: None,
 							});
+							let msg: LSPSMessage =
+								LSPS1Message::Response(request_id.clone(), response).into();
 
-							(Ok(()), Some(response))
+							// We only create the order once we know the response fits into a
+							// message, so that the request may still be answered otherwise.
+							match msg.to_payload() {
+								Ok(_) => {
+									peer_state_lock.pending_requests.remove(&request_id);
+									peer_state_lock.insert_outbound_channel(order_id, channel);
+									(Ok(()), Some(msg))
+								},
+								Err(e) => (Err(e.into()), None),
+							}
 						},
 
 						_ => (
@@ -297,8 +628,7 @@ where
 			}
 		};
 
-		if let Some(response) = response {
-			let msg = LSPS1Message::Response(request_id, response).into();
+		if let Some(msg) = msg {
 			self.pending_messages.enqueue(counterparty_node_id, msg);
 		}
 
@@ -324,8 +654,42 @@ where
 						action: ErrorAction::IgnoreAndLog(Level::Info),
 					})?;
 
+				self.expire_order_if_needed(
+					counterparty_node_id,
+					&params.order_id,
+					outbound_channel,
+					self.current_time(),
+				);
+
+				if outbound_channel.is_settled() {
+					// We're opening the channel ourselves or the order failed, so we can respond
+					// right away.
+					let response =
+						LSPS1Response::GetOrder(outbound_channel.order_response(params.order_id));
+					let msg = LSPS1Message::Response(request_id.clone(), response).into();
+					if let Err(e) = self.pending_messages.try_enqueue(counterparty_node_id, msg) {
+						// The channel details added since the order was created may have pushed
+						// the response over the limit.
+						let response = LSPS1Response::GetOrderError(ResponseError {
+							code: JSONRPC_INTERNAL_ERROR_ERROR_CODE,
+							message: JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE.to_string(),
+							data: None,
+						});
+						let msg = LSPS1Message::Response(request_id, response).into();
+						self.pending_messages.enqueue(counterparty_node_id, msg);
+						return Err(LightningError {
+							err: format!(
+								"Failed to respond to get_order request from node {:?}: {}",
+								counterparty_node_id, e
+							),
+							action: ErrorAction::IgnoreAndLog(Level::Error),
+						});
+					}
+					return Ok(());
+				}
+
 				if let Err(e) = outbound_channel.awaiting_payment() {
-					peer_state_lock.outbound_channels_by_order_id.remove(&params.order_id);
+					// We keep the order around so that it can still be refunded.
 					self.pending_events.enqueue(Event::LSPS1Service(LSPS1ServiceEvent::Refund {
 						request_id,
 						counterparty_node_id: *counterparty_node_id,
@@ -364,6 +728,9 @@ where
 	///
 	/// Should be called in response to receiving a [`LSPS1ServiceEvent::CheckPaymentConfirmation`] event.
 	///
+	/// Returns an error if the response, e.g., due to the given `channel`, doesn't fit into an
+	/// LSPS message.
+	///
 	/// [`LSPS1ServiceEvent::CheckPaymentConfirmation`]: crate::lsps1::event::LSPS1ServiceEvent::CheckPaymentConfirmation
 	pub fn update_order_status(
 		&self, request_id: RequestId, counterparty_node_id: PublicKey, order_id: OrderId,
@@ -379,8 +746,34 @@ where
 					if let Some(outbound_channel) =
 						peer_state_lock.outbound_channels_by_order_id.get_mut(&order_id)
 					{
+						self.expire_order_if_needed(
+							&counterparty_node_id,
+							&order_id,
+							outbound_channel,
+							self.current_time(),
+						);
+
+						// Expired orders have failed, no matter what the LSP thinks.
+						let order_state = if outbound_channel.is_expired() {
+							OrderState::Failed
+						} else {
+							order_state
+						};
+
 						let config = &outbound_channel.config;
 
+						if order_state == OrderState::Failed
+							&& refundable_amount_sat(&config.payment).is_some()
+						{
+							self.pending_events.enqueue(Event::LSPS1Service(
+								LSPS1ServiceEvent::Refund {
+									request_id: request_id.clone(),
+									counterparty_node_id,
+									order_id: order_id.clone(),
+								},
+							));
+						}
+
 						let response = LSPS1Response::GetOrder(CreateOrderResponse {
 							order_id,
 							order: config.order.clone(),
@@ -388,7 +781,7 @@ where
 							created_at: config.created_at,
 							expires_at: config.expires_at,
 							payment: config.payment.clone(),
-							channel,
+							channel: channel.or_else(|| outbound_channel.channel.clone()),
 						});
 						(Ok(()), Some(response))
 					} else {
@@ -414,12 +807,393 @@ where
 
 		if let Some(response) = response {
 			let msg = LSPS1Message::Response(request_id, response).into();
-			self.pending_messages.enqueue(&counterparty_node_id, msg);
+			self.pending_messages.try_enqueue(&counterparty_node_id, msg)?;
 		}
 
 		result
 	}
 
+	/// Used by LSP to signal that the payment for an order has been received.
+	///
+	/// If [`LSPS1ServiceConfig::channel_open_config`] is set, this will open the ordered channel
+	/// via [`ChannelManager::create_channel`], pushing the `client_balance_sat` to the client. The
+	/// funding of the channel will then be tracked and the client's `get_order` requests will be
+	/// answered automatically, i.e., no further [`LSPS1ServiceEvent::CheckPaymentConfirmation`]
+	/// events will be emitted for this order.
+	///
+	/// Returns an error without marking the order as paid if it already expired, in which case
+	/// the payment should be failed back. Likewise, orders that are being or were refunded, and
+	/// orders that were already paid, are left untouched. Only if opening the channel of a paid
+	/// order failed, the call may be retried.
+	///
+	/// [`ChannelManager::create_channel`]: lightning::ln::channelmanager::ChannelManager::create_channel
+	/// [`LSPS1ServiceEvent::CheckPaymentConfirmation`]: crate::lsps1::event::LSPS1ServiceEvent::CheckPaymentConfirmation
+	pub fn order_paid(
+		&self, counterparty_node_id: &PublicKey, order_id: OrderId,
+	) -> Result<(), APIError> {
+		let (channel_value_satoshis, push_msat, override_config, user_channel_id) = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock =
+				outer_state_lock.get(counterparty_node_id).ok_or(APIError::APIMisuseError {
+					err: format!("No existing state with counterparty {}", counterparty_node_id),
+				})?;
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+
+			let outbound_channel = peer_state_lock
+				.outbound_channels_by_order_id
+				.get_mut(&order_id)
+				.ok_or(APIError::APIMisuseError {
+					err: format!("Channel with order_id {} not found", order_id.0),
+				})?;
+
+			match outbound_channel.state {
+				OutboundRequestState::Refunding { .. } | OutboundRequestState::Refunded { .. } => {
+					return Err(APIError::APIMisuseError {
+						err: format!(
+							"Order with order_id {} is being or was already refunded",
+							order_id.0
+						),
+					});
+				},
+				OutboundRequestState::ChannelOpening { .. } | OutboundRequestState::Ready => {
+					return Err(APIError::APIMisuseError {
+						err: format!(
+							"Order with order_id {} was already paid and its channel opened",
+							order_id.0
+						),
+					});
+				},
+				_ => {},
+			}
+
+			// Without a channel to open, there is nothing left to retry for an order that was
+			// already paid.
+			if self.channel_open_config.is_none()
+				&& outbound_channel.config.payment.state == PaymentState::Paid
+			{
+				return Err(APIError::APIMisuseError {
+					err: format!("Order with order_id {} was already paid", order_id.0),
+				});
+			}
+
+			self.expire_order_if_needed(
+				counterparty_node_id,
+				&order_id,
+				outbound_channel,
+				self.current_time(),
+			);
+
+			if outbound_channel.is_expired() {
+				return Err(APIError::APIMisuseError {
+					err: format!(
+						"Order with order_id {} already expired, the payment needs to be failed back",
+						order_id.0
+					),
+				});
+			}
+
+			outbound_channel.config.payment.state = PaymentState::Paid;
+
+			let channel_open_config = match self.channel_open_config {
+				Some(channel_open_config) => channel_open_config,
+				None => return Ok(()),
+			};
+
+			let order = &outbound_channel.config.order;
+			let channel_value_satoshis = order
+				.lsp_balance_sat
+				.checked_add(order.client_balance_sat)
+				.ok_or(APIError::APIMisuseError {
+					err: format!("Channel value overflowed for order_id {}", order_id.0),
+				})?;
+			let push_msat =
+				order.client_balance_sat.checked_mul(1000).ok_or(APIError::APIMisuseError {
+					err: format!("Push amount overflowed for order_id {}", order_id.0),
+				})?;
+
+			let mut override_config = channel_open_config;
+			override_config.channel_handshake_config.announced_channel = order.announce_channel;
+
+			// We transition the state before opening the channel, so that concurrent or repeated
+			// calls can't open more than one channel for the order.
+			let user_channel_id = self.generate_user_channel_id();
+			outbound_channel
+				.channel_opening(user_channel_id)
+				.map_err(|e| APIError::APIMisuseError { err: e.err })?;
+
+			(channel_value_satoshis, push_msat, override_config, user_channel_id)
+		};
+
+		let res = self.channel_manager.get_cm().create_channel(
+			*counterparty_node_id,
+			channel_value_satoshis,
+			push_msat,
+			user_channel_id,
+			None,
+			Some(override_config),
+		);
+
+		if let Err(e) = res {
+			// Allow the LSP to retry opening the channel.
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			if let Some(inner_state_lock) = outer_state_lock.get(counterparty_node_id) {
+				let mut peer_state_lock = inner_state_lock.lock().unwrap();
+				if let Some(outbound_channel) =
+					peer_state_lock.outbound_channels_by_order_id.get_mut(&order_id)
+				{
+					if let Err(e) = outbound_channel.channel_open_failed(user_channel_id) {
+						debug_assert!(false, "Failed to roll back the order's state: {}", e.err);
+					}
+				}
+			}
+			return Err(e);
+		}
+
+		Ok(())
+	}
+
+	/// Used by LSP to refund the payment of an order to the client's refund address.
+	///
+	/// The refundable amount is determined from the order's [`PaymentInfo`]: a confirmed on-chain
+	/// payment is refunded in full, while a claimed Lightning payment is refunded with the order
+	/// total. A [`RefundRequest`] is then handed to the given `wallet`, and once it returns, the
+	/// payment is moved to [`PaymentState::Refunded`] and the order is considered failed.
+	///
+	/// No locks are held while the `wallet` pays out the refund. In the meantime, the order is
+	/// reported as failed and further refunds of it are refused. If the `wallet` fails, the refund
+	/// may be retried.
+	///
+	/// Note that orders for which a channel is already being opened can't be refunded. Held
+	/// Lightning payments should be failed back rather than refunded on-chain.
+	///
+	/// Should be called in response to receiving a [`LSPS1ServiceEvent::Refund`] event.
+	///
+	/// [`LSPS1ServiceEvent::Refund`]: crate::lsps1::event::LSPS1ServiceEvent::Refund
+	pub fn refund_order<W: Deref>(
+		&self, counterparty_node_id: &PublicKey, order_id: OrderId, fee_policy: RefundFeePolicy,
+		wallet: W,
+	) -> Result<Txid, APIError>
+	where
+		W::Target: RefundWallet,
+	{
+		let request = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock =
+				outer_state_lock.get(counterparty_node_id).ok_or(APIError::APIMisuseError {
+					err: format!("No existing state with counterparty {}", counterparty_node_id),
+				})?;
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+
+			let outbound_channel = peer_state_lock
+				.outbound_channels_by_order_id
+				.get_mut(&order_id)
+				.ok_or(APIError::APIMisuseError {
+					err: format!("Channel with order_id {} not found", order_id.0),
+				})?;
+
+			match outbound_channel.state {
+				OutboundRequestState::Refunding { .. } => {
+					return Err(APIError::APIMisuseError {
+						err: format!(
+							"Order with order_id {} is already being refunded",
+							order_id.0
+						),
+					});
+				},
+				OutboundRequestState::Refunded { txid, .. } => {
+					return Err(APIError::APIMisuseError {
+						err: format!(
+							"Order with order_id {} was already refunded in {}",
+							order_id.0, txid
+						),
+					});
+				},
+				_ => {},
+			}
+
+			if outbound_channel.is_channel_managed() {
+				return Err(APIError::APIMisuseError {
+					err: format!(
+						"Can't refund order_id {} as its channel is being opened",
+						order_id.0
+					),
+				});
+			}
+
+			let amount_sat = refundable_amount_sat(&outbound_channel.config.payment).ok_or(
+				APIError::APIMisuseError {
+					err: format!("No refundable payment for order_id {}", order_id.0),
+				},
+			)?;
+
+			let address = outbound_channel.config.order.refund_onchain_address.clone().ok_or(
+				APIError::APIMisuseError {
+					err: format!("No refund address given for order_id {}", order_id.0),
+				},
+			)?;
+
+			// Marking the order as being refunded ensures we never pay out the same refund twice,
+			// while not blocking the peer's state on the wallet.
+			outbound_channel.refunding().map_err(|e| APIError::APIMisuseError { err: e.err })?;
+
+			RefundRequest {
+				counterparty_node_id: *counterparty_node_id,
+				order_id: order_id.clone(),
+				address,
+				amount_sat,
+				fee_policy,
+			}
+		};
+
+		let res = wallet.send_refund(request);
+
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let inner_state_lock =
+			outer_state_lock.get(counterparty_node_id).ok_or(APIError::APIMisuseError {
+				err: format!("No existing state with counterparty {}", counterparty_node_id),
+			})?;
+		let mut peer_state_lock = inner_state_lock.lock().unwrap();
+
+		let outbound_channel = peer_state_lock
+			.outbound_channels_by_order_id
+			.get_mut(&order_id)
+			.ok_or(APIError::APIMisuseError {
+				err: format!("Channel with order_id {} not found", order_id.0),
+			})?;
+
+		match res {
+			Ok(txid) => {
+				outbound_channel
+					.refunded(txid)
+					.map_err(|e| APIError::APIMisuseError { err: e.err })?;
+				Ok(txid)
+			},
+			Err(()) => {
+				outbound_channel
+					.refund_failed()
+					.map_err(|e| APIError::APIMisuseError { err: e.err })?;
+				Err(APIError::APIMisuseError {
+					err: format!("Failed to send refund for order_id {}", order_id.0),
+				})
+			},
+		}
+	}
+
+	/// Checks whether the funding transactions of the channels we opened reached the number of
+	/// confirmations required by the respective orders, and if so fills in their [`ChannelInfo`].
+	///
+	/// Also expires unpaid orders and prunes failed ones once they're past their retention period.
+	pub(crate) fn best_block_updated(&self, header: &bitcoin::block::Header, _height: u32) {
+		let block_time = match Utc.timestamp_opt(header.time as i64, 0).single() {
+			Some(block_time) => block_time,
+			None => return,
+		};
+		*self.latest_block_time.write().unwrap() = Some(block_time);
+		let now = self.current_time();
+
+		// We query the `ChannelManager` for the channels of the orders we opened without holding
+		// any of our locks.
+		let opening_counterparties: Vec<PublicKey> = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			outer_state_lock
+				.iter()
+				.filter(|(_, inner_state_lock)| {
+					let peer_state_lock = inner_state_lock.lock().unwrap();
+					peer_state_lock.outbound_channels_by_order_id.values().any(|outbound_channel| {
+						matches!(
+							outbound_channel.state,
+							OutboundRequestState::ChannelOpening { .. }
+						)
+					})
+				})
+				.map(|(counterparty_node_id, _)| *counterparty_node_id)
+				.collect()
+		};
+		let channel_details_by_counterparty: HashMap<PublicKey, Vec<ChannelDetails>> =
+			opening_counterparties
+				.into_iter()
+				.map(|counterparty_node_id| {
+					let channel_details = self
+						.channel_manager
+						.get_cm()
+						.list_channels_with_counterparty(&counterparty_node_id);
+					(counterparty_node_id, channel_details)
+				})
+				.collect();
+
+		let mut outer_state_lock = self.per_peer_state.write().unwrap();
+		for (counterparty_node_id, inner_state_lock) in outer_state_lock.iter() {
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+
+			for (order_id, outbound_channel) in
+				peer_state_lock.outbound_channels_by_order_id.iter_mut()
+			{
+				self.expire_order_if_needed(counterparty_node_id, order_id, outbound_channel, now);
+			}
+
+			if let Some(now) = now {
+				peer_state_lock
+					.outbound_channels_by_order_id
+					.retain(|_, outbound_channel| !outbound_channel.is_prunable(now));
+			}
+
+			let channel_details = match channel_details_by_counterparty.get(counterparty_node_id) {
+				Some(channel_details) => channel_details,
+				None => continue,
+			};
+			for outbound_channel in peer_state_lock.outbound_channels_by_order_id.values_mut() {
+				let user_channel_id = match outbound_channel.state {
+					OutboundRequestState::ChannelOpening { user_channel_id, .. } => user_channel_id,
+					_ => continue,
+				};
+
+				let details = match channel_details
+					.iter()
+					.find(|details| details.user_channel_id == user_channel_id)
+				{
+					Some(details) => details,
+					None => continue,
+				};
+
+				let funding_outpoint = match details.funding_txo {
+					Some(funding_txo) => funding_txo.into_bitcoin_outpoint(),
+					None => continue,
+				};
+
+				let required_confirmations =
+					outbound_channel.config.order.required_channel_confirmations as u32;
+				if details.confirmations.unwrap_or(0) < required_confirmations {
+					continue;
+				}
+
+				let expiry_secs = outbound_channel.config.order.channel_expiry_blocks as i64
+					* AVERAGE_BLOCK_INTERVAL_SECS;
+				let channel = ChannelInfo {
+					funded_at: block_time,
+					funding_outpoint,
+					expires_at: block_time + chrono::Duration::seconds(expiry_secs),
+				};
+				// We only get here for orders in `ChannelOpening`, so this can't fail.
+				if let Err(e) = outbound_channel.channel_funded(channel) {
+					debug_assert!(false, "Failed to mark the order's channel as funded: {}", e.err);
+				}
+			}
+		}
+
+		outer_state_lock.retain(|_, inner_state_lock| {
+			let peer_state = inner_state_lock.lock().unwrap();
+			!peer_state.outbound_channels_by_order_id.is_empty()
+				|| !peer_state.pending_requests.is_empty()
+		});
+	}
+
+	fn generate_user_channel_id(&self) -> u128 {
+		let bytes = self.entropy_source.get_secure_random_bytes();
+		let mut id_bytes = [0u8; 16];
+		id_bytes.copy_from_slice(&bytes[0..16]);
+		u128::from_be_bytes(id_bytes)
+	}
+
 	fn generate_order_id(&self) -> OrderId {
 		let bytes = self.entropy_source.get_secure_random_bytes();
 		OrderId(utils::hex_str(&bytes[0..16]))
@@ -461,3 +1235,59 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::alloc::string::ToString;
+	use crate::tests::utils::lsps1_payment_info;
+
+	fn outbound_channel(
+		created_at: chrono::DateTime<Utc>, expires_at: chrono::DateTime<Utc>,
+	) -> OutboundCRChannel {
+		let order = OrderParams {
+			lsp_balance_sat: 5_000_000,
+			client_balance_sat: 2_000_000,
+			required_channel_confirmations: 0,
+			funding_confirms_within_blocks: 6,
+			channel_expiry_blocks: 144,
+			token: "".to_string(),
+			refund_onchain_address: None,
+			announce_channel: true,
+		};
+		let payment = lsps1_payment_info(PaymentState::ExpectPayment, None);
+		let order_id = OrderId("order".to_string());
+		OutboundCRChannel::new(order, created_at, expires_at, order_id, payment)
+	}
+
+	#[test]
+	fn expiry_and_pruning() {
+		let created_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+		let expires_at = created_at + chrono::Duration::hours(1);
+		let retention = chrono::Duration::seconds(FAILED_ORDER_RETENTION_SECS);
+
+		let mut channel = outbound_channel(created_at, expires_at);
+		assert!(!channel.expire_if_needed(expires_at));
+		assert!(!channel.is_prunable(expires_at + retention * 2));
+
+		let after_expiry = expires_at + chrono::Duration::seconds(1);
+		assert!(channel.expire_if_needed(after_expiry));
+		assert!(channel.is_expired());
+		assert!(channel.is_settled());
+		assert_eq!(
+			channel.order_response(OrderId("order".to_string())).order_state,
+			OrderState::Failed
+		);
+		// We don't expire twice.
+		assert!(!channel.expire_if_needed(after_expiry));
+
+		assert!(!channel.is_prunable(after_expiry));
+		assert!(channel.is_prunable(after_expiry + retention));
+
+		// Paid orders don't expire.
+		let mut channel = outbound_channel(created_at, expires_at);
+		channel.config.payment.state = PaymentState::Paid;
+		assert!(!channel.expire_if_needed(after_expiry));
+	}
+}
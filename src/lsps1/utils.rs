@@ -1,23 +1,271 @@
-use super::msgs::{OptionsSupported, OrderParams};
+use super::msgs::{OptionsSupported, OrderParams, OrderParamsViolation, PaymentInfo, PaymentState};
+
+use crate::prelude::{String, ToString, Vec};
 
 pub fn check_range(min: u64, max: u64, value: u64) -> bool {
 	(value >= min) && (value <= max)
 }
 
-pub fn is_valid(order: &OrderParams, options: &OptionsSupported) -> bool {
-	let bool = check_range(
-		options.min_initial_client_balance_sat,
-		options.max_initial_client_balance_sat,
-		order.client_balance_sat,
-	) && check_range(
-		options.min_initial_lsp_balance_sat,
-		options.max_initial_lsp_balance_sat,
-		order.lsp_balance_sat,
-	) && check_range(
-		1,
-		options.max_channel_expiry_blocks.into(),
-		order.channel_expiry_blocks.into(),
-	);
-
-	bool
+/// Checks the given [`OptionsSupported`] are consistent, i.e., all advertised ranges are
+/// non-empty.
+pub fn validate_options_supported(options: &OptionsSupported) -> Result<(), String> {
+	let ranges = [
+		(
+			"initial_client_balance_sat",
+			options.min_initial_client_balance_sat,
+			options.max_initial_client_balance_sat,
+		),
+		(
+			"initial_lsp_balance_sat",
+			options.min_initial_lsp_balance_sat,
+			options.max_initial_lsp_balance_sat,
+		),
+		("channel_balance_sat", options.min_channel_balance_sat, options.max_channel_balance_sat),
+	];
+
+	for (name, min, max) in ranges {
+		if min > max {
+			return Err(format!("min_{} ({}) exceeds max_{} ({})", name, min, name, max));
+		}
+	}
+
+	if options.max_channel_expiry_blocks == 0 {
+		return Err("max_channel_expiry_blocks must be non-zero".to_string());
+	}
+
+	Ok(())
+}
+
+fn range_violation(property: &str, min: u64, max: u64, value: u64) -> Option<OrderParamsViolation> {
+	if check_range(min, max, value) {
+		return None;
+	}
+
+	Some(OrderParamsViolation {
+		property: property.to_string(),
+		message: format!("Must be between {} and {}, but was {}", min, max, value),
+	})
+}
+
+fn min_violation(property: &str, min: u64, value: u64) -> Option<OrderParamsViolation> {
+	if value >= min {
+		return None;
+	}
+
+	Some(OrderParamsViolation {
+		property: property.to_string(),
+		message: format!("Must be at least {}, but was {}", min, value),
+	})
+}
+
+/// Checks the given [`OrderParams`] against the [`OptionsSupported`] by the LSP, returning all
+/// violating fields.
+///
+/// Note that [`OptionsSupported::supports_zero_channel_reserve`] and
+/// [`OptionsSupported::min_onchain_payment_size_sat`] don't constrain the order itself, but only
+/// the resulting channel and how the order may be paid, respectively. The latter is checked by
+/// [`validate_payment_info`].
+pub fn validate_order(
+	order: &OrderParams, options: &OptionsSupported,
+) -> Result<(), Vec<OrderParamsViolation>> {
+	let channel_balance_sat = order.client_balance_sat.saturating_add(order.lsp_balance_sat);
+	let channel_balance_violation = if check_range(
+		options.min_channel_balance_sat,
+		options.max_channel_balance_sat,
+		channel_balance_sat,
+	) {
+		None
+	} else {
+		// The channel size is not a property of its own, so we report it under the name of the
+		// options constraining it.
+		Some(OrderParamsViolation {
+			property: "channel_balance_sat".to_string(),
+			message: format!(
+				"Channel size (client_balance_sat + lsp_balance_sat) must be between {} and {}, but was {}",
+				options.min_channel_balance_sat, options.max_channel_balance_sat, channel_balance_sat
+			),
+		})
+	};
+
+	let violations: Vec<OrderParamsViolation> = [
+		range_violation(
+			"client_balance_sat",
+			options.min_initial_client_balance_sat,
+			options.max_initial_client_balance_sat,
+			order.client_balance_sat,
+		),
+		range_violation(
+			"lsp_balance_sat",
+			options.min_initial_lsp_balance_sat,
+			options.max_initial_lsp_balance_sat,
+			order.lsp_balance_sat,
+		),
+		channel_balance_violation,
+		min_violation(
+			"required_channel_confirmations",
+			options.min_required_channel_confirmations.into(),
+			order.required_channel_confirmations.into(),
+		),
+		min_violation(
+			"funding_confirms_within_blocks",
+			options.min_funding_confirms_within_blocks.into(),
+			order.funding_confirms_within_blocks.into(),
+		),
+		range_violation(
+			"channel_expiry_blocks",
+			1,
+			options.max_channel_expiry_blocks.into(),
+			order.channel_expiry_blocks.into(),
+		),
+	]
+	.into_iter()
+	.flatten()
+	.collect();
+
+	if violations.is_empty() {
+		Ok(())
+	} else {
+		Err(violations)
+	}
+}
+
+/// Checks the given [`PaymentInfo`] can be paid on-chain as per the [`OptionsSupported`], i.e.,
+/// that the order total meets [`OptionsSupported::min_onchain_payment_size_sat`].
+pub fn validate_payment_info(
+	payment: &PaymentInfo, options: &OptionsSupported,
+) -> Result<(), String> {
+	match options.min_onchain_payment_size_sat {
+		Some(min_onchain_payment_size_sat)
+			if payment.order_total_sat < min_onchain_payment_size_sat =>
+		{
+			Err(format!(
+				"order_total_sat ({}) is below min_onchain_payment_size_sat ({})",
+				payment.order_total_sat, min_onchain_payment_size_sat
+			))
+		},
+		_ => Ok(()),
+	}
+}
+
+/// Returns the amount in satoshis that needs to be refunded to the client for the given payment,
+/// if any.
+///
+/// A confirmed on-chain payment is refunded in full, even if it didn't cover the order total. A
+/// Lightning payment is only refunded once it was claimed, as held HTLCs should simply be failed
+/// back instead.
+pub fn refundable_amount_sat(payment: &PaymentInfo) -> Option<u64> {
+	let onchain_payment = payment.onchain_payment.as_ref();
+	match payment.state {
+		PaymentState::Paid => Some(
+			onchain_payment.map_or(payment.order_total_sat, |onchain_payment| onchain_payment.sat),
+		),
+		PaymentState::ExpectPayment | PaymentState::Hold => onchain_payment
+			.filter(|onchain_payment| onchain_payment.confirmed)
+			.map(|onchain_payment| onchain_payment.sat),
+		PaymentState::Refunded => None,
+	}
+	.filter(|amount_sat| *amount_sat > 0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::alloc::string::ToString;
+	use crate::lsps1::msgs::OnchainPayment;
+	use crate::tests::utils::{
+		lsps1_options_supported as options_supported, lsps1_payment_info as payment_info,
+	};
+
+	fn onchain_payment(sat: u64, confirmed: bool) -> OnchainPayment {
+		OnchainPayment {
+			outpoint: "0301e0480b374b32851a9462db29dc19fe830a7f7d7a88b81612b9d42099c0ae:1"
+				.to_string(),
+			sat,
+			confirmed,
+		}
+	}
+
+	#[test]
+	fn options_supported_validation() {
+		let mut options = options_supported();
+		assert!(validate_options_supported(&options).is_ok());
+
+		options.min_initial_lsp_balance_sat = 200_000_000;
+		assert!(validate_options_supported(&options).is_err());
+
+		options.min_initial_lsp_balance_sat = 100_000;
+		options.max_channel_expiry_blocks = 0;
+		assert!(validate_options_supported(&options).is_err());
+	}
+
+	#[test]
+	fn order_validation() {
+		let options = options_supported();
+		let mut order = OrderParams {
+			lsp_balance_sat: 5_000_000,
+			client_balance_sat: 20_000_000,
+			required_channel_confirmations: 0,
+			funding_confirms_within_blocks: 6,
+			channel_expiry_blocks: 144,
+			token: "".to_string(),
+			refund_onchain_address: None,
+			announce_channel: true,
+		};
+		assert_eq!(validate_order(&order, &options), Ok(()));
+
+		order.client_balance_sat = 1_000;
+		order.funding_confirms_within_blocks = 1;
+		order.channel_expiry_blocks = 0;
+		let properties: Vec<String> = validate_order(&order, &options)
+			.unwrap_err()
+			.into_iter()
+			.map(|violation| violation.property)
+			.collect();
+		assert_eq!(
+			properties,
+			vec!["client_balance_sat", "funding_confirms_within_blocks", "channel_expiry_blocks"]
+		);
+
+		// The channel size is checked in addition to the individual balances.
+		order.client_balance_sat = 100_000_000;
+		order.lsp_balance_sat = 100_000_000;
+		order.funding_confirms_within_blocks = 6;
+		order.channel_expiry_blocks = 144;
+		let violations = validate_order(&order, &options).unwrap_err();
+		assert_eq!(violations.len(), 1);
+		assert_eq!(violations[0].property, "channel_balance_sat");
+	}
+
+	#[test]
+	fn payment_info_validation() {
+		let mut options = options_supported();
+		let payment = payment_info(PaymentState::ExpectPayment, None);
+		assert!(validate_payment_info(&payment, &options).is_ok());
+
+		options.min_onchain_payment_size_sat = Some(payment.order_total_sat + 1);
+		assert!(validate_payment_info(&payment, &options).is_err());
+
+		options.min_onchain_payment_size_sat = None;
+		assert!(validate_payment_info(&payment, &options).is_ok());
+	}
+
+	#[test]
+	fn refundable_amount() {
+		// Claimed Lightning payments are refunded with the order total.
+		assert_eq!(refundable_amount_sat(&payment_info(PaymentState::Paid, None)), Some(2008888));
+		// Held Lightning payments are to be failed back instead.
+		assert_eq!(refundable_amount_sat(&payment_info(PaymentState::Hold, None)), None);
+		assert_eq!(refundable_amount_sat(&payment_info(PaymentState::ExpectPayment, None)), None);
+
+		// On-chain payments are refunded in full, even if they were insufficient.
+		let payment = payment_info(PaymentState::Paid, Some(onchain_payment(3_000_000, true)));
+		assert_eq!(refundable_amount_sat(&payment), Some(3_000_000));
+		let payment = payment_info(PaymentState::ExpectPayment, Some(onchain_payment(1200, true)));
+		assert_eq!(refundable_amount_sat(&payment), Some(1200));
+		let payment = payment_info(PaymentState::ExpectPayment, Some(onchain_payment(1200, false)));
+		assert_eq!(refundable_amount_sat(&payment), None);
+
+		let payment = payment_info(PaymentState::Refunded, Some(onchain_payment(1200, true)));
+		assert_eq!(refundable_amount_sat(&payment), None);
+	}
 }
@@ -10,20 +10,26 @@
 //! Contains the main LSPS2 client object, [`LSPS2ClientHandler`].
 
 use crate::events::{Event, EventQueue};
-use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
+use crate::lsps0::discovery::LSPS2Quote;
+use crate::lsps0::ser::{LSPSMessage, ProtocolMessageHandler, RequestId, ResponseError};
 use crate::lsps2::event::LSPS2ClientEvent;
+use crate::lsps2::utils::{
+	compute_applicable_opening_fee, compute_opening_fee, is_expired_opening_fee_params,
+	seconds_until_expiry,
+};
 use crate::message_queue::MessageQueue;
-use crate::prelude::{HashMap, HashSet, String};
+use crate::prelude::{HashMap, HashSet, String, ToString, Vec, VecDeque};
 use crate::sync::{Arc, Mutex, RwLock};
 
 use lightning::ln::msgs::{ErrorAction, LightningError};
+use lightning::ln::{ChannelId, PaymentHash};
 use lightning::sign::EntropySource;
+use lightning::util::config::ChannelConfig;
 use lightning::util::errors::APIError;
 use lightning::util::logger::Level;
 
 use bitcoin::secp256k1::PublicKey;
 
-use core::default::Default;
 use core::ops::Deref;
 
 use crate::lsps2::msgs::{
@@ -32,35 +38,328 @@ use crate::lsps2::msgs::{
 };
 
 /// Client-side configuration options for JIT channels.
-#[derive(Clone, Debug, Copy)]
-pub struct LSPS2ClientConfig {}
+///
+/// Opening fee parameters that expired or cover an empty payment size range are always rejected.
+/// As expiry can only be checked against a known time, no-std builds reject all parameters until
+/// a block has been seen by the [`LiquidityManager`]'s [`Confirm`] implementation.
+///
+/// [`LiquidityManager`]: crate::LiquidityManager
+/// [`Confirm`]: lightning::chain::Confirm
+/// The limits given here allow to additionally reject parameters our wallet doesn't consider
+/// acceptable. Unset limits aren't enforced.
+#[derive(Clone, Debug, Copy, Default)]
+pub struct LSPS2ClientConfig {
+	/// The maximum `min_fee_msat` we accept.
+	pub max_min_fee_msat: Option<u64>,
+	/// The maximum `proportional` fee, in parts per million, we accept.
+	pub max_proportional: Option<u32>,
+	/// The minimum `min_lifetime`, in blocks, we accept, i.e., how long the LSP must at least
+	/// promise to keep the channel open.
+	pub min_lifetime: Option<u32>,
+	/// The maximum `max_client_to_self_delay`, in blocks, we accept.
+	pub max_client_to_self_delay: Option<u32>,
+	/// The minimum number of seconds the parameters need to remain valid for, giving us enough
+	/// time to select them and have the invoice paid.
+	///
+	/// In no-std builds this is checked against the time of the latest block we've seen.
+	pub min_validity_secs: Option<u64>,
+}
 
-impl Default for LSPS2ClientConfig {
-	fn default() -> Self {
-		Self {}
+impl LSPS2ClientConfig {
+	/// Checks the given opening fee parameters against our limits, returning a description of the
+	/// first limit they violate, if any.
+	fn check_opening_fee_params(
+		&self, params: &OpeningFeeParams, now_secs: Option<u64>,
+	) -> Result<(), String> {
+		let now_secs = now_secs.ok_or_else(|| {
+			"the current time is unknown, so the params' expiry can't be checked".to_string()
+		})?;
+		if is_expired_opening_fee_params(params, now_secs) {
+			return Err(format!("params expired at {}", params.valid_until));
+		}
+		if params.min_payment_size_msat > params.max_payment_size_msat {
+			return Err(format!(
+				"min_payment_size_msat {} exceeds max_payment_size_msat {}",
+				params.min_payment_size_msat, params.max_payment_size_msat
+			));
+		}
+		if let Some(max_min_fee_msat) = self.max_min_fee_msat {
+			if params.min_fee_msat > max_min_fee_msat {
+				return Err(format!(
+					"min_fee_msat {} exceeds our limit of {}",
+					params.min_fee_msat, max_min_fee_msat
+				));
+			}
+		}
+		if let Some(max_proportional) = self.max_proportional {
+			if params.proportional > max_proportional {
+				return Err(format!(
+					"proportional {} exceeds our limit of {}",
+					params.proportional, max_proportional
+				));
+			}
+		}
+		if let Some(min_lifetime) = self.min_lifetime {
+			if params.min_lifetime < min_lifetime {
+				return Err(format!(
+					"min_lifetime {} is below our limit of {}",
+					params.min_lifetime, min_lifetime
+				));
+			}
+		}
+		if let Some(max_client_to_self_delay) = self.max_client_to_self_delay {
+			if params.max_client_to_self_delay > max_client_to_self_delay {
+				return Err(format!(
+					"max_client_to_self_delay {} exceeds our limit of {}",
+					params.max_client_to_self_delay, max_client_to_self_delay
+				));
+			}
+		}
+		if let Some(min_validity_secs) = self.min_validity_secs {
+			let secs = seconds_until_expiry(params, now_secs);
+			if secs < min_validity_secs as i64 {
+				return Err(format!(
+					"params are only valid for {} more seconds, below our limit of {}",
+					secs, min_validity_secs
+				));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// What to do with a payment received via a JIT channel, as determined by
+/// [`LSPS2ClientHandler::check_skimmed_fee`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JITPaymentAction {
+	/// The LSP didn't skim more than the agreed upon opening fee, the payment should be claimed.
+	Claim,
+	/// The LSP skimmed more than the agreed upon opening fee, the payment should be failed via
+	/// [`ChannelManager::fail_htlc_backwards`].
+	///
+	/// [`ChannelManager::fail_htlc_backwards`]: lightning::ln::channelmanager::ChannelManager::fail_htlc_backwards
+	Fail {
+		/// The opening fee we agreed to pay for the payment.
+		max_opening_fee_msat: u64,
+		/// The fee the LSP actually skimmed.
+		skimmed_fee_msat: u64,
+	},
+}
+
+/// Describes how to accept an inbound channel the LSP opens to us as part of a JIT channel
+/// purchase, as returned by [`LSPS2ClientHandler::jit_channel_open_requested`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JITChannelAcceptance {
+	/// The identifier of the `buy` request the channel was purchased with, as returned by
+	/// [`LSPS2ClientHandler::select_opening_params`].
+	pub request_id: RequestId,
+	/// The intercept short channel id the channel was purchased for.
+	pub intercept_scid: u64,
+	/// The `user_channel_id` to accept the channel with.
+	pub user_channel_id: u128,
+}
+
+impl JITChannelAcceptance {
+	/// Returns the given channel config, adjusted to allow the LSP to skim the opening fee from
+	/// the payments it forwards over the channel.
+	pub fn channel_config(&self, mut config: ChannelConfig) -> ChannelConfig {
+		config.accept_underpaying_htlcs = true;
+		config
 	}
 }
 
+/// Details about a `get_info` request we didn't receive a response to yet, as returned by
+/// [`LSPS2ClientHandler::list_pending_get_info_requests`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingGetInfoRequest {
+	/// The identifier of the request.
+	pub request_id: RequestId,
+	/// The node id of the LSP the request was sent to.
+	pub counterparty_node_id: PublicKey,
+	/// The identifier returned by [`LSPS2ClientHandler::request_best_opening_params`], if the
+	/// request was sent as part of a comparison.
+	pub comparison_id: Option<RequestId>,
+	/// The number of seconds since the request was sent, or [`Option::None`] in no-std builds.
+	pub age_secs: Option<u64>,
+}
+
+/// Details about a JIT channel purchase, as returned by
+/// [`LSPS2ClientHandler::list_jit_channel_purchases`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JITChannelPurchaseDetails {
+	/// The identifier of the `buy` request, as returned by
+	/// [`LSPS2ClientHandler::select_opening_params`].
+	pub request_id: RequestId,
+	/// The node id of the LSP the JIT channel is bought from.
+	pub counterparty_node_id: PublicKey,
+	/// The intercept short channel id the LSP assigned, once it responded to the `buy` request.
+	pub intercept_scid: Option<u64>,
+	/// The state the purchase is in.
+	pub state: JITChannelPurchaseState,
+	/// The size of the initial payment we requested, if any.
+	pub payment_size_msat: Option<u64>,
+	/// The opening fee parameters we selected.
+	pub opening_fee_params: OpeningFeeParams,
+	/// The number of seconds since the `buy` request was sent, or [`Option::None`] in no-std
+	/// builds.
+	pub age_secs: Option<u64>,
+}
+
+/// The state of a JIT channel purchase, see [`JITChannelPurchaseDetails::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JITChannelPurchaseState {
+	/// We are awaiting the LSP's response to the `buy` request.
+	PendingBuy,
+	/// The LSP accepted the `buy` request, and we are awaiting it to open the channel.
+	AwaitingChannelOpen,
+	/// We accepted the channel the LSP requested to open, and are awaiting it to become ready.
+	ChannelAccepted {
+		/// The `user_channel_id` we accepted the channel with.
+		user_channel_id: u128,
+	},
+	/// The channel is ready.
+	ChannelOpened,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InboundJITChannelState {
+	AwaitingChannelOpen,
+	ChannelAccepted { user_channel_id: u128 },
+	ChannelOpened,
+}
+
 struct InboundJITChannel {
+	request_id: RequestId,
 	payment_size_msat: Option<u64>,
+	opening_fee_params: OpeningFeeParams,
+	state: InboundJITChannelState,
+	created_at: Option<u64>,
 }
 
 impl InboundJITChannel {
-	fn new(payment_size_msat: Option<u64>) -> Self {
-		Self { payment_size_msat }
+	fn new(
+		request_id: RequestId, payment_size_msat: Option<u64>, opening_fee_params: OpeningFeeParams,
+	) -> Self {
+		Self {
+			request_id,
+			payment_size_msat,
+			opening_fee_params,
+			state: InboundJITChannelState::AwaitingChannelOpen,
+			created_at: crate::utils::seconds_since_epoch(),
+		}
+	}
+
+	fn details(
+		&self, counterparty_node_id: PublicKey, intercept_scid: Option<u64>,
+	) -> JITChannelPurchaseDetails {
+		let state = match (intercept_scid, self.state) {
+			(None, _) => JITChannelPurchaseState::PendingBuy,
+			(Some(_), InboundJITChannelState::AwaitingChannelOpen) => {
+				JITChannelPurchaseState::AwaitingChannelOpen
+			},
+			(Some(_), InboundJITChannelState::ChannelAccepted { user_channel_id }) => {
+				JITChannelPurchaseState::ChannelAccepted { user_channel_id }
+			},
+			(Some(_), InboundJITChannelState::ChannelOpened) => {
+				JITChannelPurchaseState::ChannelOpened
+			},
+		};
+		JITChannelPurchaseDetails {
+			request_id: self.request_id.clone(),
+			counterparty_node_id,
+			intercept_scid,
+			state,
+			payment_size_msat: self.payment_size_msat,
+			opening_fee_params: self.opening_fee_params.clone(),
+			age_secs: crate::utils::seconds_elapsed_since(self.created_at),
+		}
+	}
+
+	/// Returns the maximum opening fee the LSP may skim from a payment for which we received
+	/// `amount_msat` after fees.
+	fn max_opening_fee_msat(&self, amount_msat: u64, skimmed_fee_msat: u64) -> Option<u64> {
+		// Without a fixed payment size, the fee is computed on the payment as forwarded to the LSP.
+		let payment_size_msat = match self.payment_size_msat {
+			Some(payment_size_msat) => payment_size_msat,
+			None => amount_msat.checked_add(skimmed_fee_msat)?,
+		};
+		compute_opening_fee(
+			payment_size_msat,
+			self.opening_fee_params.min_fee_msat,
+			self.opening_fee_params.proportional.into(),
+		)
 	}
+
+	/// Returns whether a channel of `funding_satoshis` can carry the payment we bought the channel
+	/// for, after the LSP skimmed its opening fee.
+	fn fits_channel_size(&self, funding_satoshis: u64) -> bool {
+		let payment_size_msat = match self.payment_size_msat {
+			Some(payment_size_msat) => payment_size_msat,
+			// Without a fixed payment size, any channel size may be sufficient.
+			None => return true,
+		};
+		let opening_fee_msat = self.max_opening_fee_msat(payment_size_msat, 0).unwrap_or(0);
+		funding_satoshis.saturating_mul(1000) >= payment_size_msat.saturating_sub(opening_fee_msat)
+	}
+}
+
+struct OpeningParamsComparison {
+	payment_size_msat: u64,
+	pending_counterparties: HashSet<PublicKey>,
+	quotes: Vec<LSPS2Quote>,
 }
 
 struct PeerState {
-	pending_get_info_requests: HashSet<RequestId>,
+	// Maps the pending `get_info` requests to the time we sent them at.
+	pending_get_info_requests: HashMap<RequestId, Option<u64>>,
 	pending_buy_requests: HashMap<RequestId, InboundJITChannel>,
+	// Maps the `get_info` requests issued as part of a comparison to the comparison's id.
+	comparison_get_info_requests: HashMap<RequestId, RequestId>,
+	jit_channels_by_scid: HashMap<u64, InboundJITChannel>,
+	// The intercept scids of the JIT channels awaiting a channel open, in order of purchase.
+	awaiting_channel_open_scids: VecDeque<u64>,
 }
 
 impl PeerState {
 	fn new() -> Self {
-		let pending_get_info_requests = HashSet::new();
+		let pending_get_info_requests = HashMap::new();
 		let pending_buy_requests = HashMap::new();
-		Self { pending_get_info_requests, pending_buy_requests }
+		let comparison_get_info_requests = HashMap::new();
+		let jit_channels_by_scid = HashMap::new();
+		let awaiting_channel_open_scids = VecDeque::new();
+		Self {
+			pending_get_info_requests,
+			pending_buy_requests,
+			comparison_get_info_requests,
+			jit_channels_by_scid,
+			awaiting_channel_open_scids,
+		}
+	}
+
+	fn pending_get_info_requests(
+		&self, counterparty_node_id: PublicKey,
+	) -> impl Iterator<Item = PendingGetInfoRequest> + '_ {
+		self.pending_get_info_requests.iter().map(move |(request_id, created_at)| {
+			PendingGetInfoRequest {
+				request_id: request_id.clone(),
+				counterparty_node_id,
+				comparison_id: self.comparison_get_info_requests.get(request_id).cloned(),
+				age_secs: crate::utils::seconds_elapsed_since(*created_at),
+			}
+		})
+	}
+
+	fn jit_channel_purchases(
+		&self, counterparty_node_id: PublicKey,
+	) -> impl Iterator<Item = JITChannelPurchaseDetails> + '_ {
+		let pending_buys = self
+			.pending_buy_requests
+			.values()
+			.map(move |jit_channel| jit_channel.details(counterparty_node_id, None));
+		let bought = self.jit_channels_by_scid.iter().map(move |(intercept_scid, jit_channel)| {
+			jit_channel.details(counterparty_node_id, Some(*intercept_scid))
+		});
+		pending_buys.chain(bought)
 	}
 }
 
@@ -79,7 +378,10 @@ where
 	pending_messages: Arc<MessageQueue>,
 	pending_events: Arc<EventQueue>,
 	per_peer_state: RwLock<HashMap<PublicKey, Mutex<PeerState>>>,
-	_config: LSPS2ClientConfig,
+	pending_comparisons: Mutex<HashMap<RequestId, OpeningParamsComparison>>,
+	jit_payment_hashes: Mutex<HashMap<PaymentHash, (PublicKey, u64)>>,
+	latest_block_time: RwLock<Option<u64>>,
+	config: LSPS2ClientConfig,
 }
 
 impl<ES: Deref> LSPS2ClientHandler<ES>
@@ -89,17 +391,26 @@ where
 	/// Constructs an `LSPS2ClientHandler`.
 	pub(crate) fn new(
 		entropy_source: ES, pending_messages: Arc<MessageQueue>, pending_events: Arc<EventQueue>,
-		_config: LSPS2ClientConfig,
+		config: LSPS2ClientConfig,
 	) -> Self {
 		Self {
 			entropy_source,
 			pending_messages,
 			pending_events,
 			per_peer_state: RwLock::new(HashMap::new()),
-			_config,
+			pending_comparisons: Mutex::new(HashMap::new()),
+			jit_payment_hashes: Mutex::new(HashMap::new()),
+			latest_block_time: RwLock::new(None),
+			config,
 		}
 	}
 
+	/// Returns the current time in seconds since the unix epoch, falling back to the time of the
+	/// latest block we've seen in no-std builds.
+	fn current_time(&self) -> Option<u64> {
+		crate::utils::seconds_since_epoch().or(*self.latest_block_time.read().unwrap())
+	}
+
 	/// Request the channel opening parameters from the LSP.
 	///
 	/// This initiates the JIT-channel flow that, at the end of it, will have the LSP
@@ -112,28 +423,113 @@ where
 	/// `token` is an optional `String` that will be provided to the LSP.
 	/// It can be used by the LSP as an API key, coupon code, or some other way to identify a user.
 	///
-	/// Returns the used [`RequestId`], which will be returned via [`OpeningParametersReady`].
+	/// Returns the used [`RequestId`], which will be returned via [`OpeningParametersReady`], or
+	/// an error if the `token` is too long for the request to fit into an LSPS message.
 	///
 	/// [`OpeningParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::OpeningParametersReady
 	pub fn request_opening_params(
 		&self, counterparty_node_id: PublicKey, token: Option<String>,
-	) -> RequestId {
+	) -> Result<RequestId, APIError> {
 		let request_id = crate::utils::generate_request_id(&self.entropy_source);
 
+		let request = LSPS2Request::GetInfo(GetInfoRequest { token });
+		let msg: LSPSMessage = LSPS2Message::Request(request_id.clone(), request).into();
+		msg.to_payload()?;
+
 		{
 			let mut outer_state_lock = self.per_peer_state.write().unwrap();
 			let inner_state_lock = outer_state_lock
 				.entry(counterparty_node_id)
 				.or_insert(Mutex::new(PeerState::new()));
 			let mut peer_state_lock = inner_state_lock.lock().unwrap();
-			peer_state_lock.pending_get_info_requests.insert(request_id.clone());
+			peer_state_lock
+				.pending_get_info_requests
+				.insert(request_id.clone(), crate::utils::seconds_since_epoch());
 		}
 
-		let request = LSPS2Request::GetInfo(GetInfoRequest { token });
-		let msg = LSPS2Message::Request(request_id.clone(), request).into();
 		self.pending_messages.enqueue(&counterparty_node_id, msg);
 
-		request_id
+		Ok(request_id)
+	}
+
+	/// Requests the channel opening parameters from several LSPs at once and determines the
+	/// cheapest offer for a payment of the given size.
+	///
+	/// Once all LSPs responded, the user will receive the offer with the lowest opening fee, as
+	/// computed by [`compute_applicable_opening_fee`], via a [`BestOpeningParametersReady`] event.
+	/// Offers that expired, don't cover `payment_size_msat`, or whose opening fee wouldn't be
+	/// smaller than the payment itself are discarded. Note that no [`OpeningParametersReady`]
+	/// events are emitted for the individual LSPs.
+	///
+	/// The comparison only completes once every LSP responded, which an LSP might never do, e.g.,
+	/// if we're not connected to it. The user is therefore expected to cancel requests that were
+	/// pending for too long via [`LSPS2ClientHandler::cancel_get_info_request`], after which the
+	/// comparison completes based on the responses received so far. The pending requests and
+	/// their age can be retrieved via [`LSPS2ClientHandler::list_pending_get_info_requests`].
+	///
+	/// `token` is an optional `String` that will be provided to every LSP.
+	///
+	/// Returns the [`RequestId`] that will be returned via [`BestOpeningParametersReady`], or an
+	/// error if the `token` is too long for the requests to fit into an LSPS message.
+	///
+	/// [`BestOpeningParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::BestOpeningParametersReady
+	/// [`OpeningParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::OpeningParametersReady
+	pub fn request_best_opening_params(
+		&self, counterparty_node_ids: &[PublicKey], payment_size_msat: u64, token: Option<String>,
+	) -> Result<RequestId, APIError> {
+		if counterparty_node_ids.is_empty() {
+			return Err(APIError::APIMisuseError {
+				err: "At least one counterparty is required to compare opening parameters"
+					.to_string(),
+			});
+		}
+
+		let comparison_id = crate::utils::generate_request_id(&self.entropy_source);
+		let pending_counterparties = counterparty_node_ids.iter().copied().collect::<HashSet<_>>();
+
+		// All requests only differ in their equally long request ids, so checking one suffices.
+		let request = LSPS2Request::GetInfo(GetInfoRequest { token: token.clone() });
+		let msg: LSPSMessage = LSPS2Message::Request(comparison_id.clone(), request).into();
+		msg.to_payload()?;
+
+		{
+			let mut pending_comparisons = self.pending_comparisons.lock().unwrap();
+			let comparison = OpeningParamsComparison {
+				payment_size_msat,
+				pending_counterparties: pending_counterparties.clone(),
+				quotes: Vec::new(),
+			};
+			if pending_comparisons.insert(comparison_id.clone(), comparison).is_some() {
+				return Err(APIError::APIMisuseError {
+					err: "Failed due to duplicate request_id. This should never happen!"
+						.to_string(),
+				});
+			}
+		}
+
+		for counterparty_node_id in pending_counterparties {
+			let request_id = crate::utils::generate_request_id(&self.entropy_source);
+
+			{
+				let mut outer_state_lock = self.per_peer_state.write().unwrap();
+				let inner_state_lock: &Mutex<PeerState> = outer_state_lock
+					.entry(counterparty_node_id)
+					.or_insert(Mutex::new(PeerState::new()));
+				let mut peer_state_lock = inner_state_lock.lock().unwrap();
+				peer_state_lock
+					.pending_get_info_requests
+					.insert(request_id.clone(), crate::utils::seconds_since_epoch());
+				peer_state_lock
+					.comparison_get_info_requests
+					.insert(request_id.clone(), comparison_id.clone());
+			}
+
+			let request = LSPS2Request::GetInfo(GetInfoRequest { token: token.clone() });
+			let msg = LSPS2Message::Request(request_id, request).into();
+			self.pending_messages.enqueue(&counterparty_node_id, msg);
+		}
+
+		Ok(comparison_id)
 	}
 
 	/// Confirms a set of chosen channel opening parameters to use for the JIT channel and
@@ -152,14 +548,44 @@ where
 	/// The client agrees to paying an opening fee equal to
 	/// `max(min_fee_msat, proportional*(payment_size_msat/1_000_000))`.
 	///
+	/// Returns an error if the parameters violate the limits set in [`LSPS2ClientConfig`], don't
+	/// cover the given `payment_size_msat`, or are too large to fit into an LSPS message.
+	///
 	/// [`OpeningParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::OpeningParametersReady
 	/// [`InvoiceParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::InvoiceParametersReady
 	pub fn select_opening_params(
 		&self, counterparty_node_id: PublicKey, payment_size_msat: Option<u64>,
 		opening_fee_params: OpeningFeeParams,
 	) -> Result<RequestId, APIError> {
+		let now_secs = self.current_time();
+		self.config.check_opening_fee_params(&opening_fee_params, now_secs).map_err(|e| {
+			APIError::APIMisuseError { err: format!("Unacceptable opening fee params: {}", e) }
+		})?;
+		if let Some(payment_size_msat) = payment_size_msat {
+			let applicable = now_secs.and_then(|now_secs| {
+				compute_applicable_opening_fee(&opening_fee_params, payment_size_msat, now_secs)
+			});
+			if applicable.is_none() {
+				return Err(APIError::APIMisuseError {
+					err: format!(
+						"Opening fee params aren't applicable to a payment of {} msat",
+						payment_size_msat
+					),
+				});
+			}
+		}
+
 		let request_id = crate::utils::generate_request_id(&self.entropy_source);
 
+		// The parameters, including their `promise`, are provided by the LSP, so we make sure they
+		// can be echoed back before tracking the request.
+		let request = LSPS2Request::Buy(BuyRequest {
+			opening_fee_params: opening_fee_params.clone(),
+			payment_size_msat,
+		});
+		let msg: LSPSMessage = LSPS2Message::Request(request_id.clone(), request).into();
+		msg.to_payload()?;
+
 		{
 			let mut outer_state_lock = self.per_peer_state.write().unwrap();
 			let inner_state_lock = outer_state_lock
@@ -167,25 +593,326 @@ where
 				.or_insert(Mutex::new(PeerState::new()));
 			let mut peer_state_lock = inner_state_lock.lock().unwrap();
 
-			let jit_channel = InboundJITChannel::new(payment_size_msat);
+			let jit_channel =
+				InboundJITChannel::new(request_id.clone(), payment_size_msat, opening_fee_params);
 			if peer_state_lock
 				.pending_buy_requests
 				.insert(request_id.clone(), jit_channel)
 				.is_some()
 			{
 				return Err(APIError::APIMisuseError {
-					err: format!("Failed due to duplicate request_id. This should never happen!"),
+					err: "Failed due to duplicate request_id. This should never happen!"
+						.to_string(),
 				});
 			}
 		}
 
-		let request = LSPS2Request::Buy(BuyRequest { opening_fee_params, payment_size_msat });
-		let msg = LSPS2Message::Request(request_id.clone(), request).into();
 		self.pending_messages.enqueue(&counterparty_node_id, msg);
 
 		Ok(request_id)
 	}
 
+	/// Registers the payment hash of an invoice generated for a JIT channel, allowing to check the
+	/// opening fee skimmed from the payment via [`LSPS2ClientHandler::check_skimmed_fee`].
+	///
+	/// Should be called after creating the invoice with the parameters received via an
+	/// [`InvoiceParametersReady`] event.
+	///
+	/// [`InvoiceParametersReady`]: crate::lsps2::event::LSPS2ClientEvent::InvoiceParametersReady
+	pub fn register_jit_payment(
+		&self, counterparty_node_id: &PublicKey, intercept_scid: u64, payment_hash: PaymentHash,
+	) -> Result<(), APIError> {
+		let is_known_channel = match self.per_peer_state.read().unwrap().get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				inner_state_lock.lock().unwrap().jit_channels_by_scid.contains_key(&intercept_scid)
+			},
+			None => false,
+		};
+		if !is_known_channel {
+			return Err(APIError::APIMisuseError {
+				err: format!(
+					"No JIT channel with intercept scid {} bought from {}",
+					intercept_scid, counterparty_node_id
+				),
+			});
+		}
+
+		self.jit_payment_hashes
+			.lock()
+			.unwrap()
+			.insert(payment_hash, (*counterparty_node_id, intercept_scid));
+		Ok(())
+	}
+
+	/// Checks the fee the LSP skimmed from a payment we received, returning whether it should be
+	/// claimed or failed.
+	///
+	/// Should be called when handling an [`Event::PaymentClaimable`], passing its `payment_hash`,
+	/// `amount_msat`, and `counterparty_skimmed_fee_msat`. For payments registered via
+	/// [`LSPS2ClientHandler::register_jit_payment`], the skimmed fee must not exceed the opening
+	/// fee we agreed to when selecting the opening fee parameters. Any other payment must not have
+	/// had a fee skimmed at all.
+	///
+	/// Once a registered payment is to be claimed, we stop tracking its payment hash, as well as
+	/// the JIT channel purchase if the channel was already opened. Payments to be failed remain
+	/// registered, as the sender may retry them.
+	///
+	/// [`Event::PaymentClaimable`]: lightning::events::Event::PaymentClaimable
+	pub fn check_skimmed_fee(
+		&self, payment_hash: &PaymentHash, amount_msat: u64, counterparty_skimmed_fee_msat: u64,
+	) -> JITPaymentAction {
+		let jit_payment = self.jit_payment_hashes.lock().unwrap().get(payment_hash).copied();
+		let (max_opening_fee_msat, channel_opened) = jit_payment
+			.and_then(|(counterparty_node_id, intercept_scid)| {
+				let outer_state_lock = self.per_peer_state.read().unwrap();
+				let peer_state = outer_state_lock.get(&counterparty_node_id)?.lock().unwrap();
+				let jit_channel = peer_state.jit_channels_by_scid.get(&intercept_scid)?;
+				let max_opening_fee_msat =
+					jit_channel.max_opening_fee_msat(amount_msat, counterparty_skimmed_fee_msat)?;
+				let channel_opened = jit_channel.state == InboundJITChannelState::ChannelOpened;
+				Some((max_opening_fee_msat, channel_opened))
+			})
+			.unwrap_or((0, false));
+
+		if counterparty_skimmed_fee_msat > max_opening_fee_msat {
+			return JITPaymentAction::Fail {
+				max_opening_fee_msat,
+				skimmed_fee_msat: counterparty_skimmed_fee_msat,
+			};
+		}
+
+		if let Some((counterparty_node_id, intercept_scid)) = jit_payment {
+			if channel_opened {
+				// The purchase is complete once we received the payment over the opened channel.
+				let outer_state_lock = self.per_peer_state.read().unwrap();
+				if let Some(inner_state_lock) = outer_state_lock.get(&counterparty_node_id) {
+					inner_state_lock.lock().unwrap().jit_channels_by_scid.remove(&intercept_scid);
+				}
+				self.jit_payment_hashes.lock().unwrap().retain(|_, jit_payment| {
+					*jit_payment != (counterparty_node_id, intercept_scid)
+				});
+			} else {
+				self.jit_payment_hashes.lock().unwrap().remove(payment_hash);
+			}
+		}
+
+		JITPaymentAction::Claim
+	}
+
+	/// Decides whether to accept an inbound channel the given counterparty requested to open.
+	///
+	/// Should be called when handling an [`Event::OpenChannelRequest`] with the request's
+	/// `funding_satoshis`. If the counterparty is an LSP we bought a JIT channel from that wasn't
+	/// opened yet, and the channel is large enough to carry the payment we bought it for, returns
+	/// how to accept the channel: it should be accepted via
+	/// [`ChannelManager::accept_inbound_channel_from_trusted_peer_0conf`] with the given
+	/// `user_channel_id`, and its config be updated to [`JITChannelAcceptance::channel_config`] via
+	/// [`ChannelManager::update_channel_config`] when handling the subsequent
+	/// [`Event::ChannelPending`]. If we bought several JIT channels from the counterparty, the
+	/// earliest purchase the channel is large enough for is assumed to be opened.
+	///
+	/// Returns [`Option::None`] if the request doesn't belong to a JIT channel purchase, in which
+	/// case it should be handled as any other request.
+	///
+	/// Note that only the channel size is matched against our purchases. The caller remains
+	/// responsible for checking that the requested `channel_type` and `push_msat` are acceptable
+	/// before accepting the channel.
+	///
+	/// [`Event::OpenChannelRequest`]: lightning::events::Event::OpenChannelRequest
+	/// [`Event::ChannelPending`]: lightning::events::Event::ChannelPending
+	/// [`ChannelManager::accept_inbound_channel_from_trusted_peer_0conf`]: lightning::ln::channelmanager::ChannelManager::accept_inbound_channel_from_trusted_peer_0conf
+	/// [`ChannelManager::update_channel_config`]: lightning::ln::channelmanager::ChannelManager::update_channel_config
+	pub fn jit_channel_open_requested(
+		&self, counterparty_node_id: &PublicKey, funding_satoshis: u64,
+	) -> Option<JITChannelAcceptance> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let mut peer_state_lock = outer_state_lock.get(counterparty_node_id)?.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let jit_channels_by_scid = &mut peer_state.jit_channels_by_scid;
+		let awaiting_channel_open_scids = &mut peer_state.awaiting_channel_open_scids;
+
+		awaiting_channel_open_scids.retain(|intercept_scid| {
+			matches!(
+				jit_channels_by_scid.get(intercept_scid),
+				Some(jit_channel) if jit_channel.state == InboundJITChannelState::AwaitingChannelOpen
+			)
+		});
+		let position = awaiting_channel_open_scids.iter().position(|intercept_scid| {
+			matches!(
+				jit_channels_by_scid.get(intercept_scid),
+				Some(jit_channel) if jit_channel.fits_channel_size(funding_satoshis)
+			)
+		})?;
+		let intercept_scid = awaiting_channel_open_scids.remove(position)?;
+		let jit_channel = jit_channels_by_scid.get_mut(&intercept_scid)?;
+
+		let mut user_channel_id = [0; 16];
+		user_channel_id.copy_from_slice(&self.entropy_source.get_secure_random_bytes()[..16]);
+		let user_channel_id = u128::from_be_bytes(user_channel_id);
+		jit_channel.state = InboundJITChannelState::ChannelAccepted { user_channel_id };
+		Some(JITChannelAcceptance {
+			request_id: jit_channel.request_id.clone(),
+			intercept_scid,
+			user_channel_id,
+		})
+	}
+
+	/// Forward [`Event::ChannelReady`] event parameters into this function.
+	///
+	/// Will generate a [`JITChannelOpened`] event if the channel was accepted following
+	/// [`LSPS2ClientHandler::jit_channel_open_requested`], and do nothing otherwise.
+	///
+	/// [`Event::ChannelReady`]: lightning::events::Event::ChannelReady
+	/// [`JITChannelOpened`]: crate::lsps2::event::LSPS2ClientEvent::JITChannelOpened
+	pub fn channel_ready(
+		&self, user_channel_id: u128, channel_id: &ChannelId, counterparty_node_id: &PublicKey,
+	) {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let mut peer_state = match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => inner_state_lock.lock().unwrap(),
+			None => return,
+		};
+
+		let accepted_state = InboundJITChannelState::ChannelAccepted { user_channel_id };
+		if let Some((intercept_scid, jit_channel)) = peer_state
+			.jit_channels_by_scid
+			.iter_mut()
+			.find(|(_, jit_channel)| jit_channel.state == accepted_state)
+		{
+			jit_channel.state = InboundJITChannelState::ChannelOpened;
+			self.pending_events.enqueue(Event::LSPS2Client(LSPS2ClientEvent::JITChannelOpened {
+				request_id: jit_channel.request_id.clone(),
+				counterparty_node_id: *counterparty_node_id,
+				intercept_scid: *intercept_scid,
+				channel_id: *channel_id,
+				user_channel_id,
+			}));
+		}
+	}
+
+	/// Returns the `get_info` requests we didn't receive a response to yet from any LSP.
+	pub fn list_pending_get_info_requests(&self) -> Vec<PendingGetInfoRequest> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		outer_state_lock
+			.iter()
+			.flat_map(|(counterparty_node_id, inner_state_lock)| {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state.pending_get_info_requests(*counterparty_node_id).collect::<Vec<_>>()
+			})
+			.collect()
+	}
+
+	/// Returns the `get_info` requests we didn't receive a response to yet from the given LSP.
+	pub fn list_pending_get_info_requests_with_counterparty(
+		&self, counterparty_node_id: &PublicKey,
+	) -> Vec<PendingGetInfoRequest> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state.pending_get_info_requests(*counterparty_node_id).collect()
+			},
+			None => Vec::new(),
+		}
+	}
+
+	/// Returns the JIT channels we requested to buy or bought from any LSP and still track.
+	pub fn list_jit_channel_purchases(&self) -> Vec<JITChannelPurchaseDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		outer_state_lock
+			.iter()
+			.flat_map(|(counterparty_node_id, inner_state_lock)| {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state.jit_channel_purchases(*counterparty_node_id).collect::<Vec<_>>()
+			})
+			.collect()
+	}
+
+	/// Returns the JIT channels we requested to buy or bought from the given LSP and still track.
+	pub fn list_jit_channel_purchases_with_counterparty(
+		&self, counterparty_node_id: &PublicKey,
+	) -> Vec<JITChannelPurchaseDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state.jit_channel_purchases(*counterparty_node_id).collect()
+			},
+			None => Vec::new(),
+		}
+	}
+
+	/// Stops tracking the given `get_info` request, e.g., if the LSP doesn't respond to it.
+	///
+	/// A response the LSP sends later on will be ignored. If the request was sent as part of a
+	/// comparison started via [`LSPS2ClientHandler::request_best_opening_params`], the comparison
+	/// proceeds as if the LSP didn't offer any parameters.
+	pub fn cancel_get_info_request(
+		&self, counterparty_node_id: &PublicKey, request_id: &RequestId,
+	) -> Result<(), APIError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let inner_state_lock =
+			outer_state_lock.get(counterparty_node_id).ok_or_else(|| APIError::APIMisuseError {
+				err: format!("No counterparty state for: {}", counterparty_node_id),
+			})?;
+		let mut peer_state = inner_state_lock.lock().unwrap();
+
+		if peer_state.pending_get_info_requests.remove(request_id).is_none() {
+			return Err(APIError::APIMisuseError {
+				err: format!("No pending get_info request found for: {:?}", request_id),
+			});
+		}
+
+		if let Some(comparison_id) = peer_state.comparison_get_info_requests.remove(request_id) {
+			self.update_comparison(&comparison_id, counterparty_node_id, Vec::new());
+		}
+		Ok(())
+	}
+
+	/// Stops tracking the JIT channel purchase initiated by the given `buy` request, e.g., if the
+	/// LSP doesn't respond to it or never opens the channel.
+	///
+	/// A response the LSP sends later on will be ignored, as will be any channel it requests to
+	/// open for the purchase. Payments registered via [`LSPS2ClientHandler::register_jit_payment`]
+	/// for the JIT channel will no longer be allowed to have a fee skimmed.
+	///
+	/// Note that this won't close the channel if one was already opened.
+	pub fn cancel_jit_channel_purchase(
+		&self, counterparty_node_id: &PublicKey, request_id: &RequestId,
+	) -> Result<(), APIError> {
+		let intercept_scid = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock = outer_state_lock.get(counterparty_node_id).ok_or_else(|| {
+				APIError::APIMisuseError {
+					err: format!("No counterparty state for: {}", counterparty_node_id),
+				}
+			})?;
+			let mut peer_state = inner_state_lock.lock().unwrap();
+
+			if peer_state.pending_buy_requests.remove(request_id).is_some() {
+				return Ok(());
+			}
+
+			let intercept_scid = peer_state
+				.jit_channels_by_scid
+				.iter()
+				.find(|(_, jit_channel)| jit_channel.request_id == *request_id)
+				.map(|(intercept_scid, _)| *intercept_scid)
+				.ok_or_else(|| APIError::APIMisuseError {
+					err: format!("No JIT channel purchase found for: {:?}", request_id),
+				})?;
+			peer_state.jit_channels_by_scid.remove(&intercept_scid);
+			peer_state.awaiting_channel_open_scids.retain(|scid| *scid != intercept_scid);
+			intercept_scid
+		};
+
+		self.jit_payment_hashes
+			.lock()
+			.unwrap()
+			.retain(|_, jit_payment| *jit_payment != (*counterparty_node_id, intercept_scid));
+		Ok(())
+	}
+
 	fn handle_get_info_response(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey, result: GetInfoResponse,
 	) -> Result<(), LightningError> {
@@ -194,7 +921,7 @@ where
 			Some(inner_state_lock) => {
 				let mut peer_state = inner_state_lock.lock().unwrap();
 
-				if !peer_state.pending_get_info_requests.remove(&request_id) {
+				if peer_state.pending_get_info_requests.remove(&request_id).is_none() {
 					return Err(LightningError {
 						err: format!(
 							"Received get_info response for an unknown request: {:?}",
@@ -204,11 +931,30 @@ where
 					});
 				}
 
+				// Don't hand out parameters we'd refuse to select anyways.
+				let now_secs = self.current_time();
+				let opening_fee_params_menu = result
+					.opening_fee_params_menu
+					.into_iter()
+					.filter(|params| self.config.check_opening_fee_params(params, now_secs).is_ok())
+					.collect::<Vec<_>>();
+
+				if let Some(comparison_id) =
+					peer_state.comparison_get_info_requests.remove(&request_id)
+				{
+					self.update_comparison(
+						&comparison_id,
+						counterparty_node_id,
+						opening_fee_params_menu,
+					);
+					return Ok(());
+				}
+
 				self.pending_events.enqueue(Event::LSPS2Client(
 					LSPS2ClientEvent::OpeningParametersReady {
 						request_id,
 						counterparty_node_id: *counterparty_node_id,
-						opening_fee_params_menu: result.opening_fee_params_menu,
+						opening_fee_params_menu,
 					},
 				));
 			},
@@ -234,7 +980,7 @@ where
 			Some(inner_state_lock) => {
 				let mut peer_state = inner_state_lock.lock().unwrap();
 
-				if !peer_state.pending_get_info_requests.remove(&request_id) {
+				if peer_state.pending_get_info_requests.remove(&request_id).is_none() {
 					return Err(LightningError {
 						err: format!(
 							"Received get_info error for an unknown request: {:?}",
@@ -244,14 +990,73 @@ where
 					});
 				}
 
+				if let Some(comparison_id) =
+					peer_state.comparison_get_info_requests.remove(&request_id)
+				{
+					self.update_comparison(&comparison_id, counterparty_node_id, Vec::new());
+				}
+
 				Ok(())
 			},
 			None => {
-				return Err(LightningError { err: format!("Received error response for a get_info request from an unknown counterparty ({:?})",counterparty_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)});
+				Err(LightningError { err: format!("Received error response for a get_info request from an unknown counterparty ({:?})",counterparty_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)})
 			},
 		}
 	}
 
+	fn update_comparison(
+		&self, comparison_id: &RequestId, counterparty_node_id: &PublicKey,
+		opening_fee_params_menu: Vec<OpeningFeeParams>,
+	) {
+		let mut pending_comparisons = self.pending_comparisons.lock().unwrap();
+		let comparison = match pending_comparisons.get_mut(comparison_id) {
+			Some(comparison) => comparison,
+			None => return,
+		};
+
+		comparison.pending_counterparties.remove(counterparty_node_id);
+		let now_secs = self.current_time();
+		for opening_fee_params in opening_fee_params_menu {
+			if let Some(opening_fee_msat) = now_secs.and_then(|now_secs| {
+				compute_applicable_opening_fee(
+					&opening_fee_params,
+					comparison.payment_size_msat,
+					now_secs,
+				)
+			}) {
+				comparison.quotes.push(LSPS2Quote {
+					node_id: *counterparty_node_id,
+					opening_fee_params,
+					opening_fee_msat,
+				});
+			}
+		}
+
+		if !comparison.pending_counterparties.is_empty() {
+			return;
+		}
+
+		if let Some(comparison) = pending_comparisons.remove(comparison_id) {
+			// Offers might have expired while we were waiting for the other LSPs.
+			let now_secs = self.current_time();
+			let best_quote = comparison
+				.quotes
+				.into_iter()
+				.filter(|quote| {
+					matches!(now_secs, Some(now_secs)
+						if !is_expired_opening_fee_params(&quote.opening_fee_params, now_secs))
+				})
+				.min_by_key(|quote| (quote.opening_fee_msat, quote.node_id));
+			self.pending_events.enqueue(Event::LSPS2Client(
+				LSPS2ClientEvent::BestOpeningParametersReady {
+					request_id: comparison_id.clone(),
+					payment_size_msat: comparison.payment_size_msat,
+					best_quote,
+				},
+			));
+		}
+	}
+
 	fn handle_buy_response(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey, result: BuyResponse,
 	) -> Result<(), LightningError> {
@@ -279,6 +1084,8 @@ where
 							payment_size_msat: jit_channel.payment_size_msat,
 						},
 					));
+					peer_state.jit_channels_by_scid.insert(intercept_scid, jit_channel);
+					peer_state.awaiting_channel_open_scids.push_back(intercept_scid);
 				} else {
 					return Err(LightningError {
 						err: format!(
@@ -317,11 +1124,21 @@ where
 
 				Ok(())
 			},
-			None => {
-				return Err(LightningError { err: format!("Received error response for a buy request from an unknown counterparty ({:?})", counterparty_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)});
-			},
+			None => Err(LightningError {
+				err: format!(
+					"Received error response for a buy request from an unknown counterparty ({:?})",
+					counterparty_node_id
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			}),
 		}
 	}
+
+	/// Records the time of the latest block, which is used to check the expiry of opening fee
+	/// params in no-std builds.
+	pub(crate) fn best_block_updated(&self, header: &bitcoin::block::Header, _height: u32) {
+		*self.latest_block_time.write().unwrap() = Some(header.time as u64);
+	}
 }
 
 impl<ES: Deref> ProtocolMessageHandler for LSPS2ClientHandler<ES>
@@ -361,4 +1178,337 @@ where
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+	use super::*;
+
+	use crate::lsps0::ser::{LSPSMessage, ResponseError, MAX_LSPS_MESSAGE_SIZE};
+	use crate::prelude::ToString;
+	use crate::tests::utils::TestEntropy;
+
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+	use chrono::{TimeZone, Utc};
+
+	fn opening_fee_params(min_fee_msat: u64, valid_until_secs: i64) -> OpeningFeeParams {
+		OpeningFeeParams {
+			min_fee_msat,
+			proportional: 1_000,
+			valid_until: Utc.timestamp_opt(valid_until_secs, 0).unwrap(),
+			min_lifetime: 144,
+			max_client_to_self_delay: 128,
+			min_payment_size_msat: 10_000,
+			max_payment_size_msat: 100_000_000,
+			promise: "ignore".to_string(),
+		}
+	}
+
+	#[test]
+	fn filters_and_refuses_unacceptable_opening_fee_params() {
+		let pending_messages = Arc::new(MessageQueue::new());
+		let pending_events = Arc::new(EventQueue::new());
+		let config = LSPS2ClientConfig {
+			max_min_fee_msat: Some(10_000),
+			max_proportional: Some(10_000),
+			min_lifetime: Some(100),
+			max_client_to_self_delay: Some(2016),
+			min_validity_secs: Some(600),
+		};
+		let client_handler = LSPS2ClientHandler::new(
+			Arc::new(TestEntropy {}),
+			Arc::clone(&pending_messages),
+			Arc::clone(&pending_events),
+			config,
+		);
+		let secp_ctx = Secp256k1::new();
+		let node_id =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[1; 32]).unwrap());
+
+		let acceptable = opening_fee_params(5_000, 4_000_000_000);
+		let mut unacceptable = Vec::new();
+		unacceptable.push(opening_fee_params(5_000, 0));
+		let mut params = acceptable.clone();
+		params.min_payment_size_msat = params.max_payment_size_msat + 1;
+		unacceptable.push(params);
+		unacceptable.push(opening_fee_params(50_000, 4_000_000_000));
+		let mut params = acceptable.clone();
+		params.proportional = 500_000;
+		unacceptable.push(params);
+		let mut params = acceptable.clone();
+		params.min_lifetime = 6;
+		unacceptable.push(params);
+		let mut params = acceptable.clone();
+		params.max_client_to_self_delay = 4032;
+		unacceptable.push(params);
+		#[cfg(feature = "std")]
+		{
+			let now = std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.unwrap()
+				.as_secs() as i64;
+			unacceptable.push(opening_fee_params(5_000, now + 60));
+		}
+
+		for params in &unacceptable {
+			assert!(client_handler.select_opening_params(node_id, None, params.clone()).is_err());
+		}
+		assert!(client_handler
+			.select_opening_params(node_id, Some(1_000), acceptable.clone())
+			.is_err());
+		assert!(pending_messages.get_and_clear_pending_msgs().is_empty());
+
+		let request_id = client_handler.request_opening_params(node_id, None).unwrap();
+		pending_messages.get_and_clear_pending_msgs();
+		let mut opening_fee_params_menu = unacceptable;
+		opening_fee_params_menu.push(acceptable.clone());
+		let response = LSPS2Response::GetInfo(GetInfoResponse { opening_fee_params_menu });
+		let msg = LSPS2Message::Response(request_id, response);
+		client_handler.handle_message(msg, &node_id).unwrap();
+
+		match pending_events.get_and_clear_pending_events().pop() {
+			Some(Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+				opening_fee_params_menu,
+				..
+			})) => {
+				assert_eq!(opening_fee_params_menu, vec![acceptable.clone()]);
+			},
+			event => panic!("Unexpected event: {:?}", event),
+		}
+		assert!(client_handler.select_opening_params(node_id, Some(1_000_000), acceptable).is_ok());
+	}
+
+	#[test]
+	fn checks_opening_fee_params_expiry_against_a_known_time() {
+		let config = LSPS2ClientConfig { min_validity_secs: Some(600), ..Default::default() };
+		let params = opening_fee_params(5_000, 1_700_000_000);
+
+		// Without knowing the time, e.g., before seeing a block in no-std builds, we can't tell
+		// whether the params expired.
+		assert!(config.check_opening_fee_params(&params, None).is_err());
+
+		assert!(config.check_opening_fee_params(&params, Some(1_699_999_000)).is_ok());
+		assert!(config.check_opening_fee_params(&params, Some(1_699_999_500)).is_err());
+		assert!(config.check_opening_fee_params(&params, Some(1_700_000_001)).is_err());
+	}
+
+	#[test]
+	fn request_best_opening_params_selects_cheapest_valid_offer() {
+		let pending_messages = Arc::new(MessageQueue::new());
+		let pending_events = Arc::new(EventQueue::new());
+		let client_handler = LSPS2ClientHandler::new(
+			Arc::new(TestEntropy {}),
+			Arc::clone(&pending_messages),
+			Arc::clone(&pending_events),
+			LSPS2ClientConfig::default(),
+		);
+
+		let secp_ctx = Secp256k1::new();
+		let node_id = |byte| {
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap())
+		};
+		let (node_a, node_b, node_c) = (node_id(1), node_id(2), node_id(3));
+
+		assert!(client_handler.request_best_opening_params(&[], 1_000_000, None).is_err());
+
+		let comparison_id = client_handler
+			.request_best_opening_params(&[node_a, node_b, node_c, node_a], 1_000_000, None)
+			.unwrap();
+
+		let mut request_ids = HashMap::new();
+		for (node_id, msg) in pending_messages.get_and_clear_pending_msgs() {
+			match msg {
+				LSPSMessage::LSPS2(LSPS2Message::Request(request_id, LSPS2Request::GetInfo(_))) => {
+					assert!(request_ids.insert(node_id, request_id).is_none());
+				},
+				_ => panic!("Unexpected message"),
+			}
+		}
+		assert_eq!(request_ids.len(), 3);
+
+		// Node A offers the cheapest parameters, but they expired, or don't cover the payment.
+		let expired = opening_fee_params(100, 0);
+		let mut out_of_range = opening_fee_params(100, 4_000_000_000);
+		out_of_range.max_payment_size_msat = 100_000;
+		let response = LSPS2Response::GetInfo(GetInfoResponse {
+			opening_fee_params_menu: vec![
+				expired,
+				out_of_range,
+				opening_fee_params(5_000, 4_000_000_000),
+			],
+		});
+		let msg = LSPS2Message::Response(request_ids[&node_a].clone(), response);
+		client_handler.handle_message(msg, &node_a).unwrap();
+
+		let response = LSPS2Response::GetInfo(GetInfoResponse {
+			opening_fee_params_menu: vec![opening_fee_params(2_000, 4_000_000_000)],
+		});
+		let msg = LSPS2Message::Response(request_ids[&node_b].clone(), response);
+		client_handler.handle_message(msg, &node_b).unwrap();
+
+		// We wait for all LSPs to respond.
+		assert!(pending_events.get_and_clear_pending_events().is_empty());
+
+		let error =
+			ResponseError { code: 1, message: "Unrecognized token".to_string(), data: None };
+		let msg = LSPS2Message::Response(
+			request_ids[&node_c].clone(),
+			LSPS2Response::GetInfoError(error),
+		);
+		client_handler.handle_message(msg, &node_c).unwrap();
+
+		let events = pending_events.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		match &events[0] {
+			Event::LSPS2Client(LSPS2ClientEvent::BestOpeningParametersReady {
+				request_id,
+				payment_size_msat,
+				best_quote: Some(best_quote),
+			}) => {
+				assert_eq!(*request_id, comparison_id);
+				assert_eq!(*payment_size_msat, 1_000_000);
+				assert_eq!(best_quote.node_id, node_b);
+				assert_eq!(best_quote.opening_fee_msat, 2_000);
+				assert_eq!(best_quote.opening_fee_params, opening_fee_params(2_000, 4_000_000_000));
+			},
+			event => panic!("Unexpected event: {:?}", event),
+		}
+	}
+
+	#[test]
+	fn rejects_requests_too_large_for_a_message() {
+		let pending_messages = Arc::new(MessageQueue::new());
+		let client_handler = LSPS2ClientHandler::new(
+			Arc::new(TestEntropy {}),
+			Arc::clone(&pending_messages),
+			Arc::new(EventQueue::new()),
+			LSPS2ClientConfig::default(),
+		);
+
+		let secp_ctx = Secp256k1::new();
+		let node_id =
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[1; 32]).unwrap());
+
+		let token = Some("a".repeat(MAX_LSPS_MESSAGE_SIZE));
+		assert!(client_handler.request_opening_params(node_id, token.clone()).is_err());
+		assert!(client_handler.request_best_opening_params(&[node_id], 1_000_000, token).is_err());
+
+		let mut params = opening_fee_params(5_000, 4_000_000_000);
+		params.promise = "a".repeat(MAX_LSPS_MESSAGE_SIZE);
+		assert!(client_handler.select_opening_params(node_id, Some(1_000_000), params).is_err());
+
+		// Nothing was tracked or sent.
+		assert!(client_handler.list_pending_get_info_requests().is_empty());
+		assert!(client_handler.list_jit_channel_purchases().is_empty());
+		assert!(pending_messages.get_and_clear_pending_msgs().is_empty());
+	}
+
+	#[test]
+	fn lists_and_cancels_pending_requests() {
+		let pending_messages = Arc::new(MessageQueue::new());
+		let pending_events = Arc::new(EventQueue::new());
+		let client_handler = LSPS2ClientHandler::new(
+			Arc::new(TestEntropy {}),
+			Arc::clone(&pending_messages),
+			Arc::clone(&pending_events),
+			LSPS2ClientConfig::default(),
+		);
+
+		let secp_ctx = Secp256k1::new();
+		let node_id = |byte| {
+			PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap())
+		};
+		let (node_a, node_b, node_c) = (node_id(1), node_id(2), node_id(3));
+
+		let get_info_request_id = client_handler.request_opening_params(node_a, None).unwrap();
+		let comparison_id =
+			client_handler.request_best_opening_params(&[node_b, node_c], 1_000_000, None).unwrap();
+		let params = opening_fee_params(5_000, 4_000_000_000);
+		let buy_request_id =
+			client_handler.select_opening_params(node_a, Some(1_000_000), params.clone()).unwrap();
+		pending_messages.get_and_clear_pending_msgs();
+
+		assert_eq!(client_handler.list_pending_get_info_requests().len(), 3);
+		let pending_get_info_requests =
+			client_handler.list_pending_get_info_requests_with_counterparty(&node_b);
+		assert_eq!(pending_get_info_requests.len(), 1);
+		assert_eq!(pending_get_info_requests[0].comparison_id, Some(comparison_id.clone()));
+		#[cfg(feature = "std")]
+		assert!(pending_get_info_requests[0].age_secs.is_some());
+
+		let purchases = client_handler.list_jit_channel_purchases();
+		assert_eq!(purchases.len(), 1);
+		assert_eq!(
+			purchases[0],
+			JITChannelPurchaseDetails {
+				request_id: buy_request_id.clone(),
+				counterparty_node_id: node_a,
+				intercept_scid: None,
+				state: JITChannelPurchaseState::PendingBuy,
+				payment_size_msat: Some(1_000_000),
+				opening_fee_params: params.clone(),
+				age_secs: purchases[0].age_secs,
+			}
+		);
+
+		// Late responses to cancelled requests are ignored.
+		client_handler.cancel_get_info_request(&node_a, &get_info_request_id).unwrap();
+		assert!(client_handler.cancel_get_info_request(&node_a, &get_info_request_id).is_err());
+		assert!(client_handler
+			.list_pending_get_info_requests_with_counterparty(&node_a)
+			.is_empty());
+		let response =
+			LSPS2Response::GetInfo(GetInfoResponse { opening_fee_params_menu: vec![params] });
+		let msg = LSPS2Message::Response(get_info_request_id, response);
+		assert!(client_handler.handle_message(msg, &node_a).is_err());
+		assert!(pending_events.get_and_clear_pending_events().is_empty());
+
+		let intercept_scid = 42 << 40;
+		let response = LSPS2Response::Buy(BuyResponse {
+			jit_channel_scid: intercept_scid.into(),
+			lsp_cltv_expiry_delta: 144,
+			client_trusts_lsp: false,
+		});
+		let msg = LSPS2Message::Response(buy_request_id.clone(), response);
+		client_handler.handle_message(msg, &node_a).unwrap();
+		pending_events.get_and_clear_pending_events();
+
+		let purchases = client_handler.list_jit_channel_purchases_with_counterparty(&node_a);
+		assert_eq!(purchases.len(), 1);
+		assert_eq!(purchases[0].intercept_scid, Some(intercept_scid));
+		assert_eq!(purchases[0].state, JITChannelPurchaseState::AwaitingChannelOpen);
+
+		let payment_hash = PaymentHash([1; 32]);
+		client_handler.register_jit_payment(&node_a, intercept_scid, payment_hash).unwrap();
+		assert_eq!(
+			client_handler.check_skimmed_fee(&payment_hash, 990_000, 10_000),
+			JITPaymentAction::Fail { max_opening_fee_msat: 5_000, skimmed_fee_msat: 10_000 }
+		);
+
+		client_handler.cancel_jit_channel_purchase(&node_a, &buy_request_id).unwrap();
+		assert!(client_handler.cancel_jit_channel_purchase(&node_a, &buy_request_id).is_err());
+		assert!(client_handler.list_jit_channel_purchases().is_empty());
+		assert!(client_handler.jit_channel_open_requested(&node_a, 1_000_000).is_none());
+		assert_eq!(
+			client_handler.check_skimmed_fee(&payment_hash, 995_000, 5_000),
+			JITPaymentAction::Fail { max_opening_fee_msat: 0, skimmed_fee_msat: 5_000 }
+		);
+
+		// Cancelling a comparison's requests completes it without any offers.
+		for node_id in [node_b, node_c] {
+			assert!(pending_events.get_and_clear_pending_events().is_empty());
+			let request_id = client_handler
+				.list_pending_get_info_requests_with_counterparty(&node_id)
+				.pop()
+				.unwrap()
+				.request_id;
+			client_handler.cancel_get_info_request(&node_id, &request_id).unwrap();
+		}
+		match pending_events.get_and_clear_pending_events().pop() {
+			Some(Event::LSPS2Client(LSPS2ClientEvent::BestOpeningParametersReady {
+				request_id,
+				best_quote: None,
+				..
+			})) => assert_eq!(request_id, comparison_id),
+			event => panic!("Unexpected event: {:?}", event),
+		}
+		assert!(client_handler.list_pending_get_info_requests().is_empty());
+	}
+}
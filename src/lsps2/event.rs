@@ -10,9 +10,12 @@
 //! Contains LSPS2 event types
 
 use super::msgs::OpeningFeeParams;
+use crate::lsps0::discovery::LSPS2Quote;
 use crate::lsps0::ser::RequestId;
 use crate::prelude::{String, Vec};
 
+use lightning::ln::{ChannelId, PaymentHash};
+
 use bitcoin::secp256k1::PublicKey;
 
 /// An event which an LSPS2 client should take some action in response to.
@@ -36,8 +39,33 @@ pub enum LSPS2ClientEvent {
 		counterparty_node_id: PublicKey,
 		/// The menu of fee parameters the LSP is offering at this time.
 		/// You must select one of these if you wish to proceed.
+		///
+		/// Parameters violating the limits set in [`LSPS2ClientConfig`] have been filtered out.
+		///
+		/// [`LSPS2ClientConfig`]: crate::lsps2::client::LSPS2ClientConfig
 		opening_fee_params_menu: Vec<OpeningFeeParams>,
 	},
+	/// The result of comparing the opening fee parameters offered by several LSPs, as requested
+	/// via [`LSPS2ClientHandler::request_best_opening_params`].
+	///
+	/// If a `best_quote` was found, you may call [`LSPS2ClientHandler::select_opening_params`] with
+	/// its `node_id` and `opening_fee_params` if you wish to proceed opening a channel.
+	///
+	/// [`LSPS2ClientHandler::request_best_opening_params`]: crate::lsps2::client::LSPS2ClientHandler::request_best_opening_params
+	/// [`LSPS2ClientHandler::select_opening_params`]: crate::lsps2::client::LSPS2ClientHandler::select_opening_params
+	BestOpeningParametersReady {
+		/// The identifier returned by [`LSPS2ClientHandler::request_best_opening_params`].
+		///
+		/// This can be used to track which request this event corresponds to.
+		///
+		/// [`LSPS2ClientHandler::request_best_opening_params`]: crate::lsps2::client::LSPS2ClientHandler::request_best_opening_params
+		request_id: RequestId,
+		/// The payment size the opening fees were computed for.
+		payment_size_msat: u64,
+		/// The offer with the lowest opening fee, or [`Option::None`] if none of the LSPs offered
+		/// valid parameters applicable to the payment size.
+		best_quote: Option<LSPS2Quote>,
+	},
 	/// Provides the necessary information to generate a payable invoice that then may be given to
 	/// the payer.
 	///
@@ -60,6 +88,27 @@ pub enum LSPS2ClientEvent {
 		/// The initial payment size you specified.
 		payment_size_msat: Option<u64>,
 	},
+	/// A JIT channel we purchased was opened and is ready to be used.
+	///
+	/// The LSP will now forward the payment that triggered the channel open, minus the opening
+	/// fee, which you should check via [`LSPS2ClientHandler::check_skimmed_fee`].
+	///
+	/// [`LSPS2ClientHandler::check_skimmed_fee`]: crate::lsps2::client::LSPS2ClientHandler::check_skimmed_fee
+	JITChannelOpened {
+		/// The identifier of the `buy` request the channel was purchased with, as returned by
+		/// [`LSPS2ClientHandler::select_opening_params`].
+		///
+		/// [`LSPS2ClientHandler::select_opening_params`]: crate::lsps2::client::LSPS2ClientHandler::select_opening_params
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The intercept short channel id the channel was purchased for.
+		intercept_scid: u64,
+		/// The id of the opened channel.
+		channel_id: ChannelId,
+		/// The `user_channel_id` the channel was accepted with.
+		user_channel_id: u128,
+	},
 }
 
 /// An event which an LSPS2 server should take some action in response to.
@@ -122,4 +171,67 @@ pub enum LSPS2ServiceEvent {
 		/// The intercept short channel id to use in the route hint.
 		intercept_scid: u64,
 	},
+	/// A JIT channel we opened in response to [`LSPS2ServiceEvent::OpenChannel`] became ready, and
+	/// we started forwarding the initial payment over it.
+	///
+	/// Note that no such event is generated if the initial payment is forwarded over an existing
+	/// channel, see [`LSPS2ServiceConfig::existing_channel_policy`].
+	///
+	/// [`LSPS2ServiceConfig::existing_channel_policy`]: crate::lsps2::service::LSPS2ServiceConfig::existing_channel_policy
+	JITChannelOpened {
+		/// The node id of the client the JIT channel was sold to.
+		counterparty_node_id: PublicKey,
+		/// The intercept short channel id the client used in the route hint.
+		intercept_scid: u64,
+		/// The `user_channel_id` given to [`LSPS2ServiceHandler::invoice_parameters_generated`].
+		///
+		/// [`LSPS2ServiceHandler::invoice_parameters_generated`]: crate::lsps2::service::LSPS2ServiceHandler::invoice_parameters_generated
+		user_channel_id: u128,
+		/// The id of the opened channel.
+		channel_id: ChannelId,
+	},
+	/// A payment paying the JIT channel opening fee was successfully forwarded to the client,
+	/// completing the sale of the JIT channel.
+	///
+	/// Any further HTLCs for the intercept scid will be forwarded without skimming additional fees.
+	/// If the opening fee was waived as the payment was forwarded over an existing channel,
+	/// `amount_msat` will be zero, and the event is generated once we handed the payment to the
+	/// `ChannelManager` for forwarding, as its settlement can't be told apart from other payments
+	/// over the channel.
+	OpeningFeeCollected {
+		/// The node id of the client the JIT channel was sold to.
+		counterparty_node_id: PublicKey,
+		/// The intercept short channel id the client used in the route hint.
+		intercept_scid: u64,
+		/// The `user_channel_id` given to [`LSPS2ServiceHandler::invoice_parameters_generated`].
+		///
+		/// [`LSPS2ServiceHandler::invoice_parameters_generated`]: crate::lsps2::service::LSPS2ServiceHandler::invoice_parameters_generated
+		user_channel_id: u128,
+		/// The id of the channel the payment was forwarded over.
+		channel_id: ChannelId,
+		/// The hash of the payment the opening fee was skimmed from.
+		payment_hash: PaymentHash,
+		/// The opening fee we collected, i.e., the sum of the `skimmed_fee_msat` reported for the
+		/// payment's HTLCs via [`LSPS2ServiceHandler::payment_forwarded`].
+		///
+		/// [`LSPS2ServiceHandler::payment_forwarded`]: crate::lsps2::service::LSPS2ServiceHandler::payment_forwarded
+		amount_msat: u64,
+	},
+	/// A JIT channel failed before the JIT channel fee was collected, e.g., as the channel couldn't
+	/// be opened or closed before becoming ready.
+	///
+	/// Any HTLCs we held for the JIT channel were failed back, and its intercept scid will no
+	/// longer be recognized.
+	JITChannelFailed {
+		/// The node id of the client the JIT channel was sold to.
+		counterparty_node_id: PublicKey,
+		/// The intercept short channel id the client used in the route hint.
+		intercept_scid: u64,
+		/// The `user_channel_id` given to [`LSPS2ServiceHandler::invoice_parameters_generated`].
+		///
+		/// [`LSPS2ServiceHandler::invoice_parameters_generated`]: crate::lsps2::service::LSPS2ServiceHandler::invoice_parameters_generated
+		user_channel_id: u128,
+		/// A human-readable description of why the JIT channel failed.
+		reason: String,
+	},
 }
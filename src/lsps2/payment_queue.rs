@@ -1,13 +1,31 @@
-use crate::prelude::Vec;
+use crate::prelude::{hash_map, BTreeMap, HashMap, Vec};
 use lightning::ln::channelmanager::InterceptId;
 use lightning::ln::PaymentHash;
 
 /// Holds payments with the corresponding HTLCs until it is possible to pay the fee.
 /// When the fee is successfully paid with a forwarded payment, the queue should be consumed and the
 /// remaining payments forwarded.
+///
+/// Payments are indexed by their payment hash and keep a running total of their HTLCs, while
+/// being handled in the order their first HTLC was queued in.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub(crate) struct PaymentQueue {
-	payments: Vec<(PaymentHash, Vec<InterceptedHTLC>)>,
+	payments: HashMap<PaymentHash, QueuedPayment>,
+	// The hashes of the queued payments, keyed by the sequence number of the respective payment.
+	payment_order: BTreeMap<u64, PaymentHash>,
+	next_sequence_number: u64,
+	// The maximum number of HTLCs we queue for a single payment.
+	max_htlcs_per_payment: usize,
+	// The maximum number of distinct payments we queue at a time.
+	max_payments: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct QueuedPayment {
+	htlcs: Vec<InterceptedHTLC>,
+	total_expected_outbound_amount_msat: u64,
+	// The number of timer ticks that occurred since the first HTLC was queued.
+	ticks_elapsed: u32,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -18,39 +36,120 @@ pub(crate) struct InterceptedHTLC {
 }
 
 impl PaymentQueue {
-	pub(crate) fn new() -> PaymentQueue {
-		PaymentQueue { payments: Vec::new() }
+	pub(crate) fn new(max_htlcs_per_payment: usize, max_payments: usize) -> PaymentQueue {
+		PaymentQueue {
+			payments: HashMap::new(),
+			payment_order: BTreeMap::new(),
+			next_sequence_number: 0,
+			max_htlcs_per_payment,
+			max_payments,
+		}
 	}
 
-	pub(crate) fn add_htlc(&mut self, new_htlc: InterceptedHTLC) -> (u64, usize) {
-		let payment = self.payments.iter_mut().find(|(p, _)| p == &new_htlc.payment_hash);
-		if let Some((payment_hash, htlcs)) = payment {
-			// HTLCs within a payment should have the same payment hash.
-			debug_assert!(htlcs.iter().all(|htlc| htlc.payment_hash == *payment_hash));
-			// The given HTLC should not already be present.
-			debug_assert!(htlcs.iter().all(|htlc| htlc.intercept_id != new_htlc.intercept_id));
-			htlcs.push(new_htlc);
-			let total_expected_outbound_amount_msat =
-				htlcs.iter().map(|htlc| htlc.expected_outbound_amount_msat).sum();
-			(total_expected_outbound_amount_msat, htlcs.len())
-		} else {
-			let expected_outbound_amount_msat = new_htlc.expected_outbound_amount_msat;
-			self.payments.push((new_htlc.payment_hash, vec![new_htlc]));
-			(expected_outbound_amount_msat, 1)
+	/// Queues the given HTLC, returning the total expected outbound amount and the number of HTLCs
+	/// of its payment.
+	///
+	/// Returns `None` if queueing the HTLC would exceed the maximum number of HTLCs per payment or
+	/// the maximum number of payments, in which case the HTLC is not queued.
+	pub(crate) fn add_htlc(&mut self, new_htlc: InterceptedHTLC) -> Option<(u64, usize)> {
+		match self.payments.entry(new_htlc.payment_hash) {
+			hash_map::Entry::Occupied(mut entry) => {
+				let payment = entry.get_mut();
+				// HTLCs within a payment should have the same payment hash.
+				debug_assert!(payment
+					.htlcs
+					.iter()
+					.all(|htlc| htlc.payment_hash == new_htlc.payment_hash));
+				// The given HTLC should not already be present.
+				debug_assert!(payment
+					.htlcs
+					.iter()
+					.all(|htlc| htlc.intercept_id != new_htlc.intercept_id));
+				if payment.htlcs.len() >= self.max_htlcs_per_payment {
+					return None;
+				}
+				payment.htlcs.push(new_htlc);
+				payment.total_expected_outbound_amount_msat = payment
+					.total_expected_outbound_amount_msat
+					.saturating_add(new_htlc.expected_outbound_amount_msat);
+				Some((payment.total_expected_outbound_amount_msat, payment.htlcs.len()))
+			},
+			hash_map::Entry::Vacant(entry) => {
+				if self.payment_order.len() >= self.max_payments {
+					return None;
+				}
+				let sequence_number = self.next_sequence_number;
+				self.next_sequence_number += 1;
+				self.payment_order.insert(sequence_number, new_htlc.payment_hash);
+				entry.insert(QueuedPayment {
+					htlcs: vec![new_htlc],
+					total_expected_outbound_amount_msat: new_htlc.expected_outbound_amount_msat,
+					ticks_elapsed: 0,
+				});
+				Some((new_htlc.expected_outbound_amount_msat, 1))
+			},
 		}
 	}
 
+	/// Removes and returns the first queued payment whose HTLCs sum up to at least `amount_msat`.
+	///
+	/// Note that this scans the queued payments linearly, i.e., takes time proportional to the
+	/// maximum number of payments in the worst case.
 	pub(crate) fn pop_greater_than_msat(
 		&mut self, amount_msat: u64,
 	) -> Option<(PaymentHash, Vec<InterceptedHTLC>)> {
-		let position = self.payments.iter().position(|(_payment_hash, htlcs)| {
-			htlcs.iter().map(|htlc| htlc.expected_outbound_amount_msat).sum::<u64>() >= amount_msat
+		let payments = &self.payments;
+		let sequence_number = self
+			.payment_order
+			.iter()
+			.find(|(_, payment_hash)| {
+				payments[*payment_hash].total_expected_outbound_amount_msat >= amount_msat
+			})
+			.map(|(sequence_number, _)| *sequence_number)?;
+		let payment_hash = self.payment_order.remove(&sequence_number)?;
+		let payment = self.payments.remove(&payment_hash)?;
+		Some((payment_hash, payment.htlcs))
+	}
+
+	/// Ages all queued payments by one tick, removing and returning the HTLCs of those that have
+	/// been queued for `timeout_ticks`.
+	pub(crate) fn timer_tick(&mut self, timeout_ticks: u32) -> Vec<InterceptedHTLC> {
+		let mut timed_out_htlcs = Vec::new();
+		let payments = &mut self.payments;
+		self.payment_order.retain(|_, payment_hash| {
+			let payment = payments.get_mut(payment_hash).expect("Queued payments are indexed");
+			payment.ticks_elapsed += 1;
+			if payment.ticks_elapsed >= timeout_ticks {
+				if let Some(mut payment) = payments.remove(payment_hash) {
+					timed_out_htlcs.append(&mut payment.htlcs);
+				}
+				false
+			} else {
+				true
+			}
 		});
-		position.map(|position| self.payments.remove(position))
+		timed_out_htlcs
+	}
+
+	/// Returns the number of queued HTLCs and their total expected outbound amount.
+	pub(crate) fn queued_htlcs(&self) -> (usize, u64) {
+		self.payments.values().fold((0, 0), |(num_htlcs, total_msat), payment| {
+			(
+				num_htlcs + payment.htlcs.len(),
+				total_msat.saturating_add(payment.total_expected_outbound_amount_msat),
+			)
+		})
 	}
 
 	pub(crate) fn clear(&mut self) -> Vec<InterceptedHTLC> {
-		self.payments.drain(..).map(|(_k, v)| v).flatten().collect()
+		let payment_order = core::mem::take(&mut self.payment_order);
+		let htlcs = payment_order
+			.into_values()
+			.filter_map(|payment_hash| self.payments.remove(&payment_hash))
+			.flat_map(|payment| payment.htlcs)
+			.collect();
+		debug_assert!(self.payments.is_empty());
+		htlcs
 	}
 }
 
@@ -58,16 +157,21 @@ impl PaymentQueue {
 mod tests {
 	use super::*;
 
+	use proptest::prelude::*;
+
+	const MAX_HTLCS_PER_PAYMENT: usize = 100;
+	const MAX_PAYMENTS: usize = 50;
+
 	#[test]
 	fn test_payment_queue() {
-		let mut payment_queue = PaymentQueue::new();
+		let mut payment_queue = PaymentQueue::new(MAX_HTLCS_PER_PAYMENT, MAX_PAYMENTS);
 		assert_eq!(
 			payment_queue.add_htlc(InterceptedHTLC {
 				intercept_id: InterceptId([0; 32]),
 				expected_outbound_amount_msat: 200_000_000,
 				payment_hash: PaymentHash([100; 32]),
 			}),
-			(200_000_000, 1),
+			Some((200_000_000, 1)),
 		);
 		assert_eq!(payment_queue.pop_greater_than_msat(500_000_000), None);
 
@@ -77,7 +181,7 @@ mod tests {
 				expected_outbound_amount_msat: 300_000_000,
 				payment_hash: PaymentHash([101; 32]),
 			}),
-			(300_000_000, 1),
+			Some((300_000_000, 1)),
 		);
 		assert_eq!(payment_queue.pop_greater_than_msat(500_000_000), None);
 
@@ -87,7 +191,7 @@ mod tests {
 				expected_outbound_amount_msat: 300_000_000,
 				payment_hash: PaymentHash([100; 32]),
 			}),
-			(500_000_000, 2),
+			Some((500_000_000, 2)),
 		);
 		assert_eq!(
 			payment_queue.pop_greater_than_msat(500_000_000),
@@ -116,4 +220,165 @@ mod tests {
 			}]
 		);
 	}
+
+	#[test]
+	fn test_payment_queue_timer_tick() {
+		let mut payment_queue = PaymentQueue::new(MAX_HTLCS_PER_PAYMENT, MAX_PAYMENTS);
+		let htlc_a = InterceptedHTLC {
+			intercept_id: InterceptId([0; 32]),
+			expected_outbound_amount_msat: 200_000_000,
+			payment_hash: PaymentHash([100; 32]),
+		};
+		let htlc_b = InterceptedHTLC {
+			intercept_id: InterceptId([1; 32]),
+			expected_outbound_amount_msat: 300_000_000,
+			payment_hash: PaymentHash([101; 32]),
+		};
+		payment_queue.add_htlc(htlc_a);
+		assert!(payment_queue.timer_tick(2).is_empty());
+
+		// Payment A times out, while payment B, queued a tick later, doesn't yet.
+		payment_queue.add_htlc(htlc_b);
+		assert_eq!(payment_queue.timer_tick(2), vec![htlc_a]);
+		assert_eq!(payment_queue.timer_tick(2), vec![htlc_b]);
+		assert!(payment_queue.clear().is_empty());
+	}
+
+	#[test]
+	fn test_payment_queue_limits() {
+		let mut payment_queue = PaymentQueue::new(MAX_HTLCS_PER_PAYMENT, MAX_PAYMENTS);
+		let htlc = |intercept_id: usize, payment_hash: usize| InterceptedHTLC {
+			intercept_id: InterceptId([intercept_id as u8; 32]),
+			expected_outbound_amount_msat: 1_000,
+			payment_hash: PaymentHash([payment_hash as u8; 32]),
+		};
+		for i in 0..MAX_HTLCS_PER_PAYMENT {
+			assert_eq!(payment_queue.add_htlc(htlc(i, 0)), Some((1_000 * (i as u64 + 1), i + 1)));
+		}
+		assert_eq!(payment_queue.add_htlc(htlc(MAX_HTLCS_PER_PAYMENT, 0)), None);
+
+		for i in 1..MAX_PAYMENTS {
+			assert_eq!(payment_queue.add_htlc(htlc(0, i)), Some((1_000, 1)));
+		}
+		assert_eq!(payment_queue.add_htlc(htlc(0, MAX_PAYMENTS)), None);
+
+		// Once a payment left the queue, another one can take its place.
+		assert!(payment_queue.pop_greater_than_msat(2_000).is_some());
+		assert_eq!(payment_queue.add_htlc(htlc(0, MAX_PAYMENTS)), Some((1_000, 1)));
+	}
+
+	// The straightforward queue the indexed `PaymentQueue` is supposed to behave like.
+	#[derive(Default)]
+	struct ReferencePaymentQueue {
+		payments: Vec<(PaymentHash, Vec<InterceptedHTLC>, u32)>,
+	}
+
+	impl ReferencePaymentQueue {
+		fn add_htlc(&mut self, new_htlc: InterceptedHTLC) -> Option<(u64, usize)> {
+			let num_payments = self.payments.len();
+			match self.payments.iter_mut().find(|(hash, _, _)| *hash == new_htlc.payment_hash) {
+				Some((_, htlcs, _)) => {
+					if htlcs.len() >= MAX_HTLCS_PER_PAYMENT {
+						return None;
+					}
+					htlcs.push(new_htlc);
+					let total_msat =
+						htlcs.iter().map(|htlc| htlc.expected_outbound_amount_msat).sum();
+					Some((total_msat, htlcs.len()))
+				},
+				None => {
+					if num_payments >= MAX_PAYMENTS {
+						return None;
+					}
+					self.payments.push((new_htlc.payment_hash, vec![new_htlc], 0));
+					Some((new_htlc.expected_outbound_amount_msat, 1))
+				},
+			}
+		}
+
+		fn pop_greater_than_msat(
+			&mut self, amount_msat: u64,
+		) -> Option<(PaymentHash, Vec<InterceptedHTLC>)> {
+			let position = self.payments.iter().position(|(_, htlcs, _)| {
+				htlcs.iter().map(|htlc| htlc.expected_outbound_amount_msat).sum::<u64>()
+					>= amount_msat
+			})?;
+			let (payment_hash, htlcs, _) = self.payments.remove(position);
+			Some((payment_hash, htlcs))
+		}
+
+		fn timer_tick(&mut self, timeout_ticks: u32) -> Vec<InterceptedHTLC> {
+			let mut timed_out_htlcs = Vec::new();
+			self.payments.retain_mut(|(_, htlcs, ticks_elapsed)| {
+				*ticks_elapsed += 1;
+				if *ticks_elapsed >= timeout_ticks {
+					timed_out_htlcs.append(htlcs);
+					false
+				} else {
+					true
+				}
+			});
+			timed_out_htlcs
+		}
+
+		fn clear(&mut self) -> Vec<InterceptedHTLC> {
+			self.payments.drain(..).flat_map(|(_, htlcs, _)| htlcs).collect()
+		}
+	}
+
+	#[derive(Debug, Clone)]
+	enum QueueOperation {
+		AddHTLC { payment_hash: u8, amount_msat: u64 },
+		Pop { amount_msat: u64 },
+		TimerTick,
+		Clear,
+	}
+
+	fn arb_queue_operation() -> impl Strategy<Value = QueueOperation> {
+		prop_oneof![
+			8 => (0u8..60, 1u64..1_000_000).prop_map(|(payment_hash, amount_msat)| {
+				QueueOperation::AddHTLC { payment_hash, amount_msat }
+			}),
+			3 => (1u64..5_000_000).prop_map(|amount_msat| QueueOperation::Pop { amount_msat }),
+			1 => Just(QueueOperation::TimerTick),
+			1 => Just(QueueOperation::Clear),
+		]
+	}
+
+	proptest! {
+		#[test]
+		fn proptest_payment_queue_matches_reference(
+			operations in proptest::collection::vec(arb_queue_operation(), 0..500)
+		) {
+			let mut payment_queue = PaymentQueue::new(MAX_HTLCS_PER_PAYMENT, MAX_PAYMENTS);
+			let mut reference_queue = ReferencePaymentQueue::default();
+			for (i, operation) in operations.into_iter().enumerate() {
+				match operation {
+					QueueOperation::AddHTLC { payment_hash, amount_msat } => {
+						let mut intercept_id = [0; 32];
+						intercept_id[..8].copy_from_slice(&(i as u64).to_be_bytes());
+						let htlc = InterceptedHTLC {
+							intercept_id: InterceptId(intercept_id),
+							expected_outbound_amount_msat: amount_msat,
+							payment_hash: PaymentHash([payment_hash; 32]),
+						};
+						prop_assert_eq!(payment_queue.add_htlc(htlc), reference_queue.add_htlc(htlc));
+					},
+					QueueOperation::Pop { amount_msat } => {
+						prop_assert_eq!(
+							payment_queue.pop_greater_than_msat(amount_msat),
+							reference_queue.pop_greater_than_msat(amount_msat)
+						);
+					},
+					QueueOperation::TimerTick => {
+						prop_assert_eq!(payment_queue.timer_tick(3), reference_queue.timer_tick(3));
+					},
+					QueueOperation::Clear => {
+						prop_assert_eq!(payment_queue.clear(), reference_queue.clear());
+					},
+				}
+			}
+			prop_assert_eq!(payment_queue.clear(), reference_queue.clear());
+		}
+	}
 }
@@ -10,16 +10,16 @@
 //! Contains the main LSPS2 server-side object, [`LSPS2ServiceHandler`].
 
 use crate::events::{Event, EventQueue};
-use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
+use crate::lsps0::ser::{LSPSMessage, ProtocolMessageHandler, RequestId, ResponseError};
 use crate::lsps2::event::LSPS2ServiceEvent;
 use crate::lsps2::payment_queue::{InterceptedHTLC, PaymentQueue};
 use crate::lsps2::utils::{compute_opening_fee, is_valid_opening_fee_params};
 use crate::message_queue::MessageQueue;
-use crate::prelude::{HashMap, String, ToString, Vec};
+use crate::prelude::{Box, HashMap, String, ToString, Vec};
 use crate::sync::{Arc, Mutex, RwLock};
 
-use lightning::events::HTLCDestination;
-use lightning::ln::channelmanager::{AChannelManager, InterceptId};
+use lightning::events::{ClosureReason, HTLCDestination};
+use lightning::ln::channelmanager::{AChannelManager, ChannelDetails, InterceptId};
 use lightning::ln::msgs::{ErrorAction, LightningError};
 use lightning::ln::{ChannelId, PaymentHash};
 use lightning::util::errors::APIError;
@@ -38,6 +38,16 @@ use crate::lsps2::msgs::{
 	LSPS2_GET_INFO_REQUEST_UNRECOGNIZED_OR_STALE_TOKEN_ERROR_CODE,
 };
 
+/// The number of [`LSPS2ServiceHandler::timer_tick_occurred`] calls after which we fail the HTLCs
+/// of an initial payment we only partially received, i.e., roughly 90 seconds.
+const MPP_TIMEOUT_TICKS: u32 = 9;
+
+/// The default value of [`LSPS2ServiceConfig::max_htlcs_per_payment`].
+pub const DEFAULT_MAX_HTLCS_PER_PAYMENT: usize = 100;
+
+/// The default value of [`LSPS2ServiceConfig::max_queued_payments`].
+pub const DEFAULT_MAX_QUEUED_PAYMENTS: usize = 50;
+
 /// Server-side configuration options for JIT channels.
 #[derive(Clone, Debug)]
 pub struct LSPS2ServiceConfig {
@@ -45,6 +55,107 @@ pub struct LSPS2ServiceConfig {
 	///
 	/// Note: If this changes then old promises given out will be considered invalid.
 	pub promise_secret: [u8; 32],
+	/// Whether to forward the initial payment of a JIT channel over an existing channel with the
+	/// client, if there is one with sufficient outbound capacity, rather than opening a new one.
+	pub existing_channel_policy: ExistingChannelPolicy,
+	/// The maximum number of HTLCs we hold for a single payment to a JIT channel, e.g., while
+	/// awaiting the remaining parts of a multi-part payment. Further HTLCs are failed back.
+	///
+	/// Defaults to [`DEFAULT_MAX_HTLCS_PER_PAYMENT`].
+	pub max_htlcs_per_payment: usize,
+	/// The maximum number of distinct payments we hold HTLCs for at a time per JIT channel.
+	/// HTLCs of further payments are failed back.
+	///
+	/// Note that finding a payment that can pay the opening fee takes time linear in the number of
+	/// held payments, so this should not be set too high.
+	///
+	/// Defaults to [`DEFAULT_MAX_QUEUED_PAYMENTS`].
+	pub max_queued_payments: usize,
+}
+
+impl LSPS2ServiceConfig {
+	/// Creates a configuration using the given `promise_secret`, always opening new JIT channels,
+	/// and otherwise using the default limits.
+	pub fn new(promise_secret: [u8; 32]) -> Self {
+		Self {
+			promise_secret,
+			existing_channel_policy: ExistingChannelPolicy::OpenNewChannel,
+			max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+		}
+	}
+}
+
+/// Determines how we handle initial payments of JIT channels if the client already has a channel
+/// with us, see [`LSPS2ServiceConfig::existing_channel_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExistingChannelPolicy {
+	/// Always open a new JIT channel.
+	OpenNewChannel,
+	/// Forward the initial payment over an existing channel if it has sufficient outbound capacity,
+	/// skimming the opening fee the client agreed to.
+	///
+	/// The fee is collected just like over a new JIT channel, i.e., it is reported via a
+	/// [`LSPS2ServiceEvent::OpeningFeeCollected`] event once the fees skimmed from the forwarded
+	/// HTLCs add up to it, though no [`LSPS2ServiceEvent::JITChannelOpened`] event is generated.
+	/// While the fee payment is pending, failures of HTLCs forwarded over the existing channel
+	/// can't be told apart from the ones of the fee payment, and are handled as such.
+	///
+	/// Note that the client needs to accept underpaying HTLCs on the existing channel, see
+	/// [`ChannelConfig::accept_underpaying_htlcs`]. If the opening fee is zero, it is waived as per
+	/// [`ExistingChannelPolicy::ReuseWaivingFee`].
+	///
+	/// [`LSPS2ServiceEvent::OpeningFeeCollected`]: crate::lsps2::event::LSPS2ServiceEvent::OpeningFeeCollected
+	/// [`LSPS2ServiceEvent::JITChannelOpened`]: crate::lsps2::event::LSPS2ServiceEvent::JITChannelOpened
+	/// [`ChannelConfig::accept_underpaying_htlcs`]: lightning::util::config::ChannelConfig::accept_underpaying_htlcs
+	ReuseChargingFee,
+	/// Forward the initial payment over an existing channel if it has sufficient outbound capacity,
+	/// waiving the opening fee.
+	///
+	/// The payment is forwarded in full, as clients usually only accept underpaying HTLCs on the
+	/// JIT channels they requested.
+	ReuseWaivingFee,
+}
+
+/// A snapshot of a JIT channel tracked by the [`LSPS2ServiceHandler`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JITChannelDetails {
+	/// The node id of the client the JIT channel was sold to.
+	pub counterparty_node_id: PublicKey,
+	/// The intercept short channel id the client uses in the route hint.
+	pub intercept_scid: u64,
+	/// The `user_channel_id` given to [`LSPS2ServiceHandler::invoice_parameters_generated`].
+	pub user_channel_id: u128,
+	/// The id of the channel the initial payment is forwarded over, if any yet.
+	pub channel_id: Option<ChannelId>,
+	/// The state the JIT channel is in.
+	pub state: JITChannelState,
+	/// The opening fee parameters the client selected.
+	pub opening_fee_params: OpeningFeeParams,
+	/// The size of the initial payment the client requested, if any.
+	pub payment_size_msat: Option<u64>,
+	/// The opening fee we still need to collect, once determined by the initial payment.
+	pub opening_fee_msat: Option<u64>,
+	/// The number of intercepted HTLCs we currently hold for the JIT channel.
+	pub num_queued_htlcs: usize,
+	/// The total expected outbound amount of the HTLCs we currently hold for the JIT channel.
+	pub queued_amount_msat: u64,
+}
+
+/// The state of a JIT channel, see [`JITChannelDetails::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JITChannelState {
+	/// We are awaiting an initial payment of sufficient size to open the channel.
+	PendingInitialPayment,
+	/// We are awaiting the completion of the channel establishment.
+	PendingChannelOpen,
+	/// A payment skimming the opening fee is being forwarded.
+	PendingPaymentForward,
+	/// The channel is open, and we are awaiting a payment of sufficient size to skim the opening
+	/// fee.
+	PendingPayment,
+	/// The opening fee was collected, and any further HTLCs are forwarded as they are.
+	PaymentForwarded,
 }
 
 /// Information about the initial payment size and JIT channel opening fee.
@@ -79,6 +190,8 @@ enum HTLCInterceptedAction {
 	/// The forwarding of the intercepted HTLC.
 	ForwardHTLC(ChannelId),
 	ForwardPayment(ChannelId, FeePayment),
+	/// The failing back of the intercepted HTLC, as we can't queue any further HTLCs.
+	FailHTLC,
 }
 
 /// The forwarding of a payment while skimming the JIT channel opening fee.
@@ -89,6 +202,83 @@ struct ForwardPaymentAction(ChannelId, FeePayment);
 #[derive(Debug, PartialEq)]
 struct ForwardHTLCsAction(ChannelId, Vec<InterceptedHTLC>);
 
+/// The collection of the JIT channel opening fee by the forwarded payment with the given hash,
+/// after which any held HTLCs are forwarded without skimming any further fees.
+#[derive(Debug, PartialEq)]
+struct FeeCollectedAction {
+	opening_fee_msat: u64,
+	payment_hash: PaymentHash,
+	forward_htlcs: ForwardHTLCsAction,
+}
+
+/// A call into the `ChannelManager` resulting from a JIT channel state transition.
+///
+/// These are collected while holding our locks and only executed once all of them are released,
+/// as the `ChannelManager` might call back into us, and slow calls would stall other peers.
+#[derive(Debug, PartialEq)]
+enum ChannelManagerAction {
+	/// The forwarding of the given intercepted HTLCs with the respective amounts.
+	ForwardHTLCs {
+		counterparty_node_id: PublicKey,
+		intercept_scid: u64,
+		channel_id: ChannelId,
+		htlcs: Vec<(InterceptId, u64)>,
+		/// Whether the HTLCs pay the JIT channel opening fee, i.e., whether the JIT channel is
+		/// awaiting the outcome of their forwarding.
+		is_fee_payment: bool,
+	},
+	/// The failing back of the given intercepted HTLCs.
+	FailHTLCs(Vec<InterceptId>),
+}
+
+impl ChannelManagerAction {
+	fn forward_payment(
+		counterparty_node_id: PublicKey, intercept_scid: u64, action: ForwardPaymentAction,
+	) -> Self {
+		let ForwardPaymentAction(channel_id, FeePayment { opening_fee_msat, htlcs }) = action;
+		ChannelManagerAction::ForwardHTLCs {
+			counterparty_node_id,
+			intercept_scid,
+			channel_id,
+			htlcs: calculate_amount_to_forward_per_htlc(&htlcs, opening_fee_msat),
+			is_fee_payment: true,
+		}
+	}
+
+	fn forward_htlcs(
+		counterparty_node_id: PublicKey, intercept_scid: u64, action: ForwardHTLCsAction,
+	) -> Self {
+		let ForwardHTLCsAction(channel_id, htlcs) = action;
+		ChannelManagerAction::ForwardHTLCs {
+			counterparty_node_id,
+			intercept_scid,
+			channel_id,
+			htlcs: htlcs
+				.into_iter()
+				.map(|htlc| (htlc.intercept_id, htlc.expected_outbound_amount_msat))
+				.collect(),
+			is_fee_payment: false,
+		}
+	}
+}
+
+/// What is left to do after handling an intercepted HTLC once all of our locks are released,
+/// besides executing the collected [`ChannelManagerAction`]s.
+enum HTLCInterceptedFollowUp {
+	/// Reporting the waived opening fee, once the payment was forwarded.
+	FeeWaived(Box<Event>),
+	/// Registering the existing channel we forward the payment paying the opening fee over.
+	ChannelReused { channel_id: ChannelId, counterparty_node_id: PublicKey },
+	/// Cleaning up after the JIT channel that failed to handle the HTLC, which was already removed
+	/// from the peer's state.
+	JITChannelFailed {
+		counterparty_node_id: PublicKey,
+		channel_id: Option<ChannelId>,
+		user_channel_id: u128,
+		reason: String,
+	},
+}
+
 /// The different states a requested JIT channel can be in.
 #[derive(Debug)]
 enum OutboundJITChannelState {
@@ -105,6 +295,8 @@ enum OutboundJITChannelState {
 		payment_queue: Arc<Mutex<PaymentQueue>>,
 		opening_fee_msat: u64,
 		channel_id: ChannelId,
+		/// The hash of the payment that is being forwarded.
+		payment_hash: PaymentHash,
 	},
 	/// The channel is open, no payment is currently being forwarded, and the JIT channel fee still
 	/// needs to be paid. This state can occur when the initial payment fails, e.g. due to a
@@ -121,9 +313,9 @@ enum OutboundJITChannelState {
 }
 
 impl OutboundJITChannelState {
-	fn new() -> Self {
+	fn new(payment_queue: PaymentQueue) -> Self {
 		OutboundJITChannelState::PendingInitialPayment {
-			payment_queue: Arc::new(Mutex::new(PaymentQueue::new())),
+			payment_queue: Arc::new(Mutex::new(payment_queue)),
 		}
 	}
 
@@ -134,7 +326,19 @@ impl OutboundJITChannelState {
 		match self {
 			OutboundJITChannelState::PendingInitialPayment { payment_queue } => {
 				let (total_expected_outbound_amount_msat, num_htlcs) =
-					payment_queue.lock().unwrap().add_htlc(htlc);
+					match payment_queue.lock().unwrap().add_htlc(htlc) {
+						Some(queued) => queued,
+						None => {
+							let pending_initial_payment =
+								OutboundJITChannelState::PendingInitialPayment {
+									payment_queue: payment_queue.clone(),
+								};
+							return Ok((
+								pending_initial_payment,
+								Some(HTLCInterceptedAction::FailHTLC),
+							));
+						},
+					};
 
 				let (expected_payment_size_msat, mpp_mode) =
 					if let Some(payment_size_msat) = payment_size_msat {
@@ -143,7 +347,7 @@ impl OutboundJITChannelState {
 						debug_assert_eq!(num_htlcs, 1);
 						if num_htlcs != 1 {
 							return Err(ChannelStateError(
-								format!("Paying via multiple HTLCs is disallowed in \"no-MPP+var-invoice\" mode.")
+								"Paying via multiple HTLCs is disallowed in \"no-MPP+var-invoice\" mode.".to_string()
 							));
 						}
 						(total_expected_outbound_amount_msat, false)
@@ -204,26 +408,32 @@ impl OutboundJITChannelState {
 			},
 			OutboundJITChannelState::PendingChannelOpen { payment_queue, opening_fee_msat } => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
-				payment_queue_lock.add_htlc(htlc);
+				let action = payment_queue_lock
+					.add_htlc(htlc)
+					.map_or(Some(HTLCInterceptedAction::FailHTLC), |_| None);
 				let pending_channel_open = OutboundJITChannelState::PendingChannelOpen {
 					payment_queue: payment_queue.clone(),
 					opening_fee_msat: *opening_fee_msat,
 				};
-				Ok((pending_channel_open, None))
+				Ok((pending_channel_open, action))
 			},
 			OutboundJITChannelState::PendingPaymentForward {
 				payment_queue,
 				opening_fee_msat,
 				channel_id,
+				payment_hash,
 			} => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
-				payment_queue_lock.add_htlc(htlc);
+				let action = payment_queue_lock
+					.add_htlc(htlc)
+					.map_or(Some(HTLCInterceptedAction::FailHTLC), |_| None);
 				let pending_payment_forward = OutboundJITChannelState::PendingPaymentForward {
 					payment_queue: payment_queue.clone(),
 					opening_fee_msat: *opening_fee_msat,
 					channel_id: *channel_id,
+					payment_hash: *payment_hash,
 				};
-				Ok((pending_payment_forward, None))
+				Ok((pending_payment_forward, action))
 			},
 			OutboundJITChannelState::PendingPayment {
 				payment_queue,
@@ -231,14 +441,22 @@ impl OutboundJITChannelState {
 				channel_id,
 			} => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
-				payment_queue_lock.add_htlc(htlc);
-				if let Some((_payment_hash, htlcs)) =
+				if payment_queue_lock.add_htlc(htlc).is_none() {
+					let pending_payment = OutboundJITChannelState::PendingPayment {
+						payment_queue: payment_queue.clone(),
+						opening_fee_msat: *opening_fee_msat,
+						channel_id: *channel_id,
+					};
+					return Ok((pending_payment, Some(HTLCInterceptedAction::FailHTLC)));
+				}
+				if let Some((payment_hash, htlcs)) =
 					payment_queue_lock.pop_greater_than_msat(*opening_fee_msat)
 				{
 					let pending_payment_forward = OutboundJITChannelState::PendingPaymentForward {
 						payment_queue: payment_queue.clone(),
 						opening_fee_msat: *opening_fee_msat,
 						channel_id: *channel_id,
+						payment_hash,
 					};
 					let forward_payment = HTLCInterceptedAction::ForwardPayment(
 						*channel_id,
@@ -263,19 +481,31 @@ impl OutboundJITChannelState {
 		}
 	}
 
+	fn timer_tick(&mut self) -> Vec<InterceptedHTLC> {
+		match self {
+			// Only while collecting the initial payment we might hold on to partial payments
+			// indefinitely, as nothing else would make progress.
+			OutboundJITChannelState::PendingInitialPayment { payment_queue } => {
+				payment_queue.lock().unwrap().timer_tick(MPP_TIMEOUT_TICKS)
+			},
+			_ => Vec::new(),
+		}
+	}
+
 	fn channel_ready(
 		&self, channel_id: ChannelId,
 	) -> Result<(Self, ForwardPaymentAction), ChannelStateError> {
 		match self {
 			OutboundJITChannelState::PendingChannelOpen { payment_queue, opening_fee_msat } => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
-				if let Some((_payment_hash, htlcs)) =
+				if let Some((payment_hash, htlcs)) =
 					payment_queue_lock.pop_greater_than_msat(*opening_fee_msat)
 				{
 					let pending_payment_forward = OutboundJITChannelState::PendingPaymentForward {
 						payment_queue: Arc::clone(&payment_queue),
 						opening_fee_msat: *opening_fee_msat,
 						channel_id,
+						payment_hash,
 					};
 					let forward_payment = ForwardPaymentAction(
 						channel_id,
@@ -304,15 +534,17 @@ impl OutboundJITChannelState {
 				payment_queue,
 				opening_fee_msat,
 				channel_id,
+				..
 			} => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
-				if let Some((_payment_hash, htlcs)) =
+				if let Some((payment_hash, htlcs)) =
 					payment_queue_lock.pop_greater_than_msat(*opening_fee_msat)
 				{
 					let pending_payment_forward = OutboundJITChannelState::PendingPaymentForward {
 						payment_queue: payment_queue.clone(),
 						opening_fee_msat: *opening_fee_msat,
 						channel_id: *channel_id,
+						payment_hash,
 					};
 					let forward_payment = ForwardPaymentAction(
 						*channel_id,
@@ -354,16 +586,23 @@ impl OutboundJITChannelState {
 
 	fn payment_forwarded(
 		&mut self,
-	) -> Result<(Self, Option<ForwardHTLCsAction>), ChannelStateError> {
+	) -> Result<(Self, Option<FeeCollectedAction>), ChannelStateError> {
 		match self {
 			OutboundJITChannelState::PendingPaymentForward {
-				payment_queue, channel_id, ..
+				payment_queue,
+				opening_fee_msat,
+				channel_id,
+				payment_hash,
 			} => {
 				let mut payment_queue_lock = payment_queue.lock().unwrap();
 				let payment_forwarded =
 					OutboundJITChannelState::PaymentForwarded { channel_id: *channel_id };
-				let forward_htlcs = ForwardHTLCsAction(*channel_id, payment_queue_lock.clear());
-				Ok((payment_forwarded, Some(forward_htlcs)))
+				let fee_collected = FeeCollectedAction {
+					opening_fee_msat: *opening_fee_msat,
+					payment_hash: *payment_hash,
+					forward_htlcs: ForwardHTLCsAction(*channel_id, payment_queue_lock.clear()),
+				};
+				Ok((payment_forwarded, Some(fee_collected)))
 			},
 			OutboundJITChannelState::PaymentForwarded { channel_id } => {
 				let payment_forwarded =
@@ -376,6 +615,47 @@ impl OutboundJITChannelState {
 			))),
 		}
 	}
+
+	fn opening_fee_waived(
+		&self, channel_id: ChannelId,
+	) -> Result<(Self, ForwardHTLCsAction), ChannelStateError> {
+		match self {
+			OutboundJITChannelState::PendingChannelOpen { payment_queue, .. } => {
+				let mut payment_queue_lock = payment_queue.lock().unwrap();
+				let payment_forwarded = OutboundJITChannelState::PaymentForwarded { channel_id };
+				let forward_htlcs = ForwardHTLCsAction(channel_id, payment_queue_lock.clear());
+				Ok((payment_forwarded, forward_htlcs))
+			},
+			state => Err(ChannelStateError(format!(
+				"Opening fee waived when JIT Channel was in state: {:?}",
+				state
+			))),
+		}
+	}
+
+	fn channel_open_failed(&self) -> Result<Vec<InterceptedHTLC>, ChannelStateError> {
+		match self {
+			OutboundJITChannelState::PendingChannelOpen { payment_queue, .. } => {
+				Ok(payment_queue.lock().unwrap().clear())
+			},
+			state => Err(ChannelStateError(format!(
+				"Channel open failed when JIT Channel was in state: {:?}",
+				state
+			))),
+		}
+	}
+
+	fn channel_closed(&self) -> Vec<InterceptedHTLC> {
+		match self {
+			OutboundJITChannelState::PendingInitialPayment { payment_queue }
+			| OutboundJITChannelState::PendingChannelOpen { payment_queue, .. }
+			| OutboundJITChannelState::PendingPaymentForward { payment_queue, .. }
+			| OutboundJITChannelState::PendingPayment { payment_queue, .. } => {
+				payment_queue.lock().unwrap().clear()
+			},
+			OutboundJITChannelState::PaymentForwarded { .. } => Vec::new(),
+		}
+	}
 }
 
 struct OutboundJITChannel {
@@ -383,17 +663,31 @@ struct OutboundJITChannel {
 	user_channel_id: u128,
 	opening_fee_params: OpeningFeeParams,
 	payment_size_msat: Option<u64>,
+	// The fee we skimmed so far from the forwarded HTLCs of the payment paying the opening fee,
+	// until it adds up to the full opening fee.
+	pending_fee_collection: Option<PendingFeeCollection>,
+	// Whether we forward the initial payment over an existing channel, charging the opening fee.
+	channel_reused: bool,
+}
+
+struct PendingFeeCollection {
+	payment_hash: PaymentHash,
+	opening_fee_msat: u64,
+	skimmed_fee_msat: u64,
 }
 
 impl OutboundJITChannel {
 	fn new(
-		payment_size_msat: Option<u64>, opening_fee_params: OpeningFeeParams, user_channel_id: u128,
+		payment_size_msat: Option<u64>, opening_fee_params: OpeningFeeParams,
+		user_channel_id: u128, payment_queue: PaymentQueue,
 	) -> Self {
 		Self {
 			user_channel_id,
-			state: OutboundJITChannelState::new(),
+			state: OutboundJITChannelState::new(payment_queue),
 			opening_fee_params,
 			payment_size_msat,
+			pending_fee_collection: None,
+			channel_reused: false,
 		}
 	}
 
@@ -406,6 +700,10 @@ impl OutboundJITChannel {
 		Ok(action)
 	}
 
+	fn timer_tick(&mut self) -> Vec<InterceptedHTLC> {
+		self.state.timer_tick()
+	}
+
 	fn htlc_handling_failed(&mut self) -> Result<Option<ForwardPaymentAction>, LightningError> {
 		let (new_state, action) = self.state.htlc_handling_failed()?;
 		self.state = new_state;
@@ -420,11 +718,124 @@ impl OutboundJITChannel {
 		Ok(action)
 	}
 
-	fn payment_forwarded(&mut self) -> Result<Option<ForwardHTLCsAction>, LightningError> {
+	fn payment_forwarded(&mut self) -> Result<Option<FeeCollectedAction>, LightningError> {
 		let (new_state, action) = self.state.payment_forwarded()?;
 		self.state = new_state;
+		if let Some(FeeCollectedAction { opening_fee_msat, payment_hash, .. }) = &action {
+			self.pending_fee_collection = Some(PendingFeeCollection {
+				payment_hash: *payment_hash,
+				opening_fee_msat: *opening_fee_msat,
+				skimmed_fee_msat: 0,
+			});
+		}
+		Ok(action)
+	}
+
+	// Accounts for the fee skimmed from a forwarded HTLC, returning the hash of the payment paying
+	// the opening fee and the total fee skimmed from it once the full opening fee was collected.
+	fn fee_skimmed(&mut self, skimmed_fee_msat: u64) -> Option<(PaymentHash, u64)> {
+		let mut fee_collection = self.pending_fee_collection.take()?;
+		fee_collection.skimmed_fee_msat =
+			fee_collection.skimmed_fee_msat.saturating_add(skimmed_fee_msat);
+		if fee_collection.skimmed_fee_msat >= fee_collection.opening_fee_msat {
+			Some((fee_collection.payment_hash, fee_collection.skimmed_fee_msat))
+		} else {
+			self.pending_fee_collection = Some(fee_collection);
+			None
+		}
+	}
+
+	fn opening_fee_waived(
+		&mut self, channel_id: ChannelId,
+	) -> Result<ForwardHTLCsAction, LightningError> {
+		let (new_state, action) = self.state.opening_fee_waived(channel_id)?;
+		self.state = new_state;
+		Ok(action)
+	}
+
+	// Forwards the payment paying the opening fee over the given existing channel, as if it was
+	// the JIT channel we opened.
+	fn channel_reused(
+		&mut self, channel_id: ChannelId,
+	) -> Result<ForwardPaymentAction, LightningError> {
+		let (new_state, action) = self.state.channel_ready(channel_id)?;
+		self.state = new_state;
+		self.channel_reused = true;
 		Ok(action)
 	}
+
+	fn channel_open_failed(&self) -> Result<Vec<InterceptedHTLC>, LightningError> {
+		Ok(self.state.channel_open_failed()?)
+	}
+
+	fn channel_closed(&self) -> Vec<InterceptedHTLC> {
+		self.state.channel_closed()
+	}
+
+	fn is_payment_forwarded(&self) -> bool {
+		matches!(self.state, OutboundJITChannelState::PaymentForwarded { .. })
+	}
+
+	fn is_payment_forwarded_over(&self, channel_id: &ChannelId) -> bool {
+		matches!(
+			&self.state,
+			OutboundJITChannelState::PaymentForwarded { channel_id: forwarded_channel_id }
+				if forwarded_channel_id == channel_id
+		)
+	}
+
+	fn details(&self, counterparty_node_id: PublicKey, intercept_scid: u64) -> JITChannelDetails {
+		let (state, payment_queue, opening_fee_msat, channel_id) = match &self.state {
+			OutboundJITChannelState::PendingInitialPayment { payment_queue } => {
+				(JITChannelState::PendingInitialPayment, Some(payment_queue), None, None)
+			},
+			OutboundJITChannelState::PendingChannelOpen { payment_queue, opening_fee_msat } => (
+				JITChannelState::PendingChannelOpen,
+				Some(payment_queue),
+				Some(*opening_fee_msat),
+				None,
+			),
+			OutboundJITChannelState::PendingPaymentForward {
+				payment_queue,
+				opening_fee_msat,
+				channel_id,
+				..
+			} => (
+				JITChannelState::PendingPaymentForward,
+				Some(payment_queue),
+				Some(*opening_fee_msat),
+				Some(*channel_id),
+			),
+			OutboundJITChannelState::PendingPayment {
+				payment_queue,
+				opening_fee_msat,
+				channel_id,
+			} => (
+				JITChannelState::PendingPayment,
+				Some(payment_queue),
+				Some(*opening_fee_msat),
+				Some(*channel_id),
+			),
+			OutboundJITChannelState::PaymentForwarded { channel_id } => {
+				(JITChannelState::PaymentForwarded, None, None, Some(*channel_id))
+			},
+		};
+		let (num_queued_htlcs, queued_amount_msat) = payment_queue
+			.map(|payment_queue| payment_queue.lock().unwrap().queued_htlcs())
+			.unwrap_or((0, 0));
+		JITChannelDetails {
+			counterparty_node_id,
+			intercept_scid,
+			user_channel_id: self.user_channel_id,
+			channel_id,
+			state,
+			opening_fee_params: self.opening_fee_params.clone(),
+			payment_size_msat: self.payment_size_msat,
+			opening_fee_msat,
+			num_queued_htlcs,
+			queued_amount_msat,
+		}
+	}
 }
 
 struct PeerState {
@@ -451,6 +862,34 @@ impl PeerState {
 	fn insert_outbound_channel(&mut self, intercept_scid: u64, channel: OutboundJITChannel) {
 		self.outbound_channels_by_intercept_scid.insert(intercept_scid, channel);
 	}
+
+	// Returns whether the given channel currently carries a JIT channel payment we still need to
+	// learn the outcome of, or collect the opening fee from.
+	fn is_channel_in_use(&self, channel_id: &ChannelId) -> bool {
+		self.intercept_scid_by_channel_id
+			.get(channel_id)
+			.and_then(|scid| self.outbound_channels_by_intercept_scid.get(scid))
+			.map(|jit_channel| {
+				!jit_channel.is_payment_forwarded() || jit_channel.pending_fee_collection.is_some()
+			})
+			.unwrap_or(false)
+	}
+
+	fn remove_outbound_channel(
+		&mut self, intercept_scid: u64,
+	) -> Option<(OutboundJITChannel, Option<ChannelId>)> {
+		let channel = self.outbound_channels_by_intercept_scid.remove(&intercept_scid)?;
+		self.intercept_scid_by_user_channel_id.remove(&channel.user_channel_id);
+		let channel_id = self
+			.intercept_scid_by_channel_id
+			.iter()
+			.find(|(_, scid)| **scid == intercept_scid)
+			.map(|(channel_id, _)| *channel_id);
+		if let Some(channel_id) = channel_id {
+			self.intercept_scid_by_channel_id.remove(&channel_id);
+		}
+		Some((channel, channel_id))
+	}
 }
 
 /// The main object allowing to send and receive LSPS2 messages.
@@ -551,14 +990,14 @@ where
 		&self, counterparty_node_id: &PublicKey, request_id: RequestId,
 		opening_fee_params_menu: Vec<RawOpeningFeeParams>,
 	) -> Result<(), APIError> {
-		let (result, response) = {
+		let (result, msg) = {
 			let outer_state_lock = self.per_peer_state.read().unwrap();
 
 			match outer_state_lock.get(counterparty_node_id) {
 				Some(inner_state_lock) => {
 					let mut peer_state = inner_state_lock.lock().unwrap();
 
-					match peer_state.pending_requests.remove(&request_id) {
+					match peer_state.pending_requests.get(&request_id) {
 						Some(LSPS2Request::GetInfo(_)) => {
 							let response = LSPS2Response::GetInfo(GetInfoResponse {
 								opening_fee_params_menu: opening_fee_params_menu
@@ -568,7 +1007,18 @@ where
 									})
 									.collect(),
 							});
-							(Ok(()), Some(response))
+							let msg: LSPSMessage =
+								LSPS2Message::Response(request_id.clone(), response).into();
+
+							// We only drop the request once we know the response fits into a
+							// message, so that it may be answered with a shorter menu otherwise.
+							match msg.to_payload() {
+								Ok(_) => {
+									peer_state.pending_requests.remove(&request_id);
+									(Ok(()), Some(msg))
+								},
+								Err(e) => (Err(e.into()), None),
+							}
 						},
 						_ => (
 							Err(APIError::APIMisuseError {
@@ -593,8 +1043,7 @@ where
 			}
 		};
 
-		if let Some(response) = response {
-			let msg = LSPS2Message::Response(request_id, response).into();
+		if let Some(msg) = msg {
 			self.pending_messages.enqueue(counterparty_node_id, msg);
 		}
 
@@ -626,10 +1075,15 @@ where
 									.insert(intercept_scid, *counterparty_node_id);
 							}
 
+							let payment_queue = PaymentQueue::new(
+								self.config.max_htlcs_per_payment,
+								self.config.max_queued_payments,
+							);
 							let outbound_jit_channel = OutboundJITChannel::new(
 								buy_request.payment_size_msat,
 								buy_request.opening_fee_params,
 								user_channel_id,
+								payment_queue,
 							);
 
 							peer_state
@@ -684,6 +1138,10 @@ where
 	/// Will generate a [`LSPS2ServiceEvent::OpenChannel`] event if the intercept scid matches a payment we are expected
 	/// and the payment amount is correct and the offer has not expired.
 	///
+	/// Will instead forward the payment over an existing channel with the client if allowed by
+	/// [`LSPS2ServiceConfig::existing_channel_policy`] and the channel has sufficient outbound
+	/// capacity.
+	///
 	/// Will do nothing if the intercept scid does not match any of the ones we gave out.
 	///
 	/// [`Event::HTLCIntercepted`]: lightning::events::Event::HTLCIntercepted
@@ -691,6 +1149,74 @@ where
 	pub fn htlc_intercepted(
 		&self, intercept_scid: u64, intercept_id: InterceptId, expected_outbound_amount_msat: u64,
 		payment_hash: PaymentHash,
+	) -> Result<(), APIError> {
+		// We look up the client's existing channels before acquiring our locks, as we must not
+		// call into the `ChannelManager` while holding them.
+		let existing_channels =
+			if self.config.existing_channel_policy == ExistingChannelPolicy::OpenNewChannel {
+				Vec::new()
+			} else {
+				let counterparty_node_id =
+					self.peer_by_intercept_scid.read().unwrap().get(&intercept_scid).copied();
+				counterparty_node_id
+					.map(|node_id| {
+						self.channel_manager.get_cm().list_channels_with_counterparty(&node_id)
+					})
+					.unwrap_or_default()
+			};
+
+		let htlc = InterceptedHTLC { intercept_id, expected_outbound_amount_msat, payment_hash };
+		let mut actions = Vec::new();
+		let mut follow_up = None;
+		let result = self.do_htlc_intercepted(
+			intercept_scid,
+			htlc,
+			&existing_channels,
+			&mut actions,
+			&mut follow_up,
+		);
+
+		// We can only register the reused channel once we released our locks, as
+		// `htlc_handling_failed` acquires them in the opposite order.
+		if let Some(HTLCInterceptedFollowUp::ChannelReused { channel_id, counterparty_node_id }) =
+			&follow_up
+		{
+			self.peer_by_channel_id.write().unwrap().insert(*channel_id, *counterparty_node_id);
+		}
+		let actions_result = self.execute_actions(actions);
+
+		match follow_up {
+			// We only report the waived fee once we actually forwarded the payment.
+			Some(HTLCInterceptedFollowUp::FeeWaived(event)) => {
+				if actions_result.is_ok() {
+					self.pending_events.enqueue(*event);
+				}
+			},
+			// The actions already failed the HTLCs the JIT channel held.
+			Some(HTLCInterceptedFollowUp::JITChannelFailed {
+				counterparty_node_id,
+				channel_id,
+				user_channel_id,
+				reason,
+			}) => {
+				self.jit_channel_failed(
+					&counterparty_node_id,
+					intercept_scid,
+					channel_id,
+					user_channel_id,
+					Vec::new(),
+					Some(reason),
+				);
+			},
+			Some(HTLCInterceptedFollowUp::ChannelReused { .. }) | None => {},
+		}
+
+		result.and(actions_result)
+	}
+
+	fn do_htlc_intercepted(
+		&self, intercept_scid: u64, htlc: InterceptedHTLC, existing_channels: &[ChannelDetails],
+		actions: &mut Vec<ChannelManagerAction>, follow_up: &mut Option<HTLCInterceptedFollowUp>,
 	) -> Result<(), APIError> {
 		let peer_by_intercept_scid = self.peer_by_intercept_scid.read().unwrap();
 		if let Some(counterparty_node_id) = peer_by_intercept_scid.get(&intercept_scid) {
@@ -701,57 +1227,102 @@ where
 					if let Some(jit_channel) =
 						peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid)
 					{
-						let htlc = InterceptedHTLC {
-							intercept_id,
-							expected_outbound_amount_msat,
-							payment_hash,
-						};
 						match jit_channel.htlc_intercepted(htlc) {
 							Ok(Some(HTLCInterceptedAction::OpenChannel(open_channel_params))) => {
-								let event = Event::LSPS2Service(LSPS2ServiceEvent::OpenChannel {
-									their_network_key: counterparty_node_id.clone(),
-									amt_to_forward_msat: open_channel_params.amt_to_forward_msat,
-									opening_fee_msat: open_channel_params.opening_fee_msat,
-									user_channel_id: jit_channel.user_channel_id,
-									intercept_scid,
-								});
-								self.pending_events.enqueue(event);
+								let user_channel_id = jit_channel.user_channel_id;
+								let charge_fee = self.config.existing_channel_policy
+									== ExistingChannelPolicy::ReuseChargingFee
+									&& open_channel_params.opening_fee_msat > 0;
+								if let Some(channel_id) = self.find_reusable_channel(
+									existing_channels,
+									&peer_state,
+									&open_channel_params,
+								) {
+									if charge_fee {
+										// We map the reused channel to the JIT channel, so that we
+										// learn the outcome of the fee payment.
+										let action = self.charge_fee_over_reused_channel(
+											&mut peer_state,
+											intercept_scid,
+											channel_id,
+											counterparty_node_id,
+										)?;
+										actions.push(action);
+										*follow_up = Some(HTLCInterceptedFollowUp::ChannelReused {
+											channel_id,
+											counterparty_node_id: *counterparty_node_id,
+										});
+									} else {
+										// We don't map the reused channel to the JIT channel, as
+										// its other forwards are unrelated to the JIT channel.
+										let (action, event) = self.waive_fee_over_reused_channel(
+											&mut peer_state,
+											intercept_scid,
+											channel_id,
+											counterparty_node_id,
+											htlc.payment_hash,
+										)?;
+										actions.push(action);
+										*follow_up = Some(HTLCInterceptedFollowUp::FeeWaived(
+											Box::new(event),
+										));
+									}
+								} else {
+									let event =
+										Event::LSPS2Service(LSPS2ServiceEvent::OpenChannel {
+											their_network_key: counterparty_node_id.clone(),
+											amt_to_forward_msat: open_channel_params
+												.amt_to_forward_msat,
+											opening_fee_msat: open_channel_params.opening_fee_msat,
+											user_channel_id,
+											intercept_scid,
+										});
+									self.pending_events.enqueue(event);
+								}
 							},
 							Ok(Some(HTLCInterceptedAction::ForwardHTLC(channel_id))) => {
-								self.channel_manager.get_cm().forward_intercepted_htlc(
-									intercept_id,
-									&channel_id,
+								actions.push(ChannelManagerAction::forward_htlcs(
 									*counterparty_node_id,
-									expected_outbound_amount_msat,
-								)?;
+									intercept_scid,
+									ForwardHTLCsAction(channel_id, vec![htlc]),
+								));
 							},
 							Ok(Some(HTLCInterceptedAction::ForwardPayment(
 								channel_id,
-								FeePayment { opening_fee_msat, htlcs },
+								fee_payment,
 							))) => {
-								let amounts_to_forward_msat =
-									calculate_amount_to_forward_per_htlc(&htlcs, opening_fee_msat);
-
-								for (intercept_id, amount_to_forward_msat) in
-									amounts_to_forward_msat
-								{
-									self.channel_manager.get_cm().forward_intercepted_htlc(
-										intercept_id,
-										&channel_id,
-										*counterparty_node_id,
-										amount_to_forward_msat,
-									)?;
-								}
+								actions.push(ChannelManagerAction::forward_payment(
+									*counterparty_node_id,
+									intercept_scid,
+									ForwardPaymentAction(channel_id, fee_payment),
+								));
+							},
+							Ok(Some(HTLCInterceptedAction::FailHTLC)) => {
+								actions
+									.push(ChannelManagerAction::FailHTLCs(vec![htlc.intercept_id]));
 							},
 							Ok(None) => {},
 							Err(e) => {
-								self.channel_manager
-									.get_cm()
-									.fail_intercepted_htlc(intercept_id)?;
-								peer_state
-									.outbound_channels_by_intercept_scid
-									.remove(&intercept_scid);
-								// TODO: cleanup peer_by_intercept_scid
+								// Fail the HTLC alongside any others the JIT channel queued.
+								let mut intercept_ids = jit_channel
+									.channel_closed()
+									.into_iter()
+									.map(|htlc| htlc.intercept_id)
+									.collect::<Vec<_>>();
+								if !intercept_ids.contains(&htlc.intercept_id) {
+									intercept_ids.push(htlc.intercept_id);
+								}
+								actions.push(ChannelManagerAction::FailHTLCs(intercept_ids));
+								let user_channel_id = jit_channel.user_channel_id;
+								let channel_id = peer_state
+									.remove_outbound_channel(intercept_scid)
+									.and_then(|(_, channel_id)| channel_id);
+								*follow_up = Some(HTLCInterceptedFollowUp::JITChannelFailed {
+									counterparty_node_id: *counterparty_node_id,
+									channel_id,
+									user_channel_id,
+									reason: e.err.clone(),
+								});
 								return Err(APIError::APIMisuseError { err: e.err });
 							},
 						}
@@ -768,6 +1339,86 @@ where
 		Ok(())
 	}
 
+	fn find_reusable_channel(
+		&self, existing_channels: &[ChannelDetails], peer_state: &PeerState,
+		open_channel_params: &OpenChannelParams,
+	) -> Option<ChannelId> {
+		let required_capacity_msat = match self.config.existing_channel_policy {
+			ExistingChannelPolicy::OpenNewChannel => return None,
+			ExistingChannelPolicy::ReuseChargingFee => open_channel_params.amt_to_forward_msat,
+			ExistingChannelPolicy::ReuseWaivingFee => open_channel_params
+				.amt_to_forward_msat
+				.saturating_add(open_channel_params.opening_fee_msat),
+		};
+
+		existing_channels
+			.iter()
+			.find(|channel| {
+				channel.is_usable
+					&& channel.next_outbound_htlc_limit_msat >= required_capacity_msat
+					&& !peer_state.is_channel_in_use(&channel.channel_id)
+			})
+			.map(|channel| channel.channel_id)
+	}
+
+	fn charge_fee_over_reused_channel(
+		&self, peer_state: &mut PeerState, intercept_scid: u64, channel_id: ChannelId,
+		counterparty_node_id: &PublicKey,
+	) -> Result<ChannelManagerAction, APIError> {
+		let jit_channel =
+			match peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid) {
+				Some(jit_channel) => jit_channel,
+				None => {
+					return Err(APIError::APIMisuseError {
+						err: format!("No JIT channel found for scid: {}", intercept_scid),
+					})
+				},
+			};
+
+		let forward_payment = jit_channel.channel_reused(channel_id).map_err(|e| {
+			APIError::APIMisuseError { err: format!("Failed to reuse the channel: {}", e.err) }
+		})?;
+		peer_state.intercept_scid_by_channel_id.insert(channel_id, intercept_scid);
+		Ok(ChannelManagerAction::forward_payment(
+			*counterparty_node_id,
+			intercept_scid,
+			forward_payment,
+		))
+	}
+
+	fn waive_fee_over_reused_channel(
+		&self, peer_state: &mut PeerState, intercept_scid: u64, channel_id: ChannelId,
+		counterparty_node_id: &PublicKey, payment_hash: PaymentHash,
+	) -> Result<(ChannelManagerAction, Event), APIError> {
+		let jit_channel =
+			match peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid) {
+				Some(jit_channel) => jit_channel,
+				None => {
+					return Err(APIError::APIMisuseError {
+						err: format!("No JIT channel found for scid: {}", intercept_scid),
+					})
+				},
+			};
+
+		let forward_htlcs = jit_channel.opening_fee_waived(channel_id).map_err(|e| {
+			APIError::APIMisuseError { err: format!("Failed to waive the opening fee: {}", e.err) }
+		})?;
+		let event = Event::LSPS2Service(LSPS2ServiceEvent::OpeningFeeCollected {
+			counterparty_node_id: *counterparty_node_id,
+			intercept_scid,
+			user_channel_id: jit_channel.user_channel_id,
+			channel_id,
+			payment_hash,
+			amount_msat: 0,
+		});
+		let action = ChannelManagerAction::forward_htlcs(
+			*counterparty_node_id,
+			intercept_scid,
+			forward_htlcs,
+		);
+		Ok((action, event))
+	}
+
 	/// Forward [`Event::HTLCHandlingFailed`] event parameter into this function.
 	///
 	/// Will attempt to forward the next payment in the queue if one is present.
@@ -778,6 +1429,7 @@ where
 	pub fn htlc_handling_failed(
 		&self, failed_next_destination: HTLCDestination,
 	) -> Result<(), APIError> {
+		let mut actions = Vec::new();
 		if let HTLCDestination::NextHopChannel { channel_id, .. } = failed_next_destination {
 			let peer_by_channel_id = self.peer_by_channel_id.read().unwrap();
 			if let Some(counterparty_node_id) = peer_by_channel_id.get(&channel_id) {
@@ -793,28 +1445,12 @@ where
 								.get_mut(&intercept_scid)
 							{
 								match jit_channel.htlc_handling_failed() {
-									Ok(Some(ForwardPaymentAction(
-										channel_id,
-										FeePayment { opening_fee_msat, htlcs },
-									))) => {
-										let amounts_to_forward_msat =
-											calculate_amount_to_forward_per_htlc(
-												&htlcs,
-												opening_fee_msat,
-											);
-
-										for (intercept_id, amount_to_forward_msat) in
-											amounts_to_forward_msat
-										{
-											self.channel_manager
-												.get_cm()
-												.forward_intercepted_htlc(
-													intercept_id,
-													&channel_id,
-													*counterparty_node_id,
-													amount_to_forward_msat,
-												)?;
-										}
+									Ok(Some(forward_payment)) => {
+										actions.push(ChannelManagerAction::forward_payment(
+											*counterparty_node_id,
+											intercept_scid,
+											forward_payment,
+										));
 									},
 									Ok(None) => {},
 									Err(e) => {
@@ -831,21 +1467,26 @@ where
 			}
 		}
 
-		Ok(())
+		self.execute_actions(actions)
 	}
 
-	/// Forward [`Event::PaymentForwarded`] event parameter into this function.
+	/// Forward [`Event::PaymentForwarded`] event parameters into this function.
 	///
-	/// Will register the forwarded payment as having paid the JIT channel fee, and forward any held
-	/// and future HTLCs for the SCID of the initial invoice. In the future, this will verify the
-	/// `skimmed_fee_msat` in [`Event::PaymentForwarded`].
+	/// Will register the forwarded payment as having paid the JIT channel fee, and forward any
+	/// held and future HTLCs for the SCID of the initial invoice. Once the `skimmed_fee_msat` of
+	/// the payment's forwarded HTLCs add up to the opening fee, a
+	/// [`LSPS2ServiceEvent::OpeningFeeCollected`] event reporting the skimmed fees is generated.
 	///
-	/// Note that `next_channel_id` is required to be provided. Therefore, the corresponding
-	/// [`Event::PaymentForwarded`] events need to be generated and serialized by LDK versions
-	/// greater or equal to 0.0.107.
+	/// Note that `next_channel_id` and `skimmed_fee_msat` are required to be provided. Therefore,
+	/// the corresponding [`Event::PaymentForwarded`] events need to be generated and serialized by
+	/// LDK versions greater or equal to 0.0.122.
 	///
 	/// [`Event::PaymentForwarded`]: lightning::events::Event::PaymentForwarded
-	pub fn payment_forwarded(&self, next_channel_id: ChannelId) -> Result<(), APIError> {
+	/// [`LSPS2ServiceEvent::OpeningFeeCollected`]: crate::lsps2::event::LSPS2ServiceEvent::OpeningFeeCollected
+	pub fn payment_forwarded(
+		&self, next_channel_id: ChannelId, skimmed_fee_msat: Option<u64>,
+	) -> Result<(), APIError> {
+		let mut actions = Vec::new();
 		if let Some(counterparty_node_id) =
 			self.peer_by_channel_id.read().unwrap().get(&next_channel_id)
 		{
@@ -856,21 +1497,39 @@ where
 					if let Some(intercept_scid) =
 						peer_state.intercept_scid_by_channel_id.get(&next_channel_id).copied()
 					{
-						if let Some(jit_channel) =
-							peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid)
-						{
+						// Forwards over a reused channel are only part of the fee payment if we
+						// skimmed a fee from them.
+						if let Some(jit_channel) = peer_state
+							.outbound_channels_by_intercept_scid
+							.get_mut(&intercept_scid)
+							.filter(|jit_channel| {
+								!jit_channel.channel_reused || skimmed_fee_msat.unwrap_or(0) > 0
+							}) {
 							match jit_channel.payment_forwarded() {
-								Ok(Some(ForwardHTLCsAction(channel_id, htlcs))) => {
-									for htlc in htlcs {
-										self.channel_manager.get_cm().forward_intercepted_htlc(
-											htlc.intercept_id,
-											&channel_id,
+								Ok(action) => {
+									if let Some(FeeCollectedAction { forward_htlcs, .. }) = action {
+										actions.push(ChannelManagerAction::forward_htlcs(
 											*counterparty_node_id,
-											htlc.expected_outbound_amount_msat,
-										)?;
+											intercept_scid,
+											forward_htlcs,
+										));
+									}
+									if let Some((payment_hash, amount_msat)) =
+										jit_channel.fee_skimmed(skimmed_fee_msat.unwrap_or(0))
+									{
+										let event = Event::LSPS2Service(
+											LSPS2ServiceEvent::OpeningFeeCollected {
+												counterparty_node_id: *counterparty_node_id,
+												intercept_scid,
+												user_channel_id: jit_channel.user_channel_id,
+												channel_id: next_channel_id,
+												payment_hash,
+												amount_msat,
+											},
+										);
+										self.pending_events.enqueue(event);
 									}
 								},
-								Ok(None) => {},
 								Err(e) => {
 									return Err(APIError::APIMisuseError {
 										err: format!(
@@ -895,7 +1554,7 @@ where
 			}
 		}
 
-		Ok(())
+		self.execute_actions(actions)
 	}
 
 	/// Forward [`Event::ChannelReady`] event parameters into this function.
@@ -903,7 +1562,11 @@ where
 	/// Will forward the intercepted HTLC if it matches a channel
 	/// we need to forward a payment over otherwise it will be ignored.
 	///
+	/// Will generate a [`LSPS2ServiceEvent::JITChannelOpened`] event if the channel is a JIT channel
+	/// we opened.
+	///
 	/// [`Event::ChannelReady`]: lightning::events::Event::ChannelReady
+	/// [`LSPS2ServiceEvent::JITChannelOpened`]: crate::lsps2::event::LSPS2ServiceEvent::JITChannelOpened
 	pub fn channel_ready(
 		&self, user_channel_id: u128, channel_id: &ChannelId, counterparty_node_id: &PublicKey,
 	) -> Result<(), APIError> {
@@ -911,72 +1574,457 @@ where
 			let mut peer_by_channel_id = self.peer_by_channel_id.write().unwrap();
 			peer_by_channel_id.insert(*channel_id, *counterparty_node_id);
 		}
-		let outer_state_lock = self.per_peer_state.read().unwrap();
-		match outer_state_lock.get(counterparty_node_id) {
-			Some(inner_state_lock) => {
-				let mut peer_state = inner_state_lock.lock().unwrap();
-				if let Some(intercept_scid) =
-					peer_state.intercept_scid_by_user_channel_id.get(&user_channel_id).copied()
-				{
-					peer_state.intercept_scid_by_channel_id.insert(*channel_id, intercept_scid);
-					if let Some(jit_channel) =
-						peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid)
-					{
-						match jit_channel.channel_ready(*channel_id) {
-							Ok(ForwardPaymentAction(
-								channel_id,
-								FeePayment { opening_fee_msat, htlcs },
-							)) => {
-								let amounts_to_forward_msat =
-									calculate_amount_to_forward_per_htlc(&htlcs, opening_fee_msat);
-
-								for (intercept_id, amount_to_forward_msat) in
-									amounts_to_forward_msat
-								{
-									self.channel_manager.get_cm().forward_intercepted_htlc(
-										intercept_id,
-										&channel_id,
-										*counterparty_node_id,
-										amount_to_forward_msat,
-									)?;
+		let action = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock = outer_state_lock.get(counterparty_node_id).ok_or_else(|| {
+				APIError::APIMisuseError {
+					err: format!("No counterparty state for: {}", counterparty_node_id),
+				}
+			})?;
+			let mut peer_state = inner_state_lock.lock().unwrap();
+			let intercept_scid = peer_state
+				.intercept_scid_by_user_channel_id
+				.get(&user_channel_id)
+				.copied()
+				.ok_or_else(|| APIError::APIMisuseError {
+					err: format!(
+						"Could not find a channel with that user_channel_id {}",
+						user_channel_id
+					),
+				})?;
+			peer_state.intercept_scid_by_channel_id.insert(*channel_id, intercept_scid);
+			let jit_channel = peer_state
+				.outbound_channels_by_intercept_scid
+				.get_mut(&intercept_scid)
+				.ok_or_else(|| APIError::APIMisuseError {
+					err: format!(
+						"Could not find a channel with user_channel_id {}",
+						user_channel_id
+					),
+				})?;
+			let forward_payment =
+				jit_channel.channel_ready(*channel_id).map_err(|e| APIError::APIMisuseError {
+					err: format!("Failed to transition to channel ready: {}", e.err),
+				})?;
+			self.pending_events.enqueue(Event::LSPS2Service(LSPS2ServiceEvent::JITChannelOpened {
+				counterparty_node_id: *counterparty_node_id,
+				intercept_scid,
+				user_channel_id,
+				channel_id: *channel_id,
+			}));
+			ChannelManagerAction::forward_payment(
+				*counterparty_node_id,
+				intercept_scid,
+				forward_payment,
+			)
+		};
+
+		self.execute_actions(vec![action])
+	}
+
+	// Executes the given actions, which must only be done after releasing all of our locks.
+	//
+	// If forwarding an HTLC fails, we fail it and the remaining HTLCs of its batch back to the
+	// payer. If none of the HTLCs paying the opening fee could be forwarded, the JIT channel is
+	// rolled back to await a new payment, possibly forwarding the next one it queued. If only some
+	// of them were forwarded, we keep awaiting the outcome of the payment: as the client won't
+	// receive it in full, it fails the forwarded HTLCs back, upon which `htlc_handling_failed`
+	// rolls the JIT channel back likewise. The first encountered error is returned once all
+	// actions were taken.
+	fn execute_actions(&self, mut actions: Vec<ChannelManagerAction>) -> Result<(), APIError> {
+		let mut result = Ok(());
+		while !actions.is_empty() {
+			let mut rollback_actions = Vec::new();
+			for action in actions.drain(..) {
+				match action {
+					ChannelManagerAction::ForwardHTLCs {
+						counterparty_node_id,
+						intercept_scid,
+						channel_id,
+						htlcs,
+						is_fee_payment,
+					} => {
+						let mut htlcs = htlcs.into_iter();
+						let mut num_forwarded = 0;
+						while let Some((intercept_id, amount_to_forward_msat)) = htlcs.next() {
+							if let Err(e) = self.channel_manager.get_cm().forward_intercepted_htlc(
+								intercept_id,
+								&channel_id,
+								counterparty_node_id,
+								amount_to_forward_msat,
+							) {
+								let unforwarded = core::iter::once(intercept_id)
+									.chain(htlcs.by_ref().map(|(intercept_id, _)| intercept_id));
+								self.fail_intercepted_htlcs(unforwarded);
+								if is_fee_payment && num_forwarded == 0 {
+									rollback_actions.extend(self.fee_payment_forward_failed(
+										&counterparty_node_id,
+										intercept_scid,
+									));
 								}
-							},
-							Err(e) => {
-								return Err(APIError::APIMisuseError {
-									err: format!(
-										"Failed to transition to channel ready: {}",
-										e.err
-									),
-								})
-							},
+								result = result.and(Err(e));
+								break;
+							}
+							num_forwarded += 1;
 						}
-					} else {
-						return Err(APIError::APIMisuseError {
-							err: format!(
-								"Could not find a channel with user_channel_id {}",
-								user_channel_id
-							),
-						});
-					}
-				} else {
+					},
+					ChannelManagerAction::FailHTLCs(intercept_ids) => {
+						self.fail_intercepted_htlcs(intercept_ids);
+					},
+				}
+			}
+			actions = rollback_actions;
+		}
+		result
+	}
+
+	fn fail_intercepted_htlcs(&self, intercept_ids: impl IntoIterator<Item = InterceptId>) {
+		for intercept_id in intercept_ids {
+			// The HTLC might have been failed back in the meantime, e.g., as it expired.
+			let _ = self.channel_manager.get_cm().fail_intercepted_htlc(intercept_id);
+		}
+	}
+
+	// Rolls back a JIT channel after none of the HTLCs of its fee payment could be forwarded,
+	// returning the action forwarding the next queued payment, if any.
+	fn fee_payment_forward_failed(
+		&self, counterparty_node_id: &PublicKey, intercept_scid: u64,
+	) -> Option<ChannelManagerAction> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let mut peer_state = outer_state_lock.get(counterparty_node_id)?.lock().unwrap();
+		let jit_channel =
+			peer_state.outbound_channels_by_intercept_scid.get_mut(&intercept_scid)?;
+		match jit_channel.htlc_handling_failed() {
+			Ok(Some(forward_payment)) => Some(ChannelManagerAction::forward_payment(
+				*counterparty_node_id,
+				intercept_scid,
+				forward_payment,
+			)),
+			_ => None,
+		}
+	}
+
+	/// Used by LSP to signal that opening the JIT channel requested via a
+	/// [`LSPS2ServiceEvent::OpenChannel`] event failed, e.g., as [`ChannelManager::create_channel`]
+	/// returned an error or the client rejected the channel.
+	///
+	/// Will fail all HTLCs we held for the JIT channel, forget about it, and generate a
+	/// [`LSPS2ServiceEvent::JITChannelFailed`] event.
+	///
+	/// Note that failures reported via [`Event::ChannelClosed`] should be forwarded to
+	/// [`LSPS2ServiceHandler::channel_closed`] instead.
+	///
+	/// [`LSPS2ServiceEvent::OpenChannel`]: crate::lsps2::event::LSPS2ServiceEvent::OpenChannel
+	/// [`LSPS2ServiceEvent::JITChannelFailed`]: crate::lsps2::event::LSPS2ServiceEvent::JITChannelFailed
+	/// [`ChannelManager::create_channel`]: lightning::ln::channelmanager::ChannelManager::create_channel
+	/// [`Event::ChannelClosed`]: lightning::events::Event::ChannelClosed
+	pub fn channel_open_failed(
+		&self, user_channel_id: u128, counterparty_node_id: &PublicKey, reason: String,
+	) -> Result<(), APIError> {
+		let (intercept_scid, channel_id, htlcs) = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock = outer_state_lock.get(counterparty_node_id).ok_or_else(|| {
+				APIError::APIMisuseError {
+					err: format!("No counterparty state for: {}", counterparty_node_id),
+				}
+			})?;
+			let mut peer_state = inner_state_lock.lock().unwrap();
+			let intercept_scid = peer_state
+				.intercept_scid_by_user_channel_id
+				.get(&user_channel_id)
+				.copied()
+				.ok_or_else(|| APIError::APIMisuseError {
+					err: format!(
+						"Could not find a channel with that user_channel_id {}",
+						user_channel_id
+					),
+				})?;
+			let htlcs = match peer_state.outbound_channels_by_intercept_scid.get(&intercept_scid) {
+				Some(jit_channel) => {
+					jit_channel.channel_open_failed().map_err(|e| APIError::APIMisuseError {
+						err: format!("Failed to transition to channel open failed: {}", e.err),
+					})?
+				},
+				None => {
 					return Err(APIError::APIMisuseError {
 						err: format!(
-							"Could not find a channel with that user_channel_id {}",
+							"Could not find a channel with user_channel_id {}",
 							user_channel_id
 						),
 					});
+				},
+			};
+			let channel_id =
+				peer_state.remove_outbound_channel(intercept_scid).and_then(|(_, id)| id);
+			(intercept_scid, channel_id, htlcs)
+		};
+
+		self.jit_channel_failed(
+			counterparty_node_id,
+			intercept_scid,
+			channel_id,
+			user_channel_id,
+			htlcs,
+			Some(reason),
+		);
+		Ok(())
+	}
+
+	/// Forward [`Event::ChannelClosed`] event parameters into this function.
+	///
+	/// Will fail all HTLCs we still held for the corresponding JIT channel and forget about it. If
+	/// the channel closed before the JIT channel fee was collected, e.g., as its funding failed or
+	/// it closed before becoming ready, a [`LSPS2ServiceEvent::JITChannelFailed`] event will be
+	/// generated. Likewise, we forget about the JIT channels whose initial payment we forwarded
+	/// over the closed channel as per [`LSPS2ServiceConfig::existing_channel_policy`].
+	///
+	/// Will do nothing if the channel isn't a JIT channel we opened or reused.
+	///
+	/// Note that `counterparty_node_id` is required to be provided. Therefore, the corresponding
+	/// [`Event::ChannelClosed`] events need to be generated and serialized by LDK versions greater
+	/// or equal to 0.0.117.
+	///
+	/// [`Event::ChannelClosed`]: lightning::events::Event::ChannelClosed
+	/// [`LSPS2ServiceEvent::JITChannelFailed`]: crate::lsps2::event::LSPS2ServiceEvent::JITChannelFailed
+	pub fn channel_closed(
+		&self, user_channel_id: u128, channel_id: &ChannelId, counterparty_node_id: &PublicKey,
+		reason: &ClosureReason,
+	) {
+		let mut removed_channels = Vec::new();
+		{
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			if let Some(inner_state_lock) = outer_state_lock.get(counterparty_node_id) {
+				let mut peer_state = inner_state_lock.lock().unwrap();
+				let mut intercept_scids: Vec<u64> = peer_state
+					.outbound_channels_by_intercept_scid
+					.iter()
+					.filter(|(_, jit_channel)| jit_channel.is_payment_forwarded_over(channel_id))
+					.map(|(intercept_scid, _)| *intercept_scid)
+					.collect();
+				let mapped_intercept_scids = [
+					peer_state.intercept_scid_by_user_channel_id.get(&user_channel_id).copied(),
+					peer_state.intercept_scid_by_channel_id.get(channel_id).copied(),
+				];
+				for intercept_scid in mapped_intercept_scids.into_iter().flatten() {
+					if !intercept_scids.contains(&intercept_scid) {
+						intercept_scids.push(intercept_scid);
+					}
 				}
+				for intercept_scid in intercept_scids {
+					if let Some((jit_channel, _)) =
+						peer_state.remove_outbound_channel(intercept_scid)
+					{
+						removed_channels.push((intercept_scid, jit_channel));
+					}
+				}
+			}
+		}
+
+		if removed_channels.is_empty() {
+			self.peer_by_channel_id.write().unwrap().remove(channel_id);
+		}
+		for (intercept_scid, jit_channel) in removed_channels {
+			let reason =
+				if jit_channel.is_payment_forwarded() { None } else { Some(reason.to_string()) };
+			self.jit_channel_failed(
+				counterparty_node_id,
+				intercept_scid,
+				Some(*channel_id),
+				jit_channel.user_channel_id,
+				jit_channel.channel_closed(),
+				reason,
+			);
+		}
+	}
+
+	// Cleans up after a JIT channel we already removed from the peer's state, failing the given
+	// HTLCs, and generating a `JITChannelFailed` event if a `reason` is given.
+	fn jit_channel_failed(
+		&self, counterparty_node_id: &PublicKey, intercept_scid: u64,
+		channel_id: Option<ChannelId>, user_channel_id: u128, htlcs: Vec<InterceptedHTLC>,
+		reason: Option<String>,
+	) {
+		self.peer_by_intercept_scid.write().unwrap().remove(&intercept_scid);
+		if let Some(channel_id) = channel_id {
+			self.peer_by_channel_id.write().unwrap().remove(&channel_id);
+		}
+
+		self.fail_intercepted_htlcs(htlcs.into_iter().map(|htlc| htlc.intercept_id));
+
+		if let Some(reason) = reason {
+			self.pending_events.enqueue(Event::LSPS2Service(LSPS2ServiceEvent::JITChannelFailed {
+				counterparty_node_id: *counterparty_node_id,
+				intercept_scid,
+				user_channel_id,
+				reason,
+			}));
+		}
+	}
+
+	/// Should be called roughly every ten seconds, e.g., from a background task.
+	///
+	/// Will fail all HTLCs of an initial payment we intercepted if we didn't receive its full
+	/// `payment_size_msat` within roughly 90 seconds, as the payer could otherwise hold on to the
+	/// HTLCs indefinitely. The JIT channel then awaits a new initial payment.
+	pub fn timer_tick_occurred(&self) {
+		let mut timed_out_htlcs = Vec::new();
+		{
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			for inner_state_lock in outer_state_lock.values() {
+				let mut peer_state = inner_state_lock.lock().unwrap();
+				for jit_channel in peer_state.outbound_channels_by_intercept_scid.values_mut() {
+					timed_out_htlcs.append(&mut jit_channel.timer_tick());
+				}
+			}
+		}
+
+		self.fail_intercepted_htlcs(timed_out_htlcs.into_iter().map(|htlc| htlc.intercept_id));
+	}
+
+	/// Returns the JIT channels we currently track for all clients.
+	pub fn list_jit_channels(&self) -> Vec<JITChannelDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		outer_state_lock
+			.iter()
+			.flat_map(|(counterparty_node_id, inner_state_lock)| {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state
+					.outbound_channels_by_intercept_scid
+					.iter()
+					.map(|(intercept_scid, jit_channel)| {
+						jit_channel.details(*counterparty_node_id, *intercept_scid)
+					})
+					.collect::<Vec<_>>()
+			})
+			.collect()
+	}
+
+	/// Returns the JIT channels we currently track for the given client.
+	pub fn list_jit_channels_with_counterparty(
+		&self, counterparty_node_id: &PublicKey,
+	) -> Vec<JITChannelDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let peer_state = inner_state_lock.lock().unwrap();
+				peer_state
+					.outbound_channels_by_intercept_scid
+					.iter()
+					.map(|(intercept_scid, jit_channel)| {
+						jit_channel.details(*counterparty_node_id, *intercept_scid)
+					})
+					.collect()
 			},
-			None => {
-				return Err(APIError::APIMisuseError {
-					err: format!("No counterparty state for: {}", counterparty_node_id),
-				});
-			},
+			None => Vec::new(),
 		}
+	}
+
+	/// Returns the JIT channel using the given intercept scid, if we track one.
+	pub fn jit_channel_by_intercept_scid(&self, intercept_scid: u64) -> Option<JITChannelDetails> {
+		let counterparty_node_id =
+			self.peer_by_intercept_scid.read().unwrap().get(&intercept_scid).copied()?;
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let peer_state = outer_state_lock.get(&counterparty_node_id)?.lock().unwrap();
+		let jit_channel = peer_state.outbound_channels_by_intercept_scid.get(&intercept_scid)?;
+		Some(jit_channel.details(counterparty_node_id, intercept_scid))
+	}
+
+	/// Returns the JIT channel of the given client with the given `user_channel_id`, if we track
+	/// one.
+	pub fn jit_channel_by_user_channel_id(
+		&self, counterparty_node_id: &PublicKey, user_channel_id: u128,
+	) -> Option<JITChannelDetails> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let peer_state = outer_state_lock.get(counterparty_node_id)?.lock().unwrap();
+		let intercept_scid =
+			peer_state.intercept_scid_by_user_channel_id.get(&user_channel_id).copied()?;
+		let jit_channel = peer_state.outbound_channels_by_intercept_scid.get(&intercept_scid)?;
+		Some(jit_channel.details(*counterparty_node_id, intercept_scid))
+	}
 
+	/// Cancels the JIT channel of the given client using the given intercept scid, e.g., if it is
+	/// stuck.
+	///
+	/// Will fail all HTLCs we held for the JIT channel and forget about it. If the JIT channel fee
+	/// wasn't collected yet, a [`LSPS2ServiceEvent::JITChannelFailed`] event will be generated.
+	///
+	/// Note that this won't close the channel if one was already opened.
+	///
+	/// [`LSPS2ServiceEvent::JITChannelFailed`]: crate::lsps2::event::LSPS2ServiceEvent::JITChannelFailed
+	pub fn cancel_jit_channel(
+		&self, counterparty_node_id: &PublicKey, intercept_scid: u64,
+	) -> Result<(), APIError> {
+		let (jit_channel, channel_id) = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			let inner_state_lock = outer_state_lock.get(counterparty_node_id).ok_or_else(|| {
+				APIError::APIMisuseError {
+					err: format!("No counterparty state for: {}", counterparty_node_id),
+				}
+			})?;
+			let mut peer_state = inner_state_lock.lock().unwrap();
+			peer_state.remove_outbound_channel(intercept_scid).ok_or_else(|| {
+				APIError::APIMisuseError {
+					err: format!("No JIT channel found for scid: {}", intercept_scid),
+				}
+			})?
+		};
+
+		self.jit_channel_cancelled(counterparty_node_id, intercept_scid, channel_id, jit_channel);
 		Ok(())
 	}
 
+	/// Removes all state we track for the given client, e.g., if it is stuck.
+	///
+	/// Will cancel all of the client's JIT channels as if [`LSPS2ServiceHandler::cancel_jit_channel`]
+	/// was called for each of them, and drop its pending requests without responding to them.
+	pub fn remove_peer_state(&self, counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		let mut peer_state = self
+			.per_peer_state
+			.write()
+			.unwrap()
+			.remove(counterparty_node_id)
+			.ok_or_else(|| APIError::APIMisuseError {
+				err: format!("No counterparty state for: {}", counterparty_node_id),
+			})?
+			.into_inner()
+			.unwrap();
+
+		let intercept_scids =
+			peer_state.outbound_channels_by_intercept_scid.keys().copied().collect::<Vec<_>>();
+		for intercept_scid in intercept_scids {
+			if let Some((jit_channel, channel_id)) =
+				peer_state.remove_outbound_channel(intercept_scid)
+			{
+				self.jit_channel_cancelled(
+					counterparty_node_id,
+					intercept_scid,
+					channel_id,
+					jit_channel,
+				);
+			}
+		}
+		Ok(())
+	}
+
+	fn jit_channel_cancelled(
+		&self, counterparty_node_id: &PublicKey, intercept_scid: u64,
+		channel_id: Option<ChannelId>, jit_channel: OutboundJITChannel,
+	) {
+		let reason = if jit_channel.is_payment_forwarded() {
+			None
+		} else {
+			Some("JIT channel was cancelled by the LSP".to_string())
+		};
+		self.jit_channel_failed(
+			counterparty_node_id,
+			intercept_scid,
+			channel_id,
+			jit_channel.user_channel_id,
+			jit_channel.channel_closed(),
+			reason,
+		);
+	}
+
 	fn handle_get_info_request(
 		&self, request_id: RequestId, counterparty_node_id: &PublicKey, params: GetInfoRequest,
 	) -> Result<(), LightningError> {
@@ -1171,6 +2219,187 @@ fn calculate_amount_to_forward_per_htlc(
 	per_htlc_forwards
 }
 
+/// Drives an [`OutboundJITChannel`] through a sequence of events read from `data`, asserting that
+/// we never forward or fail an HTLC that wasn't intercepted, handle an HTLC twice, or forward more
+/// than an HTLC's amount.
+#[cfg(any(test, fuzzing))]
+pub(crate) fn do_jit_channel_state_test(data: &[u8]) {
+	use crate::fuzz::FuzzReader;
+	use crate::prelude::HashSet;
+	use chrono::TimeZone;
+
+	let mut reader = FuzzReader::new(data);
+	let (min_fee_msat, proportional, min_payment_size_msat, max_payment_size_msat, payment_size) =
+		match (
+			reader.read_u32(),
+			reader.read_u32(),
+			reader.read_u32(),
+			reader.read_u32(),
+			reader.read_u32(),
+		) {
+			(Some(a), Some(b), Some(c), Some(d), Some(e)) => (a, b, c, d, e),
+			_ => return,
+		};
+	let opening_fee_params = OpeningFeeParams {
+		min_fee_msat: min_fee_msat as u64 * 1000,
+		proportional,
+		valid_until: chrono::Utc.timestamp_opt(0, 0).unwrap(),
+		min_lifetime: 0,
+		max_client_to_self_delay: 0,
+		min_payment_size_msat: min_payment_size_msat as u64 * 1000,
+		max_payment_size_msat: max_payment_size_msat as u64 * 1000,
+		promise: String::new(),
+	};
+	// Use "no-MPP+var-invoice" mode if the payment size is odd.
+	let payment_size_msat = Some(payment_size as u64 * 1000).filter(|_| payment_size % 2 == 0);
+	let payment_queue =
+		PaymentQueue::new(DEFAULT_MAX_HTLCS_PER_PAYMENT, DEFAULT_MAX_QUEUED_PAYMENTS);
+	let mut jit_channel =
+		OutboundJITChannel::new(payment_size_msat, opening_fee_params.clone(), 0, payment_queue);
+
+	let channel_id = ChannelId([42; 32]);
+	let mut pending_htlcs: HashMap<InterceptId, u64> = HashMap::new();
+	let mut forwarded_htlcs: HashSet<InterceptId> = HashSet::new();
+	let mut opening_fee_msat = None;
+	let mut fee_payment_in_flight = false;
+	let mut fee_paid = false;
+
+	let mut forward = |htlcs: &[(InterceptId, u64)],
+	                   pending_htlcs: &mut HashMap<InterceptId, u64>| {
+		for (intercept_id, amount_to_forward_msat) in htlcs {
+			let amount_msat = pending_htlcs
+				.remove(intercept_id)
+				.expect("Forwarded an HTLC that wasn't intercepted or was already forwarded");
+			assert!(*amount_to_forward_msat <= amount_msat);
+			assert!(forwarded_htlcs.insert(*intercept_id));
+		}
+	};
+	let check_fee_payment = |fee_payment: &FeePayment, opening_fee_msat: Option<u64>| {
+		assert_eq!(Some(fee_payment.opening_fee_msat), opening_fee_msat);
+		let total_msat: u64 =
+			fee_payment.htlcs.iter().map(|htlc| htlc.expected_outbound_amount_msat).sum();
+		assert!(total_msat >= fee_payment.opening_fee_msat);
+		calculate_amount_to_forward_per_htlc(&fee_payment.htlcs, fee_payment.opening_fee_msat)
+	};
+
+	let mut next_intercept_id = 0u64;
+	while let Some(op) = reader.read_u8() {
+		match op % 5 {
+			0 => {
+				let (payment_hash, amount) = match (reader.read_u8(), reader.read_u32()) {
+					(Some(payment_hash), Some(amount)) => (payment_hash % 4, amount),
+					_ => return,
+				};
+				let mut intercept_id = [0; 32];
+				intercept_id[..8].copy_from_slice(&next_intercept_id.to_be_bytes());
+				next_intercept_id += 1;
+				let htlc = InterceptedHTLC {
+					intercept_id: InterceptId(intercept_id),
+					expected_outbound_amount_msat: amount as u64 * 1000,
+					payment_hash: PaymentHash([payment_hash; 32]),
+				};
+				pending_htlcs.insert(htlc.intercept_id, htlc.expected_outbound_amount_msat);
+
+				match jit_channel.htlc_intercepted(htlc) {
+					Ok(Some(HTLCInterceptedAction::OpenChannel(params))) => {
+						assert!(opening_fee_msat.is_none(), "Opened the channel twice");
+						assert!(params.amt_to_forward_msat > 0);
+						assert!(params.opening_fee_msat >= opening_fee_params.min_fee_msat);
+						if let Some(payment_size_msat) = payment_size_msat {
+							assert_eq!(
+								params.amt_to_forward_msat + params.opening_fee_msat,
+								payment_size_msat
+							);
+						}
+						opening_fee_msat = Some(params.opening_fee_msat);
+					},
+					Ok(Some(HTLCInterceptedAction::ForwardHTLC(id))) => {
+						assert_eq!(id, channel_id);
+						assert!(fee_paid, "Forwarded an HTLC before the fee was paid");
+						let amount_msat = htlc.expected_outbound_amount_msat;
+						forward(&[(htlc.intercept_id, amount_msat)], &mut pending_htlcs);
+					},
+					Ok(Some(HTLCInterceptedAction::ForwardPayment(id, fee_payment))) => {
+						assert_eq!(id, channel_id);
+						assert!(!fee_payment_in_flight && !fee_paid);
+						forward(
+							&check_fee_payment(&fee_payment, opening_fee_msat),
+							&mut pending_htlcs,
+						);
+						fee_payment_in_flight = true;
+					},
+					Ok(Some(HTLCInterceptedAction::FailHTLC)) => {
+						pending_htlcs.remove(&htlc.intercept_id);
+					},
+					Ok(None) => {},
+					// The service fails the HTLC and drops the channel.
+					Err(_) => return,
+				}
+			},
+			1 => {
+				if let Ok(ForwardPaymentAction(id, fee_payment)) =
+					jit_channel.channel_ready(channel_id)
+				{
+					assert_eq!(id, channel_id);
+					assert!(!fee_payment_in_flight && !fee_paid);
+					forward(&check_fee_payment(&fee_payment, opening_fee_msat), &mut pending_htlcs);
+					fee_payment_in_flight = true;
+				}
+			},
+			2 => {
+				let was_in_flight = fee_payment_in_flight;
+				if let Ok(action) = jit_channel.htlc_handling_failed() {
+					fee_payment_in_flight = false;
+					if let Some(ForwardPaymentAction(id, fee_payment)) = action {
+						assert_eq!(id, channel_id);
+						assert!(was_in_flight && !fee_paid);
+						forward(
+							&check_fee_payment(&fee_payment, opening_fee_msat),
+							&mut pending_htlcs,
+						);
+						fee_payment_in_flight = true;
+					}
+				}
+			},
+			3 => {
+				if let Ok(action) = jit_channel.payment_forwarded() {
+					if let Some(FeeCollectedAction {
+						opening_fee_msat: collected_fee_msat,
+						forward_htlcs: ForwardHTLCsAction(id, htlcs),
+						..
+					}) = action
+					{
+						assert_eq!(id, channel_id);
+						assert_eq!(Some(collected_fee_msat), opening_fee_msat);
+						assert!(fee_payment_in_flight, "Forwarded HTLCs without a paid fee");
+						let htlcs = htlcs
+							.iter()
+							.map(|htlc| (htlc.intercept_id, htlc.expected_outbound_amount_msat))
+							.collect::<Vec<_>>();
+						forward(&htlcs, &mut pending_htlcs);
+					} else {
+						assert!(fee_paid);
+					}
+					fee_payment_in_flight = false;
+					fee_paid = true;
+				}
+			},
+			_ => {
+				for htlc in jit_channel.timer_tick() {
+					assert!(
+						opening_fee_msat.is_none(),
+						"Timed out HTLCs after opening the channel"
+					);
+					// Failed HTLCs must never be forwarded later on.
+					pending_htlcs
+						.remove(&htlc.intercept_id)
+						.expect("Failed an HTLC that wasn't intercepted or was already handled");
+				}
+			},
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1279,7 +2508,10 @@ mod tests {
 			max_payment_size_msat: 1_000_000_000,
 			promise: "ignore".to_string(),
 		};
-		let mut state = OutboundJITChannelState::new();
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
 		// Intercepts the first HTLC of a multipart payment A.
 		{
 			let (new_state, action) = state
@@ -1423,7 +2655,13 @@ mod tests {
 			let (new_state, action) = state.payment_forwarded().unwrap();
 			assert!(matches!(new_state, OutboundJITChannelState::PaymentForwarded { .. }));
 			match action {
-				Some(ForwardHTLCsAction(channel_id, htlcs)) => {
+				Some(FeeCollectedAction {
+					opening_fee_msat,
+					payment_hash,
+					forward_htlcs: ForwardHTLCsAction(channel_id, htlcs),
+				}) => {
+					assert_eq!(opening_fee_msat, 10_000_000);
+					assert_eq!(payment_hash, PaymentHash([101; 32]));
 					assert_eq!(channel_id, ChannelId([200; 32]));
 					assert_eq!(
 						htlcs,
@@ -1458,6 +2696,221 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_jit_channel_state_mpp_timeout() {
+		let payment_size_msat = Some(500_000_000);
+		let opening_fee_params = OpeningFeeParams {
+			min_fee_msat: 10_000_000,
+			proportional: 10_000,
+			valid_until: Utc.timestamp_opt(3000, 0).unwrap(),
+			min_lifetime: 4032,
+			max_client_to_self_delay: 2016,
+			min_payment_size_msat: 10_000_000,
+			max_payment_size_msat: 1_000_000_000,
+			promise: "ignore".to_string(),
+		};
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
+		let htlc = InterceptedHTLC {
+			intercept_id: InterceptId([0; 32]),
+			expected_outbound_amount_msat: 200_000_000,
+			payment_hash: PaymentHash([100; 32]),
+		};
+		// Intercepts the first HTLC of a multipart payment A.
+		{
+			let (new_state, action) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc).unwrap();
+			assert!(matches!(new_state, OutboundJITChannelState::PendingInitialPayment { .. }));
+			assert!(action.is_none());
+			state = new_state;
+		}
+		// The remaining HTLCs of payment A never arrive, and it times out.
+		for _ in 1..MPP_TIMEOUT_TICKS {
+			assert!(state.timer_tick().is_empty());
+		}
+		assert_eq!(state.timer_tick(), vec![htlc]);
+		assert!(state.timer_tick().is_empty());
+		// A new payment B can still open the channel.
+		{
+			let (new_state, action) = state
+				.htlc_intercepted(
+					&opening_fee_params,
+					&payment_size_msat,
+					InterceptedHTLC {
+						intercept_id: InterceptId([1; 32]),
+						expected_outbound_amount_msat: 500_000_000,
+						payment_hash: PaymentHash([101; 32]),
+					},
+				)
+				.unwrap();
+			assert!(matches!(new_state, OutboundJITChannelState::PendingChannelOpen { .. }));
+			assert!(matches!(action, Some(HTLCInterceptedAction::OpenChannel(_))));
+			state = new_state;
+		}
+		// Once the channel is being opened, the queued payments are no longer subject to the timeout.
+		for _ in 0..MPP_TIMEOUT_TICKS {
+			assert!(state.timer_tick().is_empty());
+		}
+	}
+
+	#[test]
+	fn test_jit_channel_state_open_failed_and_closed() {
+		let payment_size_msat = None;
+		let opening_fee_params = OpeningFeeParams {
+			min_fee_msat: 10_000_000,
+			proportional: 10_000,
+			valid_until: Utc.timestamp_opt(3000, 0).unwrap(),
+			min_lifetime: 4032,
+			max_client_to_self_delay: 2016,
+			min_payment_size_msat: 10_000_000,
+			max_payment_size_msat: 1_000_000_000,
+			promise: "ignore".to_string(),
+		};
+		let htlc_a = InterceptedHTLC {
+			intercept_id: InterceptId([0; 32]),
+			expected_outbound_amount_msat: 500_000_000,
+			payment_hash: PaymentHash([100; 32]),
+		};
+		let htlc_b = InterceptedHTLC {
+			intercept_id: InterceptId([1; 32]),
+			expected_outbound_amount_msat: 200_000_000,
+			payment_hash: PaymentHash([101; 32]),
+		};
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
+		// The channel open can only fail once it was requested.
+		assert!(state.channel_open_failed().is_err());
+		// Intercepts payment A, opening the channel, and payment B.
+		{
+			let (new_state, action) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_a).unwrap();
+			assert!(matches!(action, Some(HTLCInterceptedAction::OpenChannel(_))));
+			state = new_state;
+			let (new_state, action) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_b).unwrap();
+			assert!(action.is_none());
+			state = new_state;
+		}
+		// Failing the channel open returns all held HTLCs.
+		assert_eq!(state.channel_open_failed().unwrap(), vec![htlc_a, htlc_b]);
+
+		// Once the channel is ready, the forwarded payment is no longer held by us.
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
+		{
+			let (new_state, _) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_a).unwrap();
+			state = new_state;
+			let (new_state, _) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_b).unwrap();
+			state = new_state;
+			let (new_state, _) = state.channel_ready(ChannelId([200; 32])).unwrap();
+			state = new_state;
+		}
+		assert!(state.channel_open_failed().is_err());
+		assert_eq!(state.channel_closed(), vec![htlc_b]);
+	}
+
+	#[test]
+	fn test_jit_channel_state_opening_fee_waived() {
+		let payment_size_msat = Some(500_000_000);
+		let opening_fee_params = OpeningFeeParams {
+			min_fee_msat: 10_000_000,
+			proportional: 10_000,
+			valid_until: Utc.timestamp_opt(3000, 0).unwrap(),
+			min_lifetime: 4032,
+			max_client_to_self_delay: 2016,
+			min_payment_size_msat: 10_000_000,
+			max_payment_size_msat: 1_000_000_000,
+			promise: "ignore".to_string(),
+		};
+		let htlc_a = InterceptedHTLC {
+			intercept_id: InterceptId([0; 32]),
+			expected_outbound_amount_msat: 500_000_000,
+			payment_hash: PaymentHash([100; 32]),
+		};
+		let htlc_b = InterceptedHTLC {
+			intercept_id: InterceptId([1; 32]),
+			expected_outbound_amount_msat: 1_000_000,
+			payment_hash: PaymentHash([101; 32]),
+		};
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
+		// The opening fee can only be waived once the initial payment arrived.
+		assert!(state.opening_fee_waived(ChannelId([200; 32])).is_err());
+		// Intercepts the first HTLC of payment B, and then payment A, triggering the channel open.
+		{
+			let (new_state, action) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_b).unwrap();
+			assert!(action.is_none());
+			state = new_state;
+			let (new_state, action) =
+				state.htlc_intercepted(&opening_fee_params, &payment_size_msat, htlc_a).unwrap();
+			assert!(matches!(action, Some(HTLCInterceptedAction::OpenChannel(_))));
+			state = new_state;
+		}
+		// Waiving the opening fee forwards all held HTLCs over the existing channel as they are.
+		{
+			let (new_state, ForwardHTLCsAction(channel_id, htlcs)) =
+				state.opening_fee_waived(ChannelId([200; 32])).unwrap();
+			assert_eq!(channel_id, ChannelId([200; 32]));
+			assert_eq!(htlcs, vec![htlc_b, htlc_a]);
+			assert!(matches!(new_state, OutboundJITChannelState::PaymentForwarded { .. }));
+		}
+	}
+
+	#[test]
+	fn test_jit_channel_state_failure_returns_queued_htlcs() {
+		let payment_size_msat = Some(500_000_000);
+		let opening_fee_params = OpeningFeeParams {
+			min_fee_msat: 10_000_000,
+			proportional: 10_000,
+			valid_until: Utc.timestamp_opt(3000, 0).unwrap(),
+			min_lifetime: 4032,
+			max_client_to_self_delay: 2016,
+			min_payment_size_msat: 10_000_000,
+			max_payment_size_msat: 1_000_000_000,
+			promise: "ignore".to_string(),
+		};
+		let mut jit_channel = OutboundJITChannel::new(
+			payment_size_msat,
+			opening_fee_params,
+			42,
+			PaymentQueue::new(DEFAULT_MAX_HTLCS_PER_PAYMENT, DEFAULT_MAX_QUEUED_PAYMENTS),
+		);
+		let htlc = |byte| InterceptedHTLC {
+			intercept_id: InterceptId([byte; 32]),
+			expected_outbound_amount_msat: 100_000_000,
+			payment_hash: PaymentHash([100; 32]),
+		};
+		// Queues two HTLCs of a multipart payment.
+		assert!(jit_channel.htlc_intercepted(htlc(0)).unwrap().is_none());
+		assert!(jit_channel.htlc_intercepted(htlc(1)).unwrap().is_none());
+
+		// Fails to handle the next one, as the payment size now violates the limits.
+		jit_channel.opening_fee_params.max_payment_size_msat = 100_000_000;
+		assert!(jit_channel.htlc_intercepted(htlc(2)).is_err());
+
+		// All HTLCs we held are returned to be failed back.
+		let intercept_ids = jit_channel
+			.channel_closed()
+			.into_iter()
+			.map(|htlc| htlc.intercept_id)
+			.collect::<Vec<_>>();
+		assert_eq!(
+			intercept_ids,
+			vec![InterceptId([0; 32]), InterceptId([1; 32]), InterceptId([2; 32])]
+		);
+	}
+
 	#[test]
 	fn test_jit_channel_state_no_mpp() {
 		let payment_size_msat = None;
@@ -1471,7 +2924,10 @@ mod tests {
 			max_payment_size_msat: 1_000_000_000,
 			promise: "ignore".to_string(),
 		};
-		let mut state = OutboundJITChannelState::new();
+		let mut state = OutboundJITChannelState::new(PaymentQueue::new(
+			DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			DEFAULT_MAX_QUEUED_PAYMENTS,
+		));
 		// Intercepts payment A, opening the channel.
 		{
 			let (new_state, action) = state
@@ -1564,7 +3020,13 @@ mod tests {
 			let (new_state, action) = state.payment_forwarded().unwrap();
 			assert!(matches!(new_state, OutboundJITChannelState::PaymentForwarded { .. }));
 			match action {
-				Some(ForwardHTLCsAction(channel_id, htlcs)) => {
+				Some(FeeCollectedAction {
+					opening_fee_msat,
+					payment_hash,
+					forward_htlcs: ForwardHTLCsAction(channel_id, htlcs),
+				}) => {
+					assert_eq!(opening_fee_msat, 10_000_000);
+					assert_eq!(payment_hash, PaymentHash([101; 32]));
 					assert_eq!(channel_id, ChannelId([200; 32]));
 					assert_eq!(
 						htlcs,
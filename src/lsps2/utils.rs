@@ -7,26 +7,13 @@ use bitcoin::hashes::hmac::{Hmac, HmacEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::{Hash, HashEngine};
 
-#[cfg(feature = "std")]
-use std::time::{SystemTime, UNIX_EPOCH};
-
 /// Determines if the given parameters are valid given the secret used to generate the promise.
 pub fn is_valid_opening_fee_params(
 	fee_params: &OpeningFeeParams, promise_secret: &[u8; 32],
 ) -> bool {
-	#[cfg(feature = "std")]
-	{
-		// TODO: We need to find a way to check expiry times in no-std builds.
-		let seconds_since_epoch = SystemTime::now()
-			.duration_since(UNIX_EPOCH)
-			.expect("system clock to be ahead of the unix epoch")
-			.as_secs();
-		let valid_until_seconds_since_epoch = fee_params
-			.valid_until
-			.timestamp()
-			.try_into()
-			.expect("expiration to be ahead of unix epoch");
-		if seconds_since_epoch > valid_until_seconds_since_epoch {
+	// TODO: We need to find a way to check expiry times in no-std builds.
+	if let Some(now_secs) = utils::seconds_since_epoch() {
+		if is_expired_opening_fee_params(fee_params, now_secs) {
 			return false;
 		}
 	}
@@ -44,6 +31,37 @@ pub fn is_valid_opening_fee_params(
 	promise == fee_params.promise
 }
 
+/// Determines if the given parameters have expired at `now_secs`, i.e., whether their
+/// `valid_until` lies before the given number of seconds since the unix epoch.
+pub fn is_expired_opening_fee_params(fee_params: &OpeningFeeParams, now_secs: u64) -> bool {
+	seconds_until_expiry(fee_params, now_secs) < 0
+}
+
+/// Returns the number of seconds from `now_secs` until the given parameters expire, which is
+/// negative if they already did.
+pub(crate) fn seconds_until_expiry(fee_params: &OpeningFeeParams, now_secs: u64) -> i64 {
+	fee_params.valid_until.timestamp().saturating_sub(now_secs.try_into().unwrap_or(i64::MAX))
+}
+
+/// Computes the opening fee the LSP would charge for a payment of the given size under the given
+/// parameters.
+///
+/// Returns [`Option::None`] if the parameters aren't applicable to the payment, i.e., if they
+/// expired at `now_secs`, given in seconds since the unix epoch, the payment size lies outside of
+/// the range they cover, or the opening fee wouldn't be smaller than the payment itself.
+pub fn compute_applicable_opening_fee(
+	fee_params: &OpeningFeeParams, payment_size_msat: u64, now_secs: u64,
+) -> Option<u64> {
+	if is_expired_opening_fee_params(fee_params, now_secs)
+		|| payment_size_msat < fee_params.min_payment_size_msat
+		|| payment_size_msat > fee_params.max_payment_size_msat
+	{
+		return None;
+	}
+	compute_opening_fee(payment_size_msat, fee_params.min_fee_msat, fee_params.proportional.into())
+		.filter(|opening_fee_msat| *opening_fee_msat < payment_size_msat)
+}
+
 /// Computes the opening fee given a payment size and the fee parameters.
 ///
 /// Returns [`Option::None`] when the computation overflows.
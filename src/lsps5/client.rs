@@ -0,0 +1,384 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Contains the main LSPS5 client object, [`LSPS5ClientHandler`].
+
+use crate::events::{Event, EventQueue};
+use crate::lsps0::ser::{ProtocolMessageHandler, RequestId, ResponseError};
+use crate::lsps5::event::LSPS5ClientEvent;
+use crate::lsps5::msgs::{
+	LSPS5Message, LSPS5Request, LSPS5Response, ListWebhooksRequest, ListWebhooksResponse,
+	RemoveWebhookRequest, SetWebhookRequest, SetWebhookResponse, MAX_APP_NAME_LENGTH,
+	MAX_WEBHOOK_URL_LENGTH,
+};
+use crate::message_queue::MessageQueue;
+use crate::prelude::{HashMap, HashSet, String};
+use crate::sync::{Arc, Mutex, RwLock};
+
+use lightning::ln::msgs::{ErrorAction, LightningError};
+use lightning::sign::EntropySource;
+use lightning::util::errors::APIError;
+use lightning::util::logger::Level;
+
+use bitcoin::secp256k1::PublicKey;
+
+use core::ops::Deref;
+
+/// Client-side configuration options for LSPS5 webhook registration.
+#[derive(Clone, Debug, Copy, Default)]
+pub struct LSPS5ClientConfig {}
+
+struct PeerState {
+	pending_set_webhook_requests: HashMap<RequestId, (String, String)>,
+	pending_list_webhooks_requests: HashSet<RequestId>,
+	pending_remove_webhook_requests: HashMap<RequestId, String>,
+}
+
+impl PeerState {
+	fn new() -> Self {
+		let pending_set_webhook_requests = HashMap::new();
+		let pending_list_webhooks_requests = HashSet::new();
+		let pending_remove_webhook_requests = HashMap::new();
+		Self {
+			pending_set_webhook_requests,
+			pending_list_webhooks_requests,
+			pending_remove_webhook_requests,
+		}
+	}
+}
+
+/// The main object allowing to send and receive LSPS5 messages.
+pub struct LSPS5ClientHandler<ES: Deref>
+where
+	ES::Target: EntropySource,
+{
+	entropy_source: ES,
+	pending_messages: Arc<MessageQueue>,
+	pending_events: Arc<EventQueue>,
+	per_peer_state: RwLock<HashMap<PublicKey, Mutex<PeerState>>>,
+	_config: LSPS5ClientConfig,
+}
+
+impl<ES: Deref> LSPS5ClientHandler<ES>
+where
+	ES::Target: EntropySource,
+{
+	/// Constructs an `LSPS5ClientHandler`.
+	pub(crate) fn new(
+		entropy_source: ES, pending_messages: Arc<MessageQueue>, pending_events: Arc<EventQueue>,
+		_config: LSPS5ClientConfig,
+	) -> Self {
+		Self {
+			entropy_source,
+			pending_messages,
+			pending_events,
+			per_peer_state: RwLock::new(HashMap::new()),
+			_config,
+		}
+	}
+
+	/// Registers a webhook with the LSP, replacing any webhook previously registered for the same
+	/// `app_name`.
+	///
+	/// The LSP will use the webhook to notify us, e.g., about incoming payments while we're
+	/// offline. The result will be returned via a [`WebhookRegistered`] or
+	/// [`WebhookRegistrationFailed`] event.
+	///
+	/// `counterparty_node_id` is the `node_id` of the LSP you would like to use.
+	///
+	/// Returns an error if `app_name` or `webhook` exceed the lengths allowed by the specification.
+	///
+	/// [`WebhookRegistered`]: crate::lsps5::event::LSPS5ClientEvent::WebhookRegistered
+	/// [`WebhookRegistrationFailed`]: crate::lsps5::event::LSPS5ClientEvent::WebhookRegistrationFailed
+	pub fn set_webhook(
+		&self, counterparty_node_id: PublicKey, app_name: String, webhook: String,
+	) -> Result<RequestId, APIError> {
+		if app_name.len() > MAX_APP_NAME_LENGTH {
+			return Err(APIError::APIMisuseError {
+				err: format!("App name exceeds maximum length of {} bytes", MAX_APP_NAME_LENGTH),
+			});
+		}
+
+		if webhook.len() > MAX_WEBHOOK_URL_LENGTH {
+			return Err(APIError::APIMisuseError {
+				err: format!(
+					"Webhook URL exceeds maximum length of {} bytes",
+					MAX_WEBHOOK_URL_LENGTH
+				),
+			});
+		}
+
+		let request_id = crate::utils::generate_request_id(&self.entropy_source);
+
+		{
+			let mut outer_state_lock = self.per_peer_state.write().unwrap();
+			let inner_state_lock = &*outer_state_lock
+				.entry(counterparty_node_id)
+				.or_insert(Mutex::new(PeerState::new()));
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+			peer_state_lock
+				.pending_set_webhook_requests
+				.insert(request_id.clone(), (app_name.clone(), webhook.clone()));
+		}
+
+		let request = LSPS5Request::SetWebhook(SetWebhookRequest { app_name, webhook });
+		let msg = LSPS5Message::Request(request_id.clone(), request).into();
+		self.pending_messages.enqueue(&counterparty_node_id, msg);
+
+		Ok(request_id)
+	}
+
+	/// Requests the list of apps we have registered webhooks for with the LSP.
+	///
+	/// The result will be returned via a [`WebhooksListed`] or [`WebhooksListFailed`] event.
+	///
+	/// [`WebhooksListed`]: crate::lsps5::event::LSPS5ClientEvent::WebhooksListed
+	/// [`WebhooksListFailed`]: crate::lsps5::event::LSPS5ClientEvent::WebhooksListFailed
+	pub fn list_webhooks(&self, counterparty_node_id: PublicKey) -> RequestId {
+		let request_id = crate::utils::generate_request_id(&self.entropy_source);
+
+		{
+			let mut outer_state_lock = self.per_peer_state.write().unwrap();
+			let inner_state_lock = &*outer_state_lock
+				.entry(counterparty_node_id)
+				.or_insert(Mutex::new(PeerState::new()));
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+			peer_state_lock.pending_list_webhooks_requests.insert(request_id.clone());
+		}
+
+		let request = LSPS5Request::ListWebhooks(ListWebhooksRequest {});
+		let msg = LSPS5Message::Request(request_id.clone(), request).into();
+		self.pending_messages.enqueue(&counterparty_node_id, msg);
+
+		request_id
+	}
+
+	/// Removes the webhook registered for the given `app_name` with the LSP.
+	///
+	/// The result will be returned via a [`WebhookRemoved`] or [`WebhookRemovalFailed`] event.
+	///
+	/// Returns an error if `app_name` exceeds the length allowed by the specification.
+	///
+	/// [`WebhookRemoved`]: crate::lsps5::event::LSPS5ClientEvent::WebhookRemoved
+	/// [`WebhookRemovalFailed`]: crate::lsps5::event::LSPS5ClientEvent::WebhookRemovalFailed
+	pub fn remove_webhook(
+		&self, counterparty_node_id: PublicKey, app_name: String,
+	) -> Result<RequestId, APIError> {
+		if app_name.len() > MAX_APP_NAME_LENGTH {
+			return Err(APIError::APIMisuseError {
+				err: format!("App name exceeds maximum length of {} bytes", MAX_APP_NAME_LENGTH),
+			});
+		}
+
+		let request_id = crate::utils::generate_request_id(&self.entropy_source);
+
+		{
+			let mut outer_state_lock = self.per_peer_state.write().unwrap();
+			let inner_state_lock = &*outer_state_lock
+				.entry(counterparty_node_id)
+				.or_insert(Mutex::new(PeerState::new()));
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+			peer_state_lock
+				.pending_remove_webhook_requests
+				.insert(request_id.clone(), app_name.clone());
+		}
+
+		let request = LSPS5Request::RemoveWebhook(RemoveWebhookRequest { app_name });
+		let msg = LSPS5Message::Request(request_id.clone(), request).into();
+		self.pending_messages.enqueue(&counterparty_node_id, msg);
+
+		Ok(request_id)
+	}
+
+	fn handle_set_webhook_response(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
+		result: Result<SetWebhookResponse, ResponseError>,
+	) -> Result<(), LightningError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let mut peer_state = inner_state_lock.lock().unwrap();
+
+				let (app_name, webhook) = peer_state
+					.pending_set_webhook_requests
+					.remove(&request_id)
+					.ok_or(LightningError {
+						err: format!(
+							"Received set_webhook response for an unknown request: {:?}",
+							request_id
+						),
+						action: ErrorAction::IgnoreAndLog(Level::Info),
+					})?;
+
+				let event = match result {
+					Ok(response) => LSPS5ClientEvent::WebhookRegistered {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						app_name,
+						webhook,
+						num_webhooks: response.num_webhooks,
+						max_webhooks: response.max_webhooks,
+						no_change: response.no_change,
+					},
+					Err(error) => LSPS5ClientEvent::WebhookRegistrationFailed {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						app_name,
+						webhook,
+						error,
+					},
+				};
+				self.pending_events.enqueue(Event::LSPS5Client(event));
+				Ok(())
+			},
+			None => Err(LightningError {
+				err: format!(
+					"Received set_webhook response from unknown peer: {:?}",
+					counterparty_node_id
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			}),
+		}
+	}
+
+	fn handle_list_webhooks_response(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
+		result: Result<ListWebhooksResponse, ResponseError>,
+	) -> Result<(), LightningError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let mut peer_state = inner_state_lock.lock().unwrap();
+
+				if !peer_state.pending_list_webhooks_requests.remove(&request_id) {
+					return Err(LightningError {
+						err: format!(
+							"Received list_webhooks response for an unknown request: {:?}",
+							request_id
+						),
+						action: ErrorAction::IgnoreAndLog(Level::Info),
+					});
+				}
+
+				let event = match result {
+					Ok(response) => LSPS5ClientEvent::WebhooksListed {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						app_names: response.app_names,
+						max_webhooks: response.max_webhooks,
+					},
+					Err(error) => LSPS5ClientEvent::WebhooksListFailed {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						error,
+					},
+				};
+				self.pending_events.enqueue(Event::LSPS5Client(event));
+				Ok(())
+			},
+			None => Err(LightningError {
+				err: format!(
+					"Received list_webhooks response from unknown peer: {:?}",
+					counterparty_node_id
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			}),
+		}
+	}
+
+	fn handle_remove_webhook_response(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
+		result: Result<(), ResponseError>,
+	) -> Result<(), LightningError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		match outer_state_lock.get(counterparty_node_id) {
+			Some(inner_state_lock) => {
+				let mut peer_state = inner_state_lock.lock().unwrap();
+
+				let app_name = peer_state
+					.pending_remove_webhook_requests
+					.remove(&request_id)
+					.ok_or(LightningError {
+						err: format!(
+							"Received remove_webhook response for an unknown request: {:?}",
+							request_id
+						),
+						action: ErrorAction::IgnoreAndLog(Level::Info),
+					})?;
+
+				let event = match result {
+					Ok(()) => LSPS5ClientEvent::WebhookRemoved {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						app_name,
+					},
+					Err(error) => LSPS5ClientEvent::WebhookRemovalFailed {
+						request_id,
+						counterparty_node_id: *counterparty_node_id,
+						app_name,
+						error,
+					},
+				};
+				self.pending_events.enqueue(Event::LSPS5Client(event));
+				Ok(())
+			},
+			None => Err(LightningError {
+				err: format!(
+					"Received remove_webhook response from unknown peer: {:?}",
+					counterparty_node_id
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			}),
+		}
+	}
+}
+
+impl<ES: Deref> ProtocolMessageHandler for LSPS5ClientHandler<ES>
+where
+	ES::Target: EntropySource,
+{
+	type ProtocolMessage = LSPS5Message;
+	const PROTOCOL_NUMBER: Option<u16> = Some(5);
+
+	fn handle_message(
+		&self, message: Self::ProtocolMessage, counterparty_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		match message {
+			LSPS5Message::Response(request_id, response) => match response {
+				LSPS5Response::SetWebhook(result) => {
+					self.handle_set_webhook_response(request_id, counterparty_node_id, Ok(result))
+				},
+				LSPS5Response::SetWebhookError(error) => {
+					self.handle_set_webhook_response(request_id, counterparty_node_id, Err(error))
+				},
+				LSPS5Response::ListWebhooks(result) => {
+					self.handle_list_webhooks_response(request_id, counterparty_node_id, Ok(result))
+				},
+				LSPS5Response::ListWebhooksError(error) => {
+					self.handle_list_webhooks_response(request_id, counterparty_node_id, Err(error))
+				},
+				LSPS5Response::RemoveWebhook(_) => {
+					self.handle_remove_webhook_response(request_id, counterparty_node_id, Ok(()))
+				},
+				LSPS5Response::RemoveWebhookError(error) => self.handle_remove_webhook_response(
+					request_id,
+					counterparty_node_id,
+					Err(error),
+				),
+			},
+			_ => {
+				debug_assert!(
+					false,
+					"Client handler received LSPS5 request message. This should never happen."
+				);
+				Err(LightningError { err: format!("Client handler received LSPS5 request message from node {:?}. This should never happen.", counterparty_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)})
+			},
+		}
+	}
+}
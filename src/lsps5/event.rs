@@ -0,0 +1,131 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Contains LSPS5 event types
+
+use super::msgs::WebhookNotification;
+use crate::lsps0::ser::{RequestId, ResponseError};
+use crate::prelude::{String, Vec};
+
+use bitcoin::secp256k1::PublicKey;
+
+/// An event which an LSPS5 client should take some action in response to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LSPS5ClientEvent {
+	/// The LSP registered the webhook for the given app.
+	WebhookRegistered {
+		/// The identifier of the issued LSPS5 `set_webhook` request, as returned by
+		/// [`LSPS5ClientHandler::set_webhook`].
+		///
+		/// [`LSPS5ClientHandler::set_webhook`]: crate::lsps5::client::LSPS5ClientHandler::set_webhook
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The name of the app the webhook was registered for.
+		app_name: String,
+		/// The registered webhook URL.
+		webhook: String,
+		/// The number of webhooks we have registered with the LSP.
+		num_webhooks: u32,
+		/// The maximum number of webhooks the LSP allows per client.
+		max_webhooks: u32,
+		/// Indicates whether the exact same webhook was already registered.
+		no_change: bool,
+	},
+	/// The LSP refused to register the webhook for the given app.
+	WebhookRegistrationFailed {
+		/// The identifier of the issued LSPS5 `set_webhook` request, as returned by
+		/// [`LSPS5ClientHandler::set_webhook`].
+		///
+		/// [`LSPS5ClientHandler::set_webhook`]: crate::lsps5::client::LSPS5ClientHandler::set_webhook
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The name of the app the webhook was to be registered for.
+		app_name: String,
+		/// The webhook URL that was to be registered.
+		webhook: String,
+		/// The error returned by the LSP.
+		error: ResponseError,
+	},
+	/// The LSP returned the list of apps we have registered webhooks for.
+	WebhooksListed {
+		/// The identifier of the issued LSPS5 `list_webhooks` request, as returned by
+		/// [`LSPS5ClientHandler::list_webhooks`].
+		///
+		/// [`LSPS5ClientHandler::list_webhooks`]: crate::lsps5::client::LSPS5ClientHandler::list_webhooks
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The names of the apps we have registered webhooks for.
+		app_names: Vec<String>,
+		/// The maximum number of webhooks the LSP allows per client.
+		max_webhooks: u32,
+	},
+	/// The LSP failed to list the apps we have registered webhooks for.
+	WebhooksListFailed {
+		/// The identifier of the issued LSPS5 `list_webhooks` request, as returned by
+		/// [`LSPS5ClientHandler::list_webhooks`].
+		///
+		/// [`LSPS5ClientHandler::list_webhooks`]: crate::lsps5::client::LSPS5ClientHandler::list_webhooks
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The error returned by the LSP.
+		error: ResponseError,
+	},
+	/// The LSP removed the webhook for the given app.
+	WebhookRemoved {
+		/// The identifier of the issued LSPS5 `remove_webhook` request, as returned by
+		/// [`LSPS5ClientHandler::remove_webhook`].
+		///
+		/// [`LSPS5ClientHandler::remove_webhook`]: crate::lsps5::client::LSPS5ClientHandler::remove_webhook
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The name of the app whose webhook was removed.
+		app_name: String,
+	},
+	/// The LSP failed to remove the webhook for the given app, e.g., as none was registered.
+	WebhookRemovalFailed {
+		/// The identifier of the issued LSPS5 `remove_webhook` request, as returned by
+		/// [`LSPS5ClientHandler::remove_webhook`].
+		///
+		/// [`LSPS5ClientHandler::remove_webhook`]: crate::lsps5::client::LSPS5ClientHandler::remove_webhook
+		request_id: RequestId,
+		/// The node id of the LSP.
+		counterparty_node_id: PublicKey,
+		/// The name of the app whose webhook was to be removed.
+		app_name: String,
+		/// The error returned by the LSP.
+		error: ResponseError,
+	},
+}
+
+/// An event which an LSPS5 server should take some action in response to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LSPS5ServiceEvent {
+	/// A notification needs to be delivered to a client's webhook.
+	///
+	/// You must send an HTTP `POST` request to the given `webhook` URL, using the serialized
+	/// `notification` as its body. Please refer to the [LSPS5 specification] for how to sign the
+	/// request.
+	///
+	/// [LSPS5 specification]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/tree/main/LSPS5
+	SendWebhookNotification {
+		/// The node id of the client the notification is meant for.
+		counterparty_node_id: PublicKey,
+		/// The name of the app the webhook was registered for.
+		app_name: String,
+		/// The webhook URL to send the notification to.
+		webhook: String,
+		/// The notification to send.
+		notification: WebhookNotification,
+	},
+}
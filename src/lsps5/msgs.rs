@@ -0,0 +1,307 @@
+//! Message, request, and other primitive types used to implement LSPS5.
+
+use crate::lsps0::ser::{LSPSMessage, RequestId, ResponseError};
+use crate::prelude::{String, Vec};
+
+use serde::de::{self, Deserializer};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize};
+
+use core::convert::TryFrom;
+
+pub(crate) const LSPS5_SET_WEBHOOK_METHOD_NAME: &str = "lsps5.set_webhook";
+pub(crate) const LSPS5_LIST_WEBHOOKS_METHOD_NAME: &str = "lsps5.list_webhooks";
+pub(crate) const LSPS5_REMOVE_WEBHOOK_METHOD_NAME: &str = "lsps5.remove_webhook";
+
+pub(crate) const LSPS5_WEBHOOK_REGISTERED_NOTIFICATION: &str = "lsps5.webhook_registered";
+pub(crate) const LSPS5_PAYMENT_INCOMING_NOTIFICATION: &str = "lsps5.payment_incoming";
+pub(crate) const LSPS5_EXPIRY_SOON_NOTIFICATION: &str = "lsps5.expiry_soon";
+pub(crate) const LSPS5_LIQUIDITY_MANAGEMENT_REQUEST_NOTIFICATION: &str =
+	"lsps5.liquidity_management_request";
+pub(crate) const LSPS5_ONION_MESSAGE_INCOMING_NOTIFICATION: &str = "lsps5.onion_message_incoming";
+
+pub(crate) const LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE: i32 = 500;
+pub(crate) const LSPS5_SET_WEBHOOK_REQUEST_URL_PARSE_ERROR_CODE: i32 = 501;
+pub(crate) const LSPS5_SET_WEBHOOK_REQUEST_UNSUPPORTED_PROTOCOL_ERROR_CODE: i32 = 502;
+pub(crate) const LSPS5_SET_WEBHOOK_REQUEST_TOO_MANY_WEBHOOKS_ERROR_CODE: i32 = 503;
+pub(crate) const LSPS5_REMOVE_WEBHOOK_REQUEST_APP_NAME_NOT_FOUND_ERROR_CODE: i32 = 1010;
+
+/// The maximum length of an app name in bytes.
+pub const MAX_APP_NAME_LENGTH: usize = 64;
+
+/// The maximum length of a webhook URL in bytes.
+pub const MAX_WEBHOOK_URL_LENGTH: usize = 1024;
+
+/// A request made to an LSP to register a webhook for the given app.
+///
+/// Please refer to the [LSPS5 specification](https://github.com/BitcoinAndLightningLayerSpecs/lsp/tree/main/LSPS5#lsps5set_webhook)
+/// for more information.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SetWebhookRequest {
+	/// A human-readable name identifying the app, e.g., to allow for registering multiple webhooks.
+	pub app_name: String,
+	/// The HTTPS URL the LSP will send notifications to.
+	pub webhook: String,
+}
+
+/// A response to a [`SetWebhookRequest`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SetWebhookResponse {
+	/// The number of webhooks registered by the client, including the given one.
+	pub num_webhooks: u32,
+	/// The maximum number of webhooks the LSP allows per client.
+	pub max_webhooks: u32,
+	/// Indicates whether the exact same webhook was already registered for the given app.
+	pub no_change: bool,
+}
+
+/// A request made to an LSP to list all webhooks registered by the client.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct ListWebhooksRequest {}
+
+/// A response to a [`ListWebhooksRequest`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ListWebhooksResponse {
+	/// The names of the apps the client registered webhooks for.
+	pub app_names: Vec<String>,
+	/// The maximum number of webhooks the LSP allows per client.
+	pub max_webhooks: u32,
+}
+
+/// A request made to an LSP to remove the webhook registered for the given app.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RemoveWebhookRequest {
+	/// The name of the app whose webhook should be removed.
+	pub app_name: String,
+}
+
+/// A response to a [`RemoveWebhookRequest`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct RemoveWebhookResponse {}
+
+/// A notification an LSP sends to a client's webhook.
+///
+/// Serializes to and deserializes from the JSON-RPC 2.0 notification object that is to be sent
+/// as the body of the HTTP request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebhookNotification {
+	/// The webhook was newly registered or changed.
+	WebhookRegistered,
+	/// A payment is about to arrive, i.e., the client should come online to receive it.
+	PaymentIncoming,
+	/// An HTLC or other time-bound contract with the client will expire soon.
+	ExpirySoon {
+		/// The block height at which the LSP will be forced to close the channel.
+		timeout: u32,
+	},
+	/// The LSP wants to take back some of the liquidity it provided to the client.
+	LiquidityManagementRequest,
+	/// An onion message is waiting to be delivered to the client.
+	OnionMessageIncoming,
+}
+
+impl WebhookNotification {
+	fn method(&self) -> &'static str {
+		match self {
+			WebhookNotification::WebhookRegistered => LSPS5_WEBHOOK_REGISTERED_NOTIFICATION,
+			WebhookNotification::PaymentIncoming => LSPS5_PAYMENT_INCOMING_NOTIFICATION,
+			WebhookNotification::ExpirySoon { .. } => LSPS5_EXPIRY_SOON_NOTIFICATION,
+			WebhookNotification::LiquidityManagementRequest => {
+				LSPS5_LIQUIDITY_MANAGEMENT_REQUEST_NOTIFICATION
+			},
+			WebhookNotification::OnionMessageIncoming => LSPS5_ONION_MESSAGE_INCOMING_NOTIFICATION,
+		}
+	}
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+struct EmptyParams {}
+
+#[derive(Deserialize, Serialize)]
+struct ExpirySoonParams {
+	timeout: u32,
+}
+
+impl Serialize for WebhookNotification {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		let mut notification = serializer.serialize_struct("WebhookNotification", 3)?;
+		notification.serialize_field("jsonrpc", "2.0")?;
+		notification.serialize_field("method", self.method())?;
+		match self {
+			WebhookNotification::ExpirySoon { timeout } => {
+				notification.serialize_field("params", &ExpirySoonParams { timeout: *timeout })?
+			},
+			_ => notification.serialize_field("params", &EmptyParams {})?,
+		}
+		notification.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for WebhookNotification {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		#[derive(Deserialize)]
+		struct RawNotification {
+			method: String,
+			#[serde(default)]
+			params: serde_json::Value,
+		}
+
+		let raw = RawNotification::deserialize(deserializer)?;
+		match raw.method.as_str() {
+			LSPS5_WEBHOOK_REGISTERED_NOTIFICATION => Ok(WebhookNotification::WebhookRegistered),
+			LSPS5_PAYMENT_INCOMING_NOTIFICATION => Ok(WebhookNotification::PaymentIncoming),
+			LSPS5_EXPIRY_SOON_NOTIFICATION => {
+				let params: ExpirySoonParams =
+					serde_json::from_value(raw.params).map_err(de::Error::custom)?;
+				Ok(WebhookNotification::ExpirySoon { timeout: params.timeout })
+			},
+			LSPS5_LIQUIDITY_MANAGEMENT_REQUEST_NOTIFICATION => {
+				Ok(WebhookNotification::LiquidityManagementRequest)
+			},
+			LSPS5_ONION_MESSAGE_INCOMING_NOTIFICATION => {
+				Ok(WebhookNotification::OnionMessageIncoming)
+			},
+			method => Err(de::Error::custom(format!("Unknown notification method: {}", method))),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An enum that captures all the valid JSON-RPC requests in the LSPS5 protocol.
+pub enum LSPS5Request {
+	/// A request to register a webhook.
+	SetWebhook(SetWebhookRequest),
+	/// A request to list all registered webhooks.
+	ListWebhooks(ListWebhooksRequest),
+	/// A request to remove a webhook.
+	RemoveWebhook(RemoveWebhookRequest),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An enum that captures all the valid JSON-RPC responses in the LSPS5 protocol.
+pub enum LSPS5Response {
+	/// A successful response to a [`LSPS5Request::SetWebhook`] request.
+	SetWebhook(SetWebhookResponse),
+	/// An error response to a [`LSPS5Request::SetWebhook`] request.
+	SetWebhookError(ResponseError),
+	/// A successful response to a [`LSPS5Request::ListWebhooks`] request.
+	ListWebhooks(ListWebhooksResponse),
+	/// An error response to a [`LSPS5Request::ListWebhooks`] request.
+	ListWebhooksError(ResponseError),
+	/// A successful response to a [`LSPS5Request::RemoveWebhook`] request.
+	RemoveWebhook(RemoveWebhookResponse),
+	/// An error response to a [`LSPS5Request::RemoveWebhook`] request.
+	RemoveWebhookError(ResponseError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// An enum that captures all valid JSON-RPC messages in the LSPS5 protocol.
+pub enum LSPS5Message {
+	/// An LSPS5 JSON-RPC request.
+	Request(RequestId, LSPS5Request),
+	/// An LSPS5 JSON-RPC response.
+	Response(RequestId, LSPS5Response),
+}
+
+impl TryFrom<LSPSMessage> for LSPS5Message {
+	type Error = ();
+
+	fn try_from(message: LSPSMessage) -> Result<Self, Self::Error> {
+		if let LSPSMessage::LSPS5(message) = message {
+			return Ok(message);
+		}
+
+		Err(())
+	}
+}
+
+impl From<LSPS5Message> for LSPSMessage {
+	fn from(message: LSPS5Message) -> Self {
+		LSPSMessage::LSPS5(message)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::alloc::string::ToString;
+	use crate::lsps0::ser::{DeserializationMode, LSPSMethod};
+	use crate::prelude::HashMap;
+
+	#[test]
+	fn set_webhook_round_trip() {
+		let request_id = RequestId("abc".to_string());
+		let request = LSPS5Message::Request(
+			request_id.clone(),
+			LSPS5Request::SetWebhook(SetWebhookRequest {
+				app_name: "My LSPS-Compliant Lightning Client".to_string(),
+				webhook: "https://www.example.org/push?l=1234567890abcdefghijklmnopqrstuv&c=best"
+					.to_string(),
+			}),
+		);
+
+		let json = serde_json::to_string(&LSPSMessage::from(request.clone())).unwrap();
+		let mut request_id_to_method_map = HashMap::new();
+		let parsed = LSPSMessage::from_str_with_id_map(
+			&json,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		);
+		assert_eq!(parsed.unwrap(), LSPSMessage::from(request));
+
+		let json_str = r#"{
+			"jsonrpc": "2.0",
+			"id": "abc",
+			"result": {
+				"num_webhooks": 2,
+				"max_webhooks": 4,
+				"no_change": false
+			}
+		}"#;
+		request_id_to_method_map.insert(request_id.clone(), LSPSMethod::LSPS5SetWebhook);
+		let parsed = LSPSMessage::from_str_with_id_map(
+			json_str,
+			&mut request_id_to_method_map,
+			&HashMap::new(),
+			DeserializationMode::Permissive,
+		)
+		.unwrap();
+		let expected = LSPSMessage::LSPS5(LSPS5Message::Response(
+			request_id,
+			LSPS5Response::SetWebhook(SetWebhookResponse {
+				num_webhooks: 2,
+				max_webhooks: 4,
+				no_change: false,
+			}),
+		));
+		assert_eq!(parsed, expected);
+	}
+
+	#[test]
+	fn notification_serialization() {
+		let notification = WebhookNotification::ExpirySoon { timeout: 255001 };
+		let json = serde_json::to_string(&notification).unwrap();
+		assert_eq!(
+			json,
+			r#"{"jsonrpc":"2.0","method":"lsps5.expiry_soon","params":{"timeout":255001}}"#
+		);
+		assert_eq!(serde_json::from_str::<WebhookNotification>(&json).unwrap(), notification);
+
+		let json = serde_json::to_string(&WebhookNotification::PaymentIncoming).unwrap();
+		assert_eq!(json, r#"{"jsonrpc":"2.0","method":"lsps5.payment_incoming","params":{}}"#);
+		assert_eq!(
+			serde_json::from_str::<WebhookNotification>(&json).unwrap(),
+			WebhookNotification::PaymentIncoming
+		);
+	}
+}
@@ -0,0 +1,368 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Contains the main LSPS5 server object, [`LSPS5ServiceHandler`].
+
+use crate::events::{Event, EventQueue};
+use crate::lsps0::ser::{
+	ProtocolMessageHandler, RequestId, ResponseError, JSONRPC_INTERNAL_ERROR_ERROR_CODE,
+	JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE,
+};
+use crate::lsps5::event::LSPS5ServiceEvent;
+use crate::lsps5::msgs::{
+	LSPS5Message, LSPS5Request, LSPS5Response, ListWebhooksResponse, RemoveWebhookRequest,
+	RemoveWebhookResponse, SetWebhookRequest, SetWebhookResponse, WebhookNotification,
+	LSPS5_REMOVE_WEBHOOK_REQUEST_APP_NAME_NOT_FOUND_ERROR_CODE,
+	LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE,
+	LSPS5_SET_WEBHOOK_REQUEST_TOO_MANY_WEBHOOKS_ERROR_CODE,
+	LSPS5_SET_WEBHOOK_REQUEST_UNSUPPORTED_PROTOCOL_ERROR_CODE,
+	LSPS5_SET_WEBHOOK_REQUEST_URL_PARSE_ERROR_CODE, MAX_APP_NAME_LENGTH, MAX_WEBHOOK_URL_LENGTH,
+};
+use crate::message_queue::MessageQueue;
+use crate::prelude::{HashMap, String, ToString, Vec};
+use crate::sync::{Arc, Mutex, RwLock};
+
+use lightning::ln::msgs::{ErrorAction, LightningError};
+use lightning::util::errors::APIError;
+use lightning::util::logger::Level;
+
+use bitcoin::secp256k1::PublicKey;
+
+/// Server-side configuration options for LSPS5 webhook registration.
+#[derive(Clone, Debug)]
+pub struct LSPS5ServiceConfig {
+	/// The maximum number of webhooks a single client may register.
+	pub max_webhooks_per_client: u32,
+}
+
+impl Default for LSPS5ServiceConfig {
+	fn default() -> Self {
+		Self { max_webhooks_per_client: 10 }
+	}
+}
+
+#[derive(Default)]
+struct PeerState {
+	webhooks_by_app_name: HashMap<String, String>,
+}
+
+/// The main object allowing to send and receive LSPS5 messages.
+pub struct LSPS5ServiceHandler {
+	pending_messages: Arc<MessageQueue>,
+	pending_events: Arc<EventQueue>,
+	per_peer_state: RwLock<HashMap<PublicKey, Mutex<PeerState>>>,
+	config: LSPS5ServiceConfig,
+}
+
+impl LSPS5ServiceHandler {
+	/// Constructs a `LSPS5ServiceHandler`.
+	pub(crate) fn new(
+		pending_messages: Arc<MessageQueue>, pending_events: Arc<EventQueue>,
+		config: LSPS5ServiceConfig,
+	) -> Self {
+		Self {
+			pending_messages,
+			pending_events,
+			per_peer_state: RwLock::new(HashMap::new()),
+			config,
+		}
+	}
+
+	/// Used by LSP to notify the client with the given `counterparty_node_id` via all of its
+	/// registered webhooks.
+	///
+	/// For each registered webhook, a [`LSPS5ServiceEvent::SendWebhookNotification`] event will be
+	/// emitted that is to be delivered by the user.
+	///
+	/// Returns an error if the client hasn't registered any webhooks.
+	///
+	/// [`LSPS5ServiceEvent::SendWebhookNotification`]: crate::lsps5::event::LSPS5ServiceEvent::SendWebhookNotification
+	pub fn notify(
+		&self, counterparty_node_id: &PublicKey, notification: WebhookNotification,
+	) -> Result<(), APIError> {
+		let outer_state_lock = self.per_peer_state.read().unwrap();
+		let webhooks = outer_state_lock
+			.get(counterparty_node_id)
+			.map(|inner_state_lock| {
+				let peer_state_lock = inner_state_lock.lock().unwrap();
+				peer_state_lock
+					.webhooks_by_app_name
+					.iter()
+					.map(|(app_name, webhook)| (app_name.clone(), webhook.clone()))
+					.collect::<Vec<_>>()
+			})
+			.unwrap_or_default();
+
+		if webhooks.is_empty() {
+			return Err(APIError::APIMisuseError {
+				err: format!("No webhooks registered by counterparty {}", counterparty_node_id),
+			});
+		}
+
+		for (app_name, webhook) in webhooks {
+			self.pending_events.enqueue(Event::LSPS5Service(
+				LSPS5ServiceEvent::SendWebhookNotification {
+					counterparty_node_id: *counterparty_node_id,
+					app_name,
+					webhook,
+					notification: notification.clone(),
+				},
+			));
+		}
+
+		Ok(())
+	}
+
+	fn handle_set_webhook_request(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey, params: SetWebhookRequest,
+	) -> Result<(), LightningError> {
+		if let Err(error) = check_webhook(&params) {
+			let msg = LSPS5Message::Response(request_id, LSPS5Response::SetWebhookError(error));
+			self.pending_messages.enqueue(counterparty_node_id, msg.into());
+			return Err(LightningError {
+				err: format!(
+					"Refused to register invalid webhook {} for app {}",
+					params.webhook, params.app_name
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			});
+		}
+
+		let max_webhooks = self.config.max_webhooks_per_client;
+		let (result, response) = {
+			let mut outer_state_lock = self.per_peer_state.write().unwrap();
+			let inner_state_lock = &*outer_state_lock
+				.entry(*counterparty_node_id)
+				.or_insert(Mutex::new(PeerState::default()));
+			let mut peer_state_lock = inner_state_lock.lock().unwrap();
+			let webhooks = &mut peer_state_lock.webhooks_by_app_name;
+
+			let no_change = webhooks.get(&params.app_name) == Some(&params.webhook);
+			let is_new_app = !webhooks.contains_key(&params.app_name);
+			if is_new_app && webhooks.len() >= max_webhooks as usize {
+				let error = ResponseError {
+					code: LSPS5_SET_WEBHOOK_REQUEST_TOO_MANY_WEBHOOKS_ERROR_CODE,
+					message: "Too many webhooks".to_string(),
+					data: Some(serde_json::json!({ "max_webhooks": max_webhooks })),
+				};
+				let result = Err(LightningError {
+					err: format!(
+						"Refused to register more than {} webhooks for counterparty {}",
+						max_webhooks, counterparty_node_id
+					),
+					action: ErrorAction::IgnoreAndLog(Level::Info),
+				});
+				(result, LSPS5Response::SetWebhookError(error))
+			} else {
+				if !no_change {
+					webhooks.insert(params.app_name.clone(), params.webhook.clone());
+
+					self.pending_events.enqueue(Event::LSPS5Service(
+						LSPS5ServiceEvent::SendWebhookNotification {
+							counterparty_node_id: *counterparty_node_id,
+							app_name: params.app_name,
+							webhook: params.webhook,
+							notification: WebhookNotification::WebhookRegistered,
+						},
+					));
+				}
+
+				let response = SetWebhookResponse {
+					num_webhooks: webhooks.len() as u32,
+					max_webhooks,
+					no_change,
+				};
+				(Ok(()), LSPS5Response::SetWebhook(response))
+			}
+		};
+
+		let msg = LSPS5Message::Response(request_id, response);
+		self.pending_messages.enqueue(counterparty_node_id, msg.into());
+		result
+	}
+
+	fn handle_list_webhooks_request(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		let mut app_names = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			outer_state_lock
+				.get(counterparty_node_id)
+				.map(|inner_state_lock| {
+					let peer_state_lock = inner_state_lock.lock().unwrap();
+					peer_state_lock.webhooks_by_app_name.keys().cloned().collect::<Vec<_>>()
+				})
+				.unwrap_or_default()
+		};
+		app_names.sort();
+
+		let response =
+			ListWebhooksResponse { app_names, max_webhooks: self.config.max_webhooks_per_client };
+		let msg = LSPS5Message::Response(request_id.clone(), LSPS5Response::ListWebhooks(response));
+		if let Err(e) = self.pending_messages.try_enqueue(counterparty_node_id, msg.into()) {
+			// Only happens if `max_webhooks_per_client` is configured larger than what fits into
+			// a message.
+			let error = ResponseError {
+				code: JSONRPC_INTERNAL_ERROR_ERROR_CODE,
+				message: JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE.to_string(),
+				data: None,
+			};
+			let msg = LSPS5Message::Response(request_id, LSPS5Response::ListWebhooksError(error));
+			self.pending_messages.enqueue(counterparty_node_id, msg.into());
+			return Err(LightningError {
+				err: format!(
+					"Failed to respond to list_webhooks request from node {:?}: {}",
+					counterparty_node_id, e
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Error),
+			});
+		}
+		Ok(())
+	}
+
+	fn handle_remove_webhook_request(
+		&self, request_id: RequestId, counterparty_node_id: &PublicKey,
+		params: RemoveWebhookRequest,
+	) -> Result<(), LightningError> {
+		let removed = {
+			let outer_state_lock = self.per_peer_state.read().unwrap();
+			match outer_state_lock.get(counterparty_node_id) {
+				Some(inner_state_lock) => {
+					let mut peer_state_lock = inner_state_lock.lock().unwrap();
+					peer_state_lock.webhooks_by_app_name.remove(&params.app_name).is_some()
+				},
+				None => false,
+			}
+		};
+
+		let (result, response) = if removed {
+			(Ok(()), LSPS5Response::RemoveWebhook(RemoveWebhookResponse {}))
+		} else {
+			let error = ResponseError {
+				code: LSPS5_REMOVE_WEBHOOK_REQUEST_APP_NAME_NOT_FOUND_ERROR_CODE,
+				message: "App name not found".to_string(),
+				data: None,
+			};
+			let result = Err(LightningError {
+				err: format!(
+					"Received remove_webhook request for unknown app {} from counterparty {}",
+					params.app_name, counterparty_node_id
+				),
+				action: ErrorAction::IgnoreAndLog(Level::Info),
+			});
+			(result, LSPS5Response::RemoveWebhookError(error))
+		};
+
+		let msg = LSPS5Message::Response(request_id, response);
+		self.pending_messages.enqueue(counterparty_node_id, msg.into());
+		result
+	}
+}
+
+/// Checks the given webhook registration is acceptable as per spec, i.e., it uses a reasonably
+/// short app name and a valid HTTPS URL.
+fn check_webhook(params: &SetWebhookRequest) -> Result<(), ResponseError> {
+	if params.app_name.len() > MAX_APP_NAME_LENGTH {
+		return Err(ResponseError {
+			code: LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE,
+			message: "App name too long".to_string(),
+			data: Some(serde_json::json!({ "max_app_name_length": MAX_APP_NAME_LENGTH })),
+		});
+	}
+
+	if params.webhook.len() > MAX_WEBHOOK_URL_LENGTH {
+		return Err(ResponseError {
+			code: LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE,
+			message: "Webhook URL too long".to_string(),
+			data: Some(serde_json::json!({ "max_webhook_length": MAX_WEBHOOK_URL_LENGTH })),
+		});
+	}
+
+	let url_parse_error = || ResponseError {
+		code: LSPS5_SET_WEBHOOK_REQUEST_URL_PARSE_ERROR_CODE,
+		message: "Failed to parse webhook URL".to_string(),
+		data: None,
+	};
+
+	let (scheme, rest) = params.webhook.split_once("://").ok_or_else(url_parse_error)?;
+	let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+	if host.is_empty() || params.webhook.chars().any(|c| c.is_whitespace() || c.is_control()) {
+		return Err(url_parse_error());
+	}
+
+	if !scheme.eq_ignore_ascii_case("https") {
+		return Err(ResponseError {
+			code: LSPS5_SET_WEBHOOK_REQUEST_UNSUPPORTED_PROTOCOL_ERROR_CODE,
+			message: "Unsupported protocol: HTTPS is required".to_string(),
+			data: None,
+		});
+	}
+
+	Ok(())
+}
+
+impl ProtocolMessageHandler for LSPS5ServiceHandler {
+	type ProtocolMessage = LSPS5Message;
+	const PROTOCOL_NUMBER: Option<u16> = Some(5);
+
+	fn handle_message(
+		&self, message: Self::ProtocolMessage, counterparty_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		match message {
+			LSPS5Message::Request(request_id, request) => match request {
+				LSPS5Request::SetWebhook(params) => {
+					self.handle_set_webhook_request(request_id, counterparty_node_id, params)
+				},
+				LSPS5Request::ListWebhooks(_) => {
+					self.handle_list_webhooks_request(request_id, counterparty_node_id)
+				},
+				LSPS5Request::RemoveWebhook(params) => {
+					self.handle_remove_webhook_request(request_id, counterparty_node_id, params)
+				},
+			},
+			_ => {
+				debug_assert!(
+					false,
+					"Service handler received LSPS5 response message. This should never happen."
+				);
+				Err(LightningError { err: format!("Service handler received LSPS5 response message from node {:?}. This should never happen.", counterparty_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)})
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn request(app_name: &str, webhook: &str) -> SetWebhookRequest {
+		SetWebhookRequest { app_name: app_name.to_string(), webhook: webhook.to_string() }
+	}
+
+	#[test]
+	fn webhook_checks() {
+		assert!(check_webhook(&request("app", "https://www.example.org/push?l=1")).is_ok());
+
+		let too_long_app_name = "a".repeat(MAX_APP_NAME_LENGTH + 1);
+		let error = check_webhook(&request(&too_long_app_name, "https://example.org")).unwrap_err();
+		assert_eq!(error.code, LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE);
+
+		let too_long_url = format!("https://example.org/{}", "a".repeat(MAX_WEBHOOK_URL_LENGTH));
+		let error = check_webhook(&request("app", &too_long_url)).unwrap_err();
+		assert_eq!(error.code, LSPS5_SET_WEBHOOK_REQUEST_TOO_LONG_ERROR_CODE);
+
+		for url in ["example.org", "https://", "https:///push", "https://exa mple.org"] {
+			let error = check_webhook(&request("app", url)).unwrap_err();
+			assert_eq!(error.code, LSPS5_SET_WEBHOOK_REQUEST_URL_PARSE_ERROR_CODE);
+		}
+
+		let error = check_webhook(&request("app", "http://example.org")).unwrap_err();
+		assert_eq!(error.code, LSPS5_SET_WEBHOOK_REQUEST_UNSUPPORTED_PROTOCOL_ERROR_CODE);
+	}
+}
@@ -1,10 +1,17 @@
+use crate::custom::{
+	CustomMessage, CustomProtocolHandler, CustomProtocolHandlers, CustomRequest, CustomResponse,
+};
 use crate::events::{Event, EventQueue};
 use crate::lsps0::client::LSPS0ClientHandler;
-use crate::lsps0::msgs::LSPS0Message;
+use crate::lsps0::discovery::{find_lsps, DiscoveredLSP, LSPDirectory, LSPS_FEATURE_BIT};
+use crate::lsps0::msgs::{
+	LSPS0Message, LSPS0Request, LSPS0Response, ListProtocolsRequest, ListProtocolsResponse,
+};
 use crate::lsps0::ser::{
-	LSPSMessage, LSPSMethod, ProtocolMessageHandler, RawLSPSMessage, RequestId, ResponseError,
-	JSONRPC_INVALID_MESSAGE_ERROR_CODE, JSONRPC_INVALID_MESSAGE_ERROR_MESSAGE,
-	LSPS_MESSAGE_TYPE_ID,
+	DeserializationMode, LSPSMessage, LSPSMethod, LSPSParseError, ProtocolMessageHandler,
+	RawLSPSMessage, RequestId, ResponseError, JSONRPC_INTERNAL_ERROR_ERROR_CODE,
+	JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE, JSONRPC_INVALID_MESSAGE_ERROR_CODE,
+	JSONRPC_INVALID_MESSAGE_ERROR_MESSAGE, LSPS_MESSAGE_TYPE_ID,
 };
 use crate::lsps0::service::LSPS0ServiceHandler;
 use crate::message_queue::MessageQueue;
@@ -12,15 +19,23 @@ use crate::message_queue::MessageQueue;
 #[cfg(lsps1)]
 use crate::lsps1::client::{LSPS1ClientConfig, LSPS1ClientHandler};
 #[cfg(lsps1)]
-use crate::lsps1::msgs::LSPS1Message;
+use crate::lsps1::msgs::{
+	GetInfoRequest as LSPS1GetInfoRequest, LSPS1Message, LSPS1Request, LSPS1Response,
+};
 #[cfg(lsps1)]
 use crate::lsps1::service::{LSPS1ServiceConfig, LSPS1ServiceHandler};
 
 use crate::lsps2::client::{LSPS2ClientConfig, LSPS2ClientHandler};
-use crate::lsps2::msgs::LSPS2Message;
+use crate::lsps2::msgs::{
+	GetInfoRequest as LSPS2GetInfoRequest, LSPS2Message, LSPS2Request, LSPS2Response,
+};
 use crate::lsps2::service::{LSPS2ServiceConfig, LSPS2ServiceHandler};
-use crate::prelude::{HashMap, HashSet, ToString, Vec};
+use crate::lsps5::client::{LSPS5ClientConfig, LSPS5ClientHandler};
+use crate::lsps5::msgs::LSPS5Message;
+use crate::lsps5::service::{LSPS5ServiceConfig, LSPS5ServiceHandler};
+use crate::prelude::{HashMap, HashSet, String, ToString, Vec};
 use crate::sync::{Arc, Mutex, RwLock};
+use crate::utils;
 
 use lightning::chain::{self, BestBlock, Confirm, Filter, Listen};
 use lightning::ln::channelmanager::{AChannelManager, ChainParameters};
@@ -28,15 +43,19 @@ use lightning::ln::features::{InitFeatures, NodeFeatures};
 use lightning::ln::msgs::{ErrorAction, LightningError};
 use lightning::ln::peer_handler::CustomMessageHandler;
 use lightning::ln::wire::CustomMessageReader;
+use lightning::routing::gossip::NetworkGraph;
 use lightning::sign::EntropySource;
-use lightning::util::logger::Level;
+use lightning::util::errors::APIError;
+use lightning::util::logger::{Level, Logger};
 use lightning::util::ser::Readable;
 
 use bitcoin::secp256k1::PublicKey;
 
+use core::fmt;
 use core::ops::Deref;
 
-const LSPS_FEATURE_BIT: usize = 729;
+// The protocol numbers of the LSPS protocols implemented by this crate.
+const BUILT_IN_PROTOCOL_NUMBERS: [u16; 4] = [0, 1, 2, 5];
 
 /// A server-side configuration for [`LiquidityManager`].
 ///
@@ -49,11 +68,33 @@ pub struct LiquidityServiceConfig {
 	/// Optional server-side configuration for JIT channels
 	/// should you want to support them.
 	pub lsps2_service_config: Option<LSPS2ServiceConfig>,
+	/// Optional server-side configuration for LSPS5 webhook registration.
+	pub lsps5_service_config: Option<LSPS5ServiceConfig>,
 	/// Controls whether the liquidity service should be advertised via setting the feature bit in
 	/// node announcment and the init message.
 	pub advertise_service: bool,
 }
 
+/// An error returned by [`LiquidityManager::new`] if the given configuration is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LiquidityConfigError {
+	/// The LSPS1 service configuration is incomplete or inconsistent.
+	InvalidLSPS1ServiceConfig {
+		/// A human-readable error message.
+		err: String,
+	},
+}
+
+impl fmt::Display for LiquidityConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LiquidityConfigError::InvalidLSPS1ServiceConfig { err } => {
+				write!(f, "Invalid LSPS1 service configuration: {}", err)
+			},
+		}
+	}
+}
+
 /// A client-side configuration for [`LiquidityManager`].
 ///
 /// Allows end-user to configure options when using the [`LiquidityManager`]
@@ -64,6 +105,8 @@ pub struct LiquidityClientConfig {
 	pub lsps1_client_config: Option<LSPS1ClientConfig>,
 	/// Optional client-side configuration for JIT channels.
 	pub lsps2_client_config: Option<LSPS2ClientConfig>,
+	/// Optional client-side configuration for LSPS5 webhook registration.
+	pub lsps5_client_config: Option<LSPS5ClientConfig>,
 }
 
 /// The main interface into LSP functionality.
@@ -90,9 +133,15 @@ where
 	CM::Target: AChannelManager,
 	C::Target: Filter,
 {
+	entropy_source: ES,
 	pending_messages: Arc<MessageQueue>,
 	pending_events: Arc<EventQueue>,
 	request_id_to_method_map: Mutex<HashMap<RequestId, LSPSMethod>>,
+	custom_protocol_handlers: RwLock<CustomProtocolHandlers>,
+	deserialization_mode: RwLock<DeserializationMode>,
+	lsp_directory: Mutex<LSPDirectory>,
+	// The requests we issued to fill the `lsp_directory`, mapped to the LSP they were sent to.
+	lsp_directory_requests: Mutex<HashMap<RequestId, PublicKey>>,
 	// We ignore peers if they send us bogus data.
 	ignored_peers: RwLock<HashSet<PublicKey>>,
 	lsps0_client_handler: LSPS0ClientHandler<ES>,
@@ -103,6 +152,8 @@ where
 	lsps1_client_handler: Option<LSPS1ClientHandler<ES, CM, C>>,
 	lsps2_service_handler: Option<LSPS2ServiceHandler<CM>>,
 	lsps2_client_handler: Option<LSPS2ClientHandler<ES>>,
+	lsps5_service_handler: Option<LSPS5ServiceHandler>,
+	lsps5_client_handler: Option<LSPS5ClientHandler<ES>>,
 	service_config: Option<LiquidityServiceConfig>,
 	_client_config: Option<LiquidityClientConfig>,
 	best_block: Option<RwLock<BestBlock>>,
@@ -119,11 +170,13 @@ where
 	///
 	/// Sets up the required protocol message handlers based on the given
 	/// [`LiquidityClientConfig`] and [`LiquidityServiceConfig`].
+	///
+	/// Returns an error if the given configuration is invalid.
 	pub fn new(
 		entropy_source: ES, channel_manager: CM, chain_source: Option<C>,
 		chain_params: Option<ChainParameters>, service_config: Option<LiquidityServiceConfig>,
 		client_config: Option<LiquidityClientConfig>,
-	) -> Self
+	) -> Result<Self, LiquidityConfigError>
 where {
 		let pending_messages = Arc::new(MessageQueue::new());
 		let pending_events = Arc::new(EventQueue::new());
@@ -135,11 +188,20 @@ where {
 			Arc::clone(&pending_events),
 		);
 
-		let lsps0_service_handler = if service_config.is_some() {
-			Some(LSPS0ServiceHandler::new(vec![], Arc::clone(&pending_messages)))
-		} else {
-			None
-		};
+		let lsps0_service_handler = service_config.as_ref().map(|config| {
+			let mut protocols = Vec::new();
+			#[cfg(lsps1)]
+			if config.lsps1_service_config.is_some() {
+				protocols.push(1);
+			}
+			if config.lsps2_service_config.is_some() {
+				protocols.push(2);
+			}
+			if config.lsps5_service_config.is_some() {
+				protocols.push(5);
+			}
+			LSPS0ServiceHandler::new(protocols, Arc::clone(&pending_messages))
+		});
 
 		let lsps2_client_handler = client_config.as_ref().and_then(|config| {
 			config.lsps2_client_config.map(|config| {
@@ -162,6 +224,26 @@ where {
 			})
 		});
 
+		let lsps5_client_handler = client_config.as_ref().and_then(|config| {
+			config.lsps5_client_config.map(|config| {
+				LSPS5ClientHandler::new(
+					entropy_source.clone(),
+					Arc::clone(&pending_messages),
+					Arc::clone(&pending_events),
+					config,
+				)
+			})
+		});
+		let lsps5_service_handler = service_config.as_ref().and_then(|config| {
+			config.lsps5_service_config.as_ref().map(|config| {
+				LSPS5ServiceHandler::new(
+					Arc::clone(&pending_messages),
+					Arc::clone(&pending_events),
+					config.clone(),
+				)
+			})
+		});
+
 		#[cfg(lsps1)]
 		let lsps1_client_handler = client_config.as_ref().and_then(|config| {
 			config.lsps1_client_config.as_ref().map(|config| {
@@ -177,8 +259,10 @@ where {
 		});
 
 		#[cfg(lsps1)]
-		let lsps1_service_handler = service_config.as_ref().and_then(|config| {
-			config.lsps1_service_config.as_ref().map(|config| {
+		let lsps1_service_handler = service_config
+			.as_ref()
+			.and_then(|config| config.lsps1_service_config.as_ref())
+			.map(|config| {
 				LSPS1ServiceHandler::new(
 					entropy_source.clone(),
 					Arc::clone(&pending_messages),
@@ -188,12 +272,18 @@ where {
 					config.clone(),
 				)
 			})
-		});
+			.transpose()
+			.map_err(|err| LiquidityConfigError::InvalidLSPS1ServiceConfig { err })?;
 
-		Self {
+		Ok(Self {
+			entropy_source,
 			pending_messages,
 			pending_events,
 			request_id_to_method_map: Mutex::new(HashMap::new()),
+			custom_protocol_handlers: RwLock::new(HashMap::new()),
+			deserialization_mode: RwLock::new(DeserializationMode::default()),
+			lsp_directory: Mutex::new(LSPDirectory::default()),
+			lsp_directory_requests: Mutex::new(HashMap::new()),
 			ignored_peers,
 			lsps0_client_handler,
 			lsps0_service_handler,
@@ -203,11 +293,13 @@ where {
 			lsps1_service_handler,
 			lsps2_client_handler,
 			lsps2_service_handler,
+			lsps5_client_handler,
+			lsps5_service_handler,
 			service_config,
 			_client_config: client_config,
 			best_block: chain_params.map(|chain_params| RwLock::new(chain_params.best_block)),
 			_chain_source: chain_source,
-		}
+		})
 	}
 
 	/// Returns a reference to the LSPS0 client-side handler.
@@ -242,6 +334,291 @@ where {
 		self.lsps2_service_handler.as_ref()
 	}
 
+	/// Returns a reference to the LSPS5 client-side handler.
+	pub fn lsps5_client_handler(&self) -> Option<&LSPS5ClientHandler<ES>> {
+		self.lsps5_client_handler.as_ref()
+	}
+
+	/// Returns a reference to the LSPS5 server-side handler.
+	pub fn lsps5_service_handler(&self) -> Option<&LSPS5ServiceHandler> {
+		self.lsps5_service_handler.as_ref()
+	}
+
+	/// Finds the LSPs in the given [`NetworkGraph`] and queries them for the protocols they
+	/// support and, if configured, for their LSPS1 options and LSPS2 fees.
+	///
+	/// For each LSP found, `connect` is called and should return whether we're connected to it,
+	/// e.g., after connecting to one of its [`DiscoveredLSP::addresses`] via the [`PeerManager`].
+	/// LSPs we're connected to are queried via [`LiquidityManager::query_lsp`]. Returns all LSPs
+	/// found.
+	///
+	/// [`PeerManager`]: lightning::ln::peer_handler::PeerManager
+	pub fn discover_lsps<L: Deref>(
+		&self, network_graph: &NetworkGraph<L>, mut connect: impl FnMut(&DiscoveredLSP) -> bool,
+	) -> Vec<DiscoveredLSP>
+	where
+		L::Target: Logger,
+	{
+		let lsps = find_lsps(network_graph);
+		for lsp in &lsps {
+			if connect(lsp) {
+				self.query_lsp(lsp.node_id);
+			}
+		}
+		lsps
+	}
+
+	/// Queries the given LSP, which we need to be connected to, for the protocols it supports.
+	///
+	/// Once it answered, we'll query it for its LSPS1 options and LSPS2 fees if it supports the
+	/// respective protocol and the corresponding client handler is configured. The results are
+	/// gathered in the [`LSPDirectory`] returned by [`LiquidityManager::lsp_directory`].
+	///
+	/// These queries are independent of the client handlers, i.e., they don't create any state
+	/// within them and no events are emitted for their responses.
+	pub fn query_lsp(&self, counterparty_node_id: PublicKey) {
+		self.lsp_directory.lock().unwrap().insert(counterparty_node_id);
+
+		let request = LSPS0Request::ListProtocols(ListProtocolsRequest {});
+		self.send_lsp_directory_request(counterparty_node_id, |request_id| {
+			LSPS0Message::Request(request_id, request).into()
+		});
+	}
+
+	/// Returns a snapshot of what we learned about the LSPs we queried via
+	/// [`LiquidityManager::query_lsp`] or [`LiquidityManager::discover_lsps`].
+	pub fn lsp_directory(&self) -> LSPDirectory {
+		self.lsp_directory.lock().unwrap().clone()
+	}
+
+	fn send_lsp_directory_request(
+		&self, counterparty_node_id: PublicKey, request: impl FnOnce(RequestId) -> LSPSMessage,
+	) {
+		let request_id = utils::generate_request_id(&self.entropy_source);
+
+		// We record the request before sending it so that its response can't be processed
+		// before.
+		self.lsp_directory_requests
+			.lock()
+			.unwrap()
+			.insert(request_id.clone(), counterparty_node_id);
+		self.pending_messages.enqueue(&counterparty_node_id, request(request_id));
+	}
+
+	/// Records the answers of LSPs we queried for the [`LSPDirectory`].
+	///
+	/// Returns [`Option::None`] if the message isn't a response to a request issued by
+	/// [`LiquidityManager::query_lsp`], and the protocols we still need to query the sender for
+	/// otherwise.
+	fn update_lsp_directory(
+		&self, msg: &LSPSMessage, sender_node_id: &PublicKey,
+	) -> Option<Vec<u16>> {
+		let request_id = match msg {
+			LSPSMessage::RequestError(request_id, _) => request_id,
+			LSPSMessage::LSPS0(LSPS0Message::Response(request_id, _)) => request_id,
+			#[cfg(lsps1)]
+			LSPSMessage::LSPS1(LSPS1Message::Response(request_id, _)) => request_id,
+			LSPSMessage::LSPS2(LSPS2Message::Response(request_id, _)) => request_id,
+			_ => return None,
+		};
+
+		{
+			let mut lsp_directory_requests = self.lsp_directory_requests.lock().unwrap();
+			match lsp_directory_requests.get(request_id) {
+				Some(node_id) if node_id == sender_node_id => {
+					lsp_directory_requests.remove(request_id);
+				},
+				_ => return None,
+			}
+		}
+
+		let mut lsp_directory = self.lsp_directory.lock().unwrap();
+		let entry = match lsp_directory.entry_mut(sender_node_id) {
+			Some(entry) => entry,
+			None => return Some(Vec::new()),
+		};
+
+		match msg {
+			LSPSMessage::LSPS0(LSPS0Message::Response(
+				_,
+				LSPS0Response::ListProtocols(ListProtocolsResponse { protocols }),
+			)) => {
+				entry.protocols = Some(protocols.clone());
+				return Some(protocols.clone());
+			},
+			#[cfg(lsps1)]
+			LSPSMessage::LSPS1(LSPS1Message::Response(_, LSPS1Response::GetInfo(response))) => {
+				entry.lsps1_options = Some(response.options.clone());
+			},
+			LSPSMessage::LSPS2(LSPS2Message::Response(_, LSPS2Response::GetInfo(response))) => {
+				entry.lsps2_opening_fee_params_menu =
+					Some(response.opening_fee_params_menu.clone());
+			},
+			// Error responses leave the directory entry incomplete.
+			_ => {},
+		}
+		Some(Vec::new())
+	}
+
+	fn query_lsp_protocols(&self, counterparty_node_id: PublicKey, protocols: Vec<u16>) {
+		#[cfg(lsps1)]
+		if protocols.contains(&1) && self.lsps1_client_handler.is_some() {
+			let request = LSPS1Request::GetInfo(LSPS1GetInfoRequest {});
+			self.send_lsp_directory_request(counterparty_node_id, |request_id| {
+				LSPS1Message::Request(request_id, request).into()
+			});
+		}
+		if protocols.contains(&2) && self.lsps2_client_handler.is_some() {
+			let request = LSPS2Request::GetInfo(LSPS2GetInfoRequest { token: None });
+			self.send_lsp_directory_request(counterparty_node_id, |request_id| {
+				LSPS2Message::Request(request_id, request).into()
+			});
+		}
+	}
+
+	/// Sets how strictly received messages are checked against the JSON-RPC 2.0 and LSPS0
+	/// specifications.
+	///
+	/// Defaults to [`DeserializationMode::Permissive`]. Under [`DeserializationMode::Strict`],
+	/// requests deviating from the specifications are answered with a JSON-RPC `Invalid Request`
+	/// error detailing the violation, while any other deviating message is treated as invalid.
+	pub fn set_deserialization_mode(&self, mode: DeserializationMode) {
+		*self.deserialization_mode.write().unwrap() = mode;
+	}
+
+	/// Registers a handler for a custom protocol.
+	///
+	/// Incoming requests for any of the handler's [`method_names`] will be passed to the handler,
+	/// and, if we're configured as a service, its [`protocol_number`] will be advertised via
+	/// LSPS0's `list_protocols`.
+	///
+	/// Returns an error if the protocol number or any of the method names are already taken by a
+	/// built-in or a previously registered protocol.
+	///
+	/// [`method_names`]: CustomProtocolHandler::method_names
+	/// [`protocol_number`]: CustomProtocolHandler::protocol_number
+	pub fn register_custom_protocol_handler(
+		&self, handler: Arc<dyn CustomProtocolHandler + Send + Sync>,
+	) -> Result<(), APIError> {
+		let protocol_number = handler.protocol_number();
+		let method_names = handler.method_names();
+
+		let mut custom_protocol_handlers = self.custom_protocol_handlers.write().unwrap();
+		if BUILT_IN_PROTOCOL_NUMBERS.contains(&protocol_number)
+			|| custom_protocol_handlers.values().any(|h| h.protocol_number() == protocol_number)
+		{
+			return Err(APIError::APIMisuseError {
+				err: format!("Protocol number {} is already registered", protocol_number),
+			});
+		}
+
+		if method_names.is_empty() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Protocol {} doesn't handle any methods", protocol_number),
+			});
+		}
+
+		for (i, method) in method_names.iter().enumerate() {
+			if method.parse::<LSPSMethod>().is_ok()
+				|| custom_protocol_handlers.contains_key(method)
+				|| method_names[..i].contains(method)
+			{
+				return Err(APIError::APIMisuseError {
+					err: format!("Method {} is already registered", method),
+				});
+			}
+		}
+
+		for method in method_names {
+			custom_protocol_handlers.insert(method, Arc::clone(&handler));
+		}
+
+		if let Some(lsps0_service_handler) = &self.lsps0_service_handler {
+			lsps0_service_handler.add_protocol(protocol_number);
+		}
+
+		Ok(())
+	}
+
+	/// Sends a request of a custom protocol to the given counterparty.
+	///
+	/// The response will be passed to [`CustomProtocolHandler::handle_response`] of the handler
+	/// registered for `method`.
+	///
+	/// Returns an error if no handler is registered for `method`.
+	pub fn send_custom_request(
+		&self, counterparty_node_id: PublicKey, method: String, params: serde_json::Value,
+	) -> Result<RequestId, APIError> {
+		if !self.custom_protocol_handlers.read().unwrap().contains_key(&method) {
+			return Err(APIError::APIMisuseError {
+				err: format!("No custom protocol handler registered for method {}", method),
+			});
+		}
+
+		let request_id = utils::generate_request_id(&self.entropy_source);
+		let msg = CustomMessage::Request(request_id.clone(), CustomRequest { method, params });
+		self.pending_messages.try_enqueue(&counterparty_node_id, msg.into())?;
+
+		Ok(request_id)
+	}
+
+	fn handle_custom_message(
+		&self, msg: CustomMessage, sender_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		let method = match &msg {
+			CustomMessage::Request(_, request) => &request.method,
+			CustomMessage::Response(_, response) => &response.method,
+		};
+
+		let handler = self.custom_protocol_handlers.read().unwrap().get(method).cloned();
+		let handler = handler.ok_or_else(|| LightningError {
+			err: format!(
+				"Received message for unknown custom method {} from node {:?}",
+				method, sender_node_id
+			),
+			action: ErrorAction::IgnoreAndLog(Level::Info),
+		})?;
+
+		match msg {
+			CustomMessage::Request(request_id, request) => {
+				let result = handler.handle_request(
+					&request_id,
+					&request.method,
+					request.params,
+					sender_node_id,
+				);
+				let response = CustomResponse { method: request.method.clone(), result };
+				let msg = CustomMessage::Response(request_id.clone(), response);
+				match self.pending_messages.try_enqueue(sender_node_id, msg.into()) {
+					Ok(()) => Ok(()),
+					Err(e) => {
+						let result = Err(ResponseError {
+							code: JSONRPC_INTERNAL_ERROR_ERROR_CODE,
+							message: JSONRPC_INTERNAL_ERROR_ERROR_MESSAGE.to_string(),
+							data: None,
+						});
+						let response = CustomResponse { method: request.method, result };
+						let msg = CustomMessage::Response(request_id, response);
+						self.pending_messages.enqueue(sender_node_id, msg.into());
+						Err(LightningError {
+							err: format!(
+								"Failed to respond to custom request from node {:?}: {}",
+								sender_node_id, e
+							),
+							action: ErrorAction::IgnoreAndLog(Level::Error),
+						})
+					},
+				}
+			},
+			CustomMessage::Response(request_id, response) => handler.handle_response(
+				request_id,
+				&response.method,
+				response.result,
+				sender_node_id,
+			),
+		}
+	}
+
 	/// Allows to set a callback that will be called after new messages are pushed to the message
 	/// queue.
 	///
@@ -393,11 +770,32 @@ where {
 
 	fn handle_lsps_message(
 		&self, msg: LSPSMessage, sender_node_id: &PublicKey,
+	) -> Result<(), lightning::ln::msgs::LightningError> {
+		// Responses to our directory queries are consumed here, as the client handlers don't know
+		// about the corresponding requests.
+		if let Some(protocols_to_query) = self.update_lsp_directory(&msg, sender_node_id) {
+			self.query_lsp_protocols(*sender_node_id, protocols_to_query);
+			return Ok(());
+		}
+		self.dispatch_lsps_message(msg, sender_node_id)
+	}
+
+	fn dispatch_lsps_message(
+		&self, msg: LSPSMessage, sender_node_id: &PublicKey,
 	) -> Result<(), lightning::ln::msgs::LightningError> {
 		match msg {
 			LSPSMessage::Invalid(_error) => {
 				return Err(LightningError { err: format!("{} did not understand a message we previously sent, maybe they don't support a protocol we are trying to use?", sender_node_id), action: ErrorAction::IgnoreAndLog(Level::Error)});
 			},
+			LSPSMessage::RequestError(request_id, _error) => {
+				return Err(LightningError {
+					err: format!(
+						"Received unexpected error response for request {:?} from node {:?}",
+						request_id, sender_node_id
+					),
+					action: ErrorAction::IgnoreAndLog(Level::Info),
+				});
+			},
 			LSPSMessage::LSPS0(msg @ LSPS0Message::Response(..)) => {
 				self.lsps0_client_handler.handle_message(msg, sender_node_id)?;
 			},
@@ -449,6 +847,29 @@ where {
 					},
 				}
 			},
+			LSPSMessage::LSPS5(msg @ LSPS5Message::Response(..)) => {
+				match &self.lsps5_client_handler {
+					Some(lsps5_client_handler) => {
+						lsps5_client_handler.handle_message(msg, sender_node_id)?;
+					},
+					None => {
+						return Err(LightningError { err: format!("Received LSPS5 response message without LSPS5 client handler configured. From node = {:?}", sender_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)});
+					},
+				}
+			},
+			LSPSMessage::LSPS5(msg @ LSPS5Message::Request(..)) => {
+				match &self.lsps5_service_handler {
+					Some(lsps5_service_handler) => {
+						lsps5_service_handler.handle_message(msg, sender_node_id)?;
+					},
+					None => {
+						return Err(LightningError { err: format!("Received LSPS5 request message without LSPS5 service handler configured. From node = {:?}", sender_node_id), action: ErrorAction::IgnoreAndLog(Level::Info)});
+					},
+				}
+			},
+			LSPSMessage::Custom(msg) => {
+				self.handle_custom_message(msg, sender_node_id)?;
+			},
 		}
 		Ok(())
 	}
@@ -484,7 +905,7 @@ where
 		&self, msg: Self::CustomMessage, sender_node_id: &PublicKey,
 	) -> Result<(), lightning::ln::msgs::LightningError> {
 		{
-			if self.ignored_peers.read().unwrap().contains(&sender_node_id) {
+			if self.ignored_peers.read().unwrap().contains(sender_node_id) {
 				let err = format!("Ignoring message from peer {}.", sender_node_id);
 				return Err(LightningError {
 					err,
@@ -496,22 +917,46 @@ where
 		let message = {
 			{
 				let mut request_id_to_method_map = self.request_id_to_method_map.lock().unwrap();
-				LSPSMessage::from_str_with_id_map(&msg.payload, &mut request_id_to_method_map)
+				let custom_protocol_handlers = self.custom_protocol_handlers.read().unwrap();
+				let mode = *self.deserialization_mode.read().unwrap();
+				LSPSMessage::from_str_with_id_map(
+					&msg.payload,
+					&mut request_id_to_method_map,
+					&custom_protocol_handlers,
+					mode,
+				)
 			}
-			.map_err(|_| {
-				let error = ResponseError {
-					code: JSONRPC_INVALID_MESSAGE_ERROR_CODE,
-					message: JSONRPC_INVALID_MESSAGE_ERROR_MESSAGE.to_string(),
-					data: None,
-				};
-
-				self.pending_messages.enqueue(sender_node_id, LSPSMessage::Invalid(error));
-				self.ignored_peers.write().unwrap().insert(*sender_node_id);
-				let err = format!(
-					"Failed to deserialize invalid LSPS message. Ignoring peer {} from now on.",
-					sender_node_id
-				);
-				LightningError { err, action: ErrorAction::IgnoreAndLog(Level::Info) }
+			.map_err(|e| match e {
+				LSPSParseError::InvalidRequest(request_id, error) => {
+					let err = format!(
+						"Failed to process request {:?} from peer {}: {}",
+						request_id, sender_node_id, error.message
+					);
+					let msg = LSPSMessage::RequestError(request_id.clone(), error.clone());
+					if self.pending_messages.try_enqueue(sender_node_id, msg).is_err() {
+						// The error's data may echo parts of the request, so we leave it out if the
+						// response would be too large otherwise.
+						let error = ResponseError { data: None, ..error };
+						let msg = LSPSMessage::RequestError(request_id, error);
+						self.pending_messages.enqueue(sender_node_id, msg);
+					}
+					LightningError { err, action: ErrorAction::IgnoreAndLog(Level::Info) }
+				},
+				LSPSParseError::InvalidMessage(e) => {
+					let error = ResponseError {
+						code: JSONRPC_INVALID_MESSAGE_ERROR_CODE,
+						message: JSONRPC_INVALID_MESSAGE_ERROR_MESSAGE.to_string(),
+						data: None,
+					};
+
+					self.pending_messages.enqueue(sender_node_id, LSPSMessage::Invalid(error));
+					self.ignored_peers.write().unwrap().insert(*sender_node_id);
+					let err = format!(
+						"Failed to deserialize invalid LSPS message: {}. Ignoring peer {} from now on.",
+						e, sender_node_id
+					);
+					LightningError { err, action: ErrorAction::IgnoreAndLog(Level::Info) }
+				},
 			})?
 		};
 
@@ -535,10 +980,15 @@ where
 
 		pending_messages
 			.into_iter()
-			.filter_map(|(public_key, msg)| {
-				serde_json::to_string(&msg)
-					.ok()
-					.and_then(|payload| Some((public_key, RawLSPSMessage { payload })))
+			.filter_map(|(public_key, msg)| match msg.to_payload() {
+				Ok(payload) => Some((public_key, RawLSPSMessage { payload })),
+				Err(e) => {
+					// Messages whose size depends on user- or counterparty-provided data are
+					// checked before they are enqueued, with the error being returned to whoever
+					// created them. Hence, this is unreachable.
+					debug_assert!(false, "Dropping outbound LSPS message: {}", e);
+					None
+				},
 			})
 			.collect()
 	}
@@ -629,8 +1079,14 @@ where
 		// confirmed at a height <= the one we now unconfirmed.
 	}
 
-	fn best_block_updated(&self, _header: &bitcoin::block::Header, _height: u32) {
-		// TODO: Call best_block_updated on all sub-modules that require it, e.g., LSPS1MessageHandler.
+	fn best_block_updated(&self, header: &bitcoin::block::Header, height: u32) {
+		#[cfg(lsps1)]
+		if let Some(lsps1_service_handler) = &self.lsps1_service_handler {
+			lsps1_service_handler.best_block_updated(header, height);
+		}
+		if let Some(lsps2_client_handler) = &self.lsps2_client_handler {
+			lsps2_client_handler.best_block_updated(header, height);
+		}
 	}
 
 	fn get_relevant_txids(&self) -> Vec<(bitcoin::Txid, u32, Option<bitcoin::BlockHash>)> {
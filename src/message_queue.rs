@@ -1,6 +1,6 @@
 //! Holds types and traits used to implement message queues for [`LSPSMessage`]s.
 
-use crate::lsps0::ser::LSPSMessage;
+use crate::lsps0::ser::{LSPSMessage, MessageTooLargeError};
 use crate::prelude::{Box, Vec, VecDeque};
 use crate::sync::{Mutex, RwLock};
 
@@ -38,6 +38,20 @@ impl MessageQueue {
 		self.queue.lock().unwrap().drain(..).collect()
 	}
 
+	/// Enqueues the given message, failing if it would exceed [`MAX_LSPS_MESSAGE_SIZE`] once
+	/// serialized.
+	///
+	/// Should be used for messages whose size depends on user- or counterparty-provided data.
+	///
+	/// [`MAX_LSPS_MESSAGE_SIZE`]: crate::lsps0::ser::MAX_LSPS_MESSAGE_SIZE
+	pub(crate) fn try_enqueue(
+		&self, counterparty_node_id: &PublicKey, msg: LSPSMessage,
+	) -> Result<(), MessageTooLargeError> {
+		msg.to_payload()?;
+		self.enqueue(counterparty_node_id, msg);
+		Ok(())
+	}
+
 	pub(crate) fn enqueue(&self, counterparty_node_id: &PublicKey, msg: LSPSMessage) {
 		{
 			let mut queue = self.queue.lock().unwrap();
@@ -0,0 +1,197 @@
+//! A data-driven conformance suite for the (de-)serialization of LSPS messages.
+//!
+//! Every `*.json` file below `tests/fixtures/conformance/<protocol>/` describes a single message:
+//!
+//! ```json
+//! {
+//!   "description": "A human-readable description of the fixture",
+//!   "request_method": "lsps2.get_info",
+//!   "message": { "jsonrpc": "2.0", "id": "...", "result": { ... } },
+//!   "canonical": { ... }
+//! }
+//! ```
+//!
+//! Responses and errors need to set `request_method` to the method of the request they answer, as
+//! it's not part of the message itself. If our serialization of the message differs from
+//! `message`, e.g., as it includes optional fields, the expected serialization is given via
+//! `canonical`.
+//!
+//! Each message is deserialized in strict mode, serialized again, and compared against the
+//! expected serialization, before the serialization is deserialized and compared once more.
+//! Fixtures for protocols that aren't compiled in are skipped.
+
+use crate::lsps0::ser::{DeserializationMode, LSPSMessage, LSPSMethod, RequestId};
+use crate::prelude::{HashMap, HashSet, String, ToString, Vec};
+
+use serde_json::Value;
+
+use core::str::FromStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/conformance");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Kind {
+	Request,
+	Response,
+	Error,
+}
+
+struct Fixture {
+	path: PathBuf,
+	request_method: Option<String>,
+	message: Value,
+	canonical: Option<Value>,
+}
+
+fn enabled_protocols() -> Vec<&'static str> {
+	let mut protocols = vec!["lsps0", "lsps2", "lsps5"];
+	if cfg!(lsps1) {
+		protocols.push("lsps1");
+	}
+	protocols
+}
+
+fn built_in_methods() -> Vec<LSPSMethod> {
+	vec![
+		LSPSMethod::LSPS0ListProtocols,
+		#[cfg(lsps1)]
+		LSPSMethod::LSPS1GetInfo,
+		#[cfg(lsps1)]
+		LSPSMethod::LSPS1CreateOrder,
+		#[cfg(lsps1)]
+		LSPSMethod::LSPS1GetOrder,
+		LSPSMethod::LSPS2GetInfo,
+		LSPSMethod::LSPS2Buy,
+		LSPSMethod::LSPS5SetWebhook,
+		LSPSMethod::LSPS5ListWebhooks,
+		LSPSMethod::LSPS5RemoveWebhook,
+	]
+}
+
+fn load_fixtures(dir: &Path) -> Vec<Fixture> {
+	let mut paths = fs::read_dir(dir)
+		.unwrap_or_else(|e| panic!("Failed to read {}: {}", dir.display(), e))
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+		.collect::<Vec<_>>();
+	paths.sort();
+
+	paths
+		.into_iter()
+		.map(|path| {
+			let contents = fs::read_to_string(&path).unwrap();
+			let mut fixture: Value = serde_json::from_str(&contents)
+				.unwrap_or_else(|e| panic!("Invalid fixture {}: {}", path.display(), e));
+			assert!(fixture["description"].is_string(), "{}: missing description", path.display());
+			let request_method = fixture["request_method"].as_str().map(|m| m.to_string());
+			let message = fixture["message"].take();
+			assert!(message.is_object(), "{}: missing message", path.display());
+			let canonical = Some(fixture["canonical"].take()).filter(|c| !c.is_null());
+			Fixture { path, request_method, message, canonical }
+		})
+		.collect()
+}
+
+fn parse(
+	json: &str, request_id: &RequestId, request_method: &Option<LSPSMethod>,
+) -> Result<LSPSMessage, String> {
+	let mut request_id_to_method_map = HashMap::new();
+	if let Some(method) = request_method {
+		request_id_to_method_map.insert(request_id.clone(), method.clone());
+	}
+	let message = LSPSMessage::from_str_with_id_map(
+		json,
+		&mut request_id_to_method_map,
+		&HashMap::new(),
+		DeserializationMode::Strict,
+	)
+	.map_err(|e| format!("{:?}", e))?;
+	assert!(request_id_to_method_map.is_empty(), "Request id mapping wasn't consumed");
+	Ok(message)
+}
+
+fn check_fixture(fixture: &Fixture) -> (LSPSMethod, Kind) {
+	let path = fixture.path.display();
+	let request_id = RequestId(
+		fixture.message["id"]
+			.as_str()
+			.unwrap_or_else(|| panic!("{}: missing id", path))
+			.to_string(),
+	);
+	let request_method = fixture.request_method.as_ref().map(|method| {
+		LSPSMethod::from_str(method).unwrap_or_else(|_| panic!("{}: unknown method", path))
+	});
+
+	let kind = match (
+		fixture.message.get("method"),
+		fixture.message.get("result"),
+		fixture.message.get("error"),
+	) {
+		(Some(_), None, None) => Kind::Request,
+		(None, Some(_), None) => Kind::Response,
+		(None, None, Some(_)) => Kind::Error,
+		_ => panic!("{}: exactly one of method, result, or error required", path),
+	};
+
+	let json = fixture.message.to_string();
+	let message = parse(&json, &request_id, &request_method)
+		.unwrap_or_else(|e| panic!("{}: failed to deserialize: {}", path, e));
+
+	let method = match kind {
+		Kind::Request => {
+			assert!(request_method.is_none(), "{}: requests can't set request_method", path);
+			let (id, method) = message
+				.get_request_id_and_method()
+				.unwrap_or_else(|| panic!("{}: not deserialized as a request", path));
+			assert_eq!(id, request_id, "{}", path);
+			assert_eq!(fixture.message["method"], Value::from(method.to_string()), "{}", path);
+			method
+		},
+		Kind::Response | Kind::Error => {
+			assert!(message.get_request_id_and_method().is_none(), "{}", path);
+			request_method.clone().unwrap_or_else(|| panic!("{}: missing request_method", path))
+		},
+	};
+
+	let serialized = serde_json::to_string(&message).unwrap();
+	let expected = fixture.canonical.as_ref().unwrap_or(&fixture.message);
+	assert_eq!(&serde_json::from_str::<Value>(&serialized).unwrap(), expected, "{}", path);
+
+	let reparsed = parse(&serialized, &request_id, &request_method)
+		.unwrap_or_else(|e| panic!("{}: failed to deserialize serialization: {}", path, e));
+	assert_eq!(reparsed, message, "{}", path);
+
+	(method, kind)
+}
+
+#[test]
+fn conformance_fixtures() {
+	let mut covered = HashSet::new();
+	for protocol in enabled_protocols() {
+		let dir = Path::new(FIXTURES_DIR).join(protocol);
+		for fixture in load_fixtures(&dir) {
+			let (method, kind) = check_fixture(&fixture);
+			let method = method.to_string();
+			assert!(
+				method.starts_with(&format!("{}.", protocol)),
+				"{}: fixture for {} in the wrong directory",
+				fixture.path.display(),
+				method
+			);
+			covered.insert((method, kind));
+		}
+	}
+
+	for method in built_in_methods() {
+		for kind in [Kind::Request, Kind::Response, Kind::Error] {
+			assert!(
+				covered.contains(&(method.to_string(), kind)),
+				"No {:?} fixture for {}",
+				kind,
+				method
+			);
+		}
+	}
+}
@@ -1 +1,3 @@
+#[cfg(feature = "std")]
+mod conformance;
 pub mod utils;
@@ -1,3 +1,5 @@
+#[cfg(lsps1)]
+use crate::lsps1::msgs::{OnchainPayment, OptionsSupported, PaymentInfo, PaymentState};
 use crate::prelude::Vec;
 use bitcoin::secp256k1::PublicKey;
 use lightning::io;
@@ -56,3 +58,41 @@ pub fn parse_pubkey(pubkey_str: &str) -> Result<PublicKey, io::Error> {
 
 	Ok(pubkey.unwrap())
 }
+
+#[cfg(lsps1)]
+pub fn lsps1_payment_info(
+	state: PaymentState, onchain_payment: Option<OnchainPayment>,
+) -> PaymentInfo {
+	let json_str = r#"{
+		"state": "EXPECT_PAYMENT",
+		"fee_total_sat": "8888",
+		"order_total_sat": "2008888",
+		"bolt11_invoice": "lnbc252u1p3aht9ysp580g4633gd2x9lc5al0wd8wx0mpn9748jeyz46kqjrpxn52uhfpjqpp5qgf67tcqmuqehzgjm8mzya90h73deafvr4m5705l5u5l4r05l8cqdpud3h8ymm4w3jhytnpwpczqmt0de6xsmre2pkxzm3qydmkzdjrdev9s7zhgfaqxqyjw5qcqpjrzjqt6xptnd85lpqnu2lefq4cx070v5cdwzh2xlvmdgnu7gqp4zvkus5zapryqqx9qqqyqqqqqqqqqqqcsq9q9qyysgqen77vu8xqjelum24hgjpgfdgfgx4q0nehhalcmuggt32japhjuksq9jv6eksjfnppm4hrzsgyxt8y8xacxut9qv3fpyetz8t7tsymygq8yzn05",
+		"onchain_address": "bc1p5uvtaxzkjwvey2tfy49k5vtqfpjmrgm09cvs88ezyy8h2zv7jhas9tu4yr",
+		"min_onchain_payment_confirmations": 1,
+		"min_fee_for_0conf": 253,
+		"onchain_payment": null
+	}"#;
+	let mut payment: PaymentInfo = serde_json::from_str(json_str).unwrap();
+	payment.state = state;
+	payment.onchain_payment = onchain_payment;
+	payment
+}
+
+#[cfg(lsps1)]
+pub fn lsps1_options_supported() -> OptionsSupported {
+	OptionsSupported {
+		min_required_channel_confirmations: 0,
+		min_funding_confirms_within_blocks: 6,
+		min_onchain_payment_confirmations: Some(6),
+		supports_zero_channel_reserve: true,
+		min_onchain_payment_size_sat: Some(100_000),
+		max_channel_expiry_blocks: 144,
+		min_initial_client_balance_sat: 10_000_000,
+		max_initial_client_balance_sat: 100_000_000,
+		min_initial_lsp_balance_sat: 100_000,
+		max_initial_lsp_balance_sat: 100_000_000,
+		min_channel_balance_sat: 100_000,
+		max_channel_balance_sat: 100_000_000,
+	}
+}
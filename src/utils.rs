@@ -4,6 +4,9 @@ use lightning::sign::EntropySource;
 use crate::lsps0::ser::RequestId;
 use crate::prelude::String;
 
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
 /// Maximum transaction index that can be used in a `short_channel_id`.
 /// This value is based on the 3-bytes available for tx index.
 pub const MAX_SCID_TX_INDEX: u64 = 0x00ffffff;
@@ -45,6 +48,26 @@ where
 	RequestId(hex_str(&bytes[0..16]))
 }
 
+/// Returns the current time in seconds since the unix epoch, or [`Option::None`] in no-std
+/// builds, where we can't tell, or if the system clock is behind the unix epoch.
+pub(crate) fn seconds_since_epoch() -> Option<u64> {
+	#[cfg(feature = "std")]
+	{
+		Some(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs())
+	}
+	#[cfg(not(feature = "std"))]
+	{
+		// TODO: We need to find a way to tell the time in no-std builds.
+		None
+	}
+}
+
+/// Returns the number of seconds elapsed since the given time, as returned by
+/// [`seconds_since_epoch`].
+pub(crate) fn seconds_elapsed_since(created_at: Option<u64>) -> Option<u64> {
+	Some(seconds_since_epoch()?.saturating_sub(created_at?))
+}
+
 #[inline]
 pub fn hex_str(value: &[u8]) -> String {
 	let mut res = String::with_capacity(2 * value.len());
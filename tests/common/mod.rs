@@ -10,15 +10,18 @@ use lightning::sign::EntropySource;
 use bitcoin::blockdata::constants::{genesis_block, ChainHash};
 use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::PublicKey;
 use lightning::chain::channelmonitor::ANTI_REORG_DELAY;
 use lightning::chain::{chainmonitor, BestBlock, Confirm};
 use lightning::ln::channelmanager;
 use lightning::ln::channelmanager::ChainParameters;
+use lightning::ln::channelmanager::InterceptId;
 use lightning::ln::functional_test_utils::*;
 use lightning::ln::msgs::{ChannelMessageHandler, Init};
 use lightning::ln::peer_handler::{
 	IgnoringMessageHandler, MessageHandler, PeerManager, SocketDescriptor,
 };
+use lightning::ln::{ChannelId, PaymentHash};
 use lightning::routing::gossip::{NetworkGraph, P2PGossipSync};
 use lightning::routing::router::{CandidateRouteHop, DefaultRouter, Path};
 use lightning::routing::scoring::{ChannelUsage, ScoreLookUp, ScoreUpdate};
@@ -417,6 +420,7 @@ pub(crate) fn create_liquidity_node(
 	));
 	let best_block = BestBlock::from_network(network);
 	let chain_params = ChainParameters { network, best_block };
+	let user_config = UserConfig { accept_intercept_htlcs: true, ..Default::default() };
 	let channel_manager = Arc::new(ChannelManager::new(
 		fee_estimator.clone(),
 		chain_monitor.clone(),
@@ -426,7 +430,7 @@ pub(crate) fn create_liquidity_node(
 		keys_manager.clone(),
 		keys_manager.clone(),
 		keys_manager.clone(),
-		UserConfig::default(),
+		user_config,
 		chain_params,
 		genesis_block.header.time,
 	));
@@ -436,14 +440,17 @@ pub(crate) fn create_liquidity_node(
 		logger.clone(),
 	));
 
-	let liquidity_manager = Arc::new(LiquidityManager::new(
-		Arc::clone(&keys_manager),
-		Arc::clone(&channel_manager),
-		None::<Arc<dyn Filter + Send + Sync>>,
-		Some(chain_params),
-		service_config,
-		client_config,
-	));
+	let liquidity_manager = Arc::new(
+		LiquidityManager::new(
+			Arc::clone(&keys_manager),
+			Arc::clone(&channel_manager),
+			None::<Arc<dyn Filter + Send + Sync>>,
+			Some(chain_params),
+			service_config,
+			client_config,
+		)
+		.unwrap(),
+	);
 	let msg_handler = MessageHandler {
 		chan_handler: Arc::new(test_utils::TestChannelMessageHandler::new(
 			ChainHash::using_genesis_block(Network::Testnet),
@@ -489,33 +496,36 @@ pub(crate) fn create_service_and_client_nodes(
 
 	let service_node = create_liquidity_node(1, &persist_dir, network, Some(service_config), None);
 	let client_node = create_liquidity_node(2, &persist_dir, network, None, Some(client_config));
+	connect_nodes(&service_node, &client_node);
+
+	(service_node, client_node)
+}
 
-	service_node
+pub(crate) fn connect_nodes(node_a: &Node, node_b: &Node) {
+	node_a
 		.channel_manager
 		.peer_connected(
-			&client_node.channel_manager.get_our_node_id(),
+			&node_b.channel_manager.get_our_node_id(),
 			&Init {
-				features: client_node.channel_manager.init_features(),
+				features: node_b.channel_manager.init_features(),
 				networks: None,
 				remote_network_address: None,
 			},
 			true,
 		)
 		.unwrap();
-	client_node
+	node_b
 		.channel_manager
 		.peer_connected(
-			&service_node.channel_manager.get_our_node_id(),
+			&node_a.channel_manager.get_our_node_id(),
 			&Init {
-				features: service_node.channel_manager.init_features(),
+				features: node_a.channel_manager.init_features(),
 				networks: None,
 				remote_network_address: None,
 			},
 			true,
 		)
 		.unwrap();
-
-	(service_node, client_node)
 }
 
 macro_rules! open_channel {
@@ -666,3 +676,187 @@ fn advance_chain(node: &mut Node, num_blocks: u32) {
 		}
 	}
 }
+
+/// Opens a channel from `funder` to `acceptor` and confirms it, returning its channel id once it
+/// is usable.
+pub(crate) fn create_usable_channel(
+	funder: &mut Node, acceptor: &mut Node, channel_value_sat: u64,
+) -> ChannelId {
+	use bitcoin::absolute::LockTime;
+	use bitcoin::TxOut;
+	use lightning::events::{Event, MessageSendEvent, MessageSendEventsProvider};
+
+	let funder_node_id = funder.channel_manager.get_our_node_id();
+	let acceptor_node_id = acceptor.channel_manager.get_our_node_id();
+
+	funder
+		.channel_manager
+		.create_channel(acceptor_node_id, channel_value_sat, 0, 42, None, None)
+		.unwrap();
+	match funder.channel_manager.get_and_clear_pending_msg_events().pop().unwrap() {
+		MessageSendEvent::SendOpenChannel { msg, .. } => {
+			acceptor.channel_manager.handle_open_channel(&funder_node_id, &msg)
+		},
+		_ => panic!("Unexpected message"),
+	}
+	match acceptor.channel_manager.get_and_clear_pending_msg_events().pop().unwrap() {
+		MessageSendEvent::SendAcceptChannel { msg, .. } => {
+			funder.channel_manager.handle_accept_channel(&acceptor_node_id, &msg)
+		},
+		_ => panic!("Unexpected message"),
+	}
+
+	let funding_tx = match funder.channel_manager.get_and_clear_pending_events().pop().unwrap() {
+		Event::FundingGenerationReady { temporary_channel_id, output_script, .. } => {
+			let funding_tx = Transaction {
+				version: 1,
+				lock_time: LockTime::ZERO,
+				input: Vec::new(),
+				output: vec![TxOut { value: channel_value_sat, script_pubkey: output_script }],
+			};
+			funder
+				.channel_manager
+				.funding_transaction_generated(
+					&temporary_channel_id,
+					&acceptor_node_id,
+					funding_tx.clone(),
+				)
+				.unwrap();
+			funding_tx
+		},
+		_ => panic!("Unexpected event"),
+	};
+	match funder.channel_manager.get_and_clear_pending_msg_events().pop().unwrap() {
+		MessageSendEvent::SendFundingCreated { msg, .. } => {
+			acceptor.channel_manager.handle_funding_created(&funder_node_id, &msg)
+		},
+		_ => panic!("Unexpected message"),
+	}
+	match acceptor.channel_manager.get_and_clear_pending_msg_events().pop().unwrap() {
+		MessageSendEvent::SendFundingSigned { msg, .. } => {
+			funder.channel_manager.handle_funding_signed(&acceptor_node_id, &msg)
+		},
+		_ => panic!("Unexpected message"),
+	}
+	funder.channel_manager.get_and_clear_pending_events();
+	acceptor.channel_manager.get_and_clear_pending_events();
+
+	confirm_transaction_depth(funder, &funding_tx, 6);
+	confirm_transaction_depth(acceptor, &funding_tx, 6);
+	for (node, counterparty) in [(&*funder, &*acceptor), (&*acceptor, &*funder)] {
+		let node_id = node.channel_manager.get_our_node_id();
+		for msg_event in node.channel_manager.get_and_clear_pending_msg_events() {
+			if let MessageSendEvent::SendChannelReady { msg, .. } = msg_event {
+				counterparty.channel_manager.handle_channel_ready(&node_id, &msg);
+			}
+		}
+	}
+	funder.channel_manager.get_and_clear_pending_events();
+	acceptor.channel_manager.get_and_clear_pending_events();
+
+	let channel =
+		funder.channel_manager.list_channels_with_counterparty(&acceptor_node_id).pop().unwrap();
+	assert!(channel.is_usable);
+	channel.channel_id
+}
+
+/// Sends an HTLC of `amount_msat` from `payer` via `forwarder` to the given intercept scid and
+/// returns the [`Event::HTLCIntercepted`] parameters `forwarder` generated for it.
+///
+/// [`Event::HTLCIntercepted`]: lightning::events::Event::HTLCIntercepted
+pub(crate) fn send_intercepted_htlc(
+	payer: &Node, forwarder: &Node, intercept_scid: u64, recipient_node_id: PublicKey,
+	amount_msat: u64, payment_hash: PaymentHash,
+) -> (InterceptId, u64) {
+	use lightning::events::{Event, MessageSendEvent, MessageSendEventsProvider};
+	use lightning::ln::channelmanager::{PaymentId, RecipientOnionFields};
+	use lightning::ln::features::ChannelFeatures;
+	use lightning::ln::PaymentSecret;
+	use lightning::routing::router::{Route, RouteHop};
+
+	let payer_node_id = payer.channel_manager.get_our_node_id();
+	let forwarder_node_id = forwarder.channel_manager.get_our_node_id();
+	let first_hop = payer
+		.channel_manager
+		.list_channels_with_counterparty(&forwarder_node_id)
+		.pop()
+		.unwrap()
+		.short_channel_id
+		.unwrap();
+	let hops = vec![
+		RouteHop {
+			pubkey: forwarder_node_id,
+			node_features: forwarder.channel_manager.node_features(),
+			short_channel_id: first_hop,
+			channel_features: ChannelFeatures::empty(),
+			fee_msat: 0,
+			cltv_expiry_delta: 144,
+			maybe_announced_channel: false,
+		},
+		RouteHop {
+			pubkey: recipient_node_id,
+			node_features: forwarder.channel_manager.node_features(),
+			short_channel_id: intercept_scid,
+			channel_features: ChannelFeatures::empty(),
+			fee_msat: amount_msat,
+			cltv_expiry_delta: 144,
+			maybe_announced_channel: false,
+		},
+	];
+	let route = Route { paths: vec![Path { hops, blinded_tail: None }], route_params: None };
+	payer
+		.channel_manager
+		.send_payment_with_route(
+			&route,
+			payment_hash,
+			RecipientOnionFields::secret_only(PaymentSecret([42; 32])),
+			PaymentId(payment_hash.0),
+		)
+		.unwrap();
+
+	// Run the commitment dance until the HTLC is irrevocably committed.
+	loop {
+		let mut delivered = false;
+		for (from, to, from_node_id, to_node_id) in [
+			(payer, forwarder, payer_node_id, forwarder_node_id),
+			(forwarder, payer, forwarder_node_id, payer_node_id),
+		] {
+			for msg_event in from.channel_manager.get_and_clear_pending_msg_events() {
+				match msg_event {
+					MessageSendEvent::UpdateHTLCs { node_id, updates } if node_id == to_node_id => {
+						for update_add_htlc in &updates.update_add_htlcs {
+							to.channel_manager
+								.handle_update_add_htlc(&from_node_id, update_add_htlc);
+						}
+						to.channel_manager
+							.handle_commitment_signed(&from_node_id, &updates.commitment_signed);
+						delivered = true;
+					},
+					MessageSendEvent::SendRevokeAndACK { node_id, msg }
+						if node_id == to_node_id =>
+					{
+						to.channel_manager.handle_revoke_and_ack(&from_node_id, &msg);
+						delivered = true;
+					},
+					_ => {},
+				}
+			}
+		}
+		if !delivered {
+			break;
+		}
+	}
+
+	forwarder.channel_manager.process_pending_htlc_forwards();
+	forwarder
+		.channel_manager
+		.get_and_clear_pending_events()
+		.into_iter()
+		.find_map(|event| match event {
+			Event::HTLCIntercepted { intercept_id, expected_outbound_amount_msat, .. } => {
+				Some((intercept_id, expected_outbound_amount_msat))
+			},
+			_ => None,
+		})
+		.unwrap()
+}
@@ -0,0 +1,137 @@
+#![cfg(all(test, feature = "std"))]
+
+mod common;
+
+use common::{create_service_and_client_nodes, get_lsps_message};
+
+use lightning_liquidity::custom::CustomProtocolHandler;
+use lightning_liquidity::lsps0::ser::{RequestId, ResponseError};
+use lightning_liquidity::{LiquidityClientConfig, LiquidityServiceConfig};
+
+use lightning::ln::msgs::LightningError;
+use lightning::ln::peer_handler::CustomMessageHandler;
+
+use bitcoin::secp256k1::PublicKey;
+
+use serde_json::json;
+
+use std::sync::{Arc, Mutex};
+
+const GET_QUOTE_METHOD_NAME: &str = "lsps42.get_quote";
+
+#[derive(Default)]
+struct QuoteHandler {
+	responses: Mutex<Vec<(RequestId, Result<serde_json::Value, ResponseError>)>>,
+}
+
+impl CustomProtocolHandler for QuoteHandler {
+	fn protocol_number(&self) -> u16 {
+		42
+	}
+
+	fn method_names(&self) -> Vec<String> {
+		vec![GET_QUOTE_METHOD_NAME.to_string()]
+	}
+
+	fn handle_request(
+		&self, _request_id: &RequestId, method: &str, params: serde_json::Value,
+		_counterparty_node_id: &PublicKey,
+	) -> Result<serde_json::Value, ResponseError> {
+		assert_eq!(method, GET_QUOTE_METHOD_NAME);
+		match params["amount_sat"].as_u64() {
+			Some(amount_sat) => Ok(json!({ "fee_sat": amount_sat / 100 })),
+			None => Err(ResponseError {
+				code: -32602,
+				message: "Invalid params".to_string(),
+				data: None,
+			}),
+		}
+	}
+
+	fn handle_response(
+		&self, request_id: RequestId, method: &str,
+		result: Result<serde_json::Value, ResponseError>, _counterparty_node_id: &PublicKey,
+	) -> Result<(), LightningError> {
+		assert_eq!(method, GET_QUOTE_METHOD_NAME);
+		self.responses.lock().unwrap().push((request_id, result));
+		Ok(())
+	}
+}
+
+#[test]
+fn custom_protocol_flow() {
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: None,
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: None,
+		lsps5_client_config: None,
+	};
+
+	let (service_node, client_node) =
+		create_service_and_client_nodes("custom_protocol_flow", service_config, client_config);
+
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let service_handler = Arc::new(QuoteHandler::default());
+	service_node.liquidity_manager.register_custom_protocol_handler(service_handler).unwrap();
+
+	let client_handler = Arc::new(QuoteHandler::default());
+	client_node
+		.liquidity_manager
+		.register_custom_protocol_handler(Arc::clone(&client_handler) as _)
+		.unwrap();
+
+	// Registering the same protocol twice fails.
+	assert!(client_node
+		.liquidity_manager
+		.register_custom_protocol_handler(Arc::new(QuoteHandler::default()))
+		.is_err());
+
+	let request_id = client_node
+		.liquidity_manager
+		.send_custom_request(
+			service_node_id,
+			GET_QUOTE_METHOD_NAME.to_string(),
+			json!({ "amount_sat": 100_000 }),
+		)
+		.unwrap();
+	let request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(request, &client_node_id).unwrap();
+
+	let response = get_lsps_message!(service_node, client_node_id);
+	client_node.liquidity_manager.handle_custom_message(response, &service_node_id).unwrap();
+
+	let responses = client_handler.responses.lock().unwrap().split_off(0);
+	assert_eq!(responses, vec![(request_id, Ok(json!({ "fee_sat": 1000 })))]);
+
+	// Errors returned by the handler are sent back as JSON-RPC errors.
+	let request_id = client_node
+		.liquidity_manager
+		.send_custom_request(service_node_id, GET_QUOTE_METHOD_NAME.to_string(), json!({}))
+		.unwrap();
+	let request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(request, &client_node_id).unwrap();
+
+	let response = get_lsps_message!(service_node, client_node_id);
+	client_node.liquidity_manager.handle_custom_message(response, &service_node_id).unwrap();
+
+	let responses = client_handler.responses.lock().unwrap().split_off(0);
+	assert_eq!(responses.len(), 1);
+	assert_eq!(responses[0].0, request_id);
+	assert_eq!(responses[0].1.as_ref().unwrap_err().code, -32602);
+
+	// Unregistered methods can't be used.
+	assert!(client_node
+		.liquidity_manager
+		.send_custom_request(service_node_id, "lsps43.unknown".to_string(), json!({}))
+		.is_err());
+}
@@ -0,0 +1,497 @@
+#![cfg(all(test, feature = "std", lsps1))]
+
+mod common;
+
+use common::{create_service_and_client_nodes, get_lsps_message, Node};
+
+use lightning_liquidity::events::Event;
+use lightning_liquidity::lsps0::ser::RawLSPSMessage;
+use lightning_liquidity::lsps1::client::LSPS1ClientConfig;
+use lightning_liquidity::lsps1::event::{LSPS1ClientEvent, LSPS1ServiceEvent};
+use lightning_liquidity::lsps1::msgs::{
+	CreateOrderResponse, OptionsSupported, OrderId, OrderParams, OrderState, PaymentInfo,
+	PaymentState,
+};
+use lightning_liquidity::lsps1::service::{
+	LSPS1ServiceConfig, RefundFeePolicy, RefundRequest, RefundWallet,
+};
+use lightning_liquidity::{LiquidityClientConfig, LiquidityServiceConfig};
+
+use lightning::chain::Confirm;
+use lightning::events::{Event as LdkEvent, MessageSendEvent, MessageSendEventsProvider};
+use lightning::ln::functional_test_utils::create_dummy_header;
+use lightning::ln::msgs::ChannelMessageHandler;
+use lightning::ln::peer_handler::CustomMessageHandler;
+use lightning::util::config::UserConfig;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, FeeRate, Transaction, TxOut, Txid};
+
+use chrono::{TimeZone, Utc};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn options_supported() -> OptionsSupported {
+	OptionsSupported {
+		min_required_channel_confirmations: 0,
+		min_funding_confirms_within_blocks: 6,
+		min_onchain_payment_confirmations: Some(6),
+		supports_zero_channel_reserve: false,
+		min_onchain_payment_size_sat: None,
+		max_channel_expiry_blocks: 20_000,
+		min_initial_client_balance_sat: 0,
+		max_initial_client_balance_sat: 10_000_000,
+		min_initial_lsp_balance_sat: 0,
+		max_initial_lsp_balance_sat: 10_000_000,
+		min_channel_balance_sat: 100_000,
+		max_channel_balance_sat: 16_000_000,
+	}
+}
+
+fn order_params() -> OrderParams {
+	OrderParams {
+		lsp_balance_sat: 5_000_000,
+		client_balance_sat: 2_000_000,
+		required_channel_confirmations: 0,
+		funding_confirms_within_blocks: 6,
+		channel_expiry_blocks: 144,
+		token: "".to_string(),
+		refund_onchain_address: None,
+		announce_channel: false,
+	}
+}
+
+fn payment_info() -> PaymentInfo {
+	let json_str = r#"{
+		"state": "EXPECT_PAYMENT",
+		"fee_total_sat": "8888",
+		"order_total_sat": "2008888",
+		"bolt11_invoice": "lnbc252u1p3aht9ysp580g4633gd2x9lc5al0wd8wx0mpn9748jeyz46kqjrpxn52uhfpjqpp5qgf67tcqmuqehzgjm8mzya90h73deafvr4m5705l5u5l4r05l8cqdpud3h8ymm4w3jhytnpwpczqmt0de6xsmre2pkxzm3qydmkzdjrdev9s7zhgfaqxqyjw5qcqpjrzjqt6xptnd85lpqnu2lefq4cx070v5cdwzh2xlvmdgnu7gqp4zvkus5zapryqqx9qqqyqqqqqqqqqqqcsq9q9qyysgqen77vu8xqjelum24hgjpgfdgfgx4q0nehhalcmuggt32japhjuksq9jv6eksjfnppm4hrzsgyxt8y8xacxut9qv3fpyetz8t7tsymygq8yzn05",
+		"onchain_address": "bc1p5uvtaxzkjwvey2tfy49k5vtqfpjmrgm09cvs88ezyy8h2zv7jhas9tu4yr",
+		"min_onchain_payment_confirmations": 1,
+		"min_fee_for_0conf": 253,
+		"onchain_payment": null
+	}"#;
+	serde_json::from_str(json_str).unwrap()
+}
+
+struct TestWallet<F: Fn(RefundRequest) -> Result<Txid, ()>>(F);
+
+impl<F: Fn(RefundRequest) -> Result<Txid, ()>> RefundWallet for TestWallet<F> {
+	fn send_refund(&self, request: RefundRequest) -> Result<Txid, ()> {
+		(self.0)(request)
+	}
+}
+
+fn response_result(msg: &RawLSPSMessage) -> CreateOrderResponse {
+	let response: serde_json::Value = serde_json::from_str(&msg.payload).unwrap();
+	serde_json::from_value(response["result"].clone()).unwrap()
+}
+
+fn create_lsps1_nodes(persist_dir: &str, channel_open_config: Option<UserConfig>) -> (Node, Node) {
+	let lsps1_service_config = LSPS1ServiceConfig {
+		token: None,
+		options_supported: Some(options_supported()),
+		channel_open_config,
+	};
+	let service_config = LiquidityServiceConfig {
+		lsps1_service_config: Some(lsps1_service_config),
+		lsps2_service_config: None,
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+	let client_config = LiquidityClientConfig {
+		lsps1_client_config: Some(LSPS1ClientConfig { max_channel_fees_msat: None }),
+		lsps2_client_config: None,
+		lsps5_client_config: None,
+	};
+	create_service_and_client_nodes(persist_dir, service_config, client_config)
+}
+
+/// Walks the client through placing an order, returning the order as created by the service.
+fn place_order(service_node: &Node, client_node: &Node, order: OrderParams) -> CreateOrderResponse {
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+	let client_handler = client_node.liquidity_manager.lsps1_client_handler().unwrap();
+
+	let user_channel_id = 42;
+	client_handler.send_get_info_request(service_node_id, user_channel_id);
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS1Client(LSPS1ClientEvent::GetInfoResponse { .. }) => {},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+
+	client_handler.place_order(user_channel_id, &service_node_id, order).unwrap();
+	let create_order_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(create_order_request, &client_node_id)
+		.unwrap();
+	let request_id = match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS1Service(LSPS1ServiceEvent::RequestForPaymentDetails { request_id, .. }) => {
+			request_id
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	};
+
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+	let created_at = Utc.timestamp_opt(now, 0).unwrap();
+	let expires_at = created_at + chrono::Duration::hours(1);
+	service_handler
+		.send_payment_details(request_id, &client_node_id, payment_info(), created_at, expires_at)
+		.unwrap();
+	let create_order_response = get_lsps_message!(service_node, client_node_id);
+	let order = response_result(&create_order_response);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(create_order_response, &service_node_id)
+		.unwrap();
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS1Client(LSPS1ClientEvent::DisplayOrder { .. }) => {},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+
+	order
+}
+
+/// Queries the order's status on behalf of the client, returning the service's response.
+fn get_order(service_node: &Node, client_node: &Node, order_id: &OrderId) -> CreateOrderResponse {
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let request = serde_json::json!({
+		"jsonrpc": "2.0",
+		"id": "get_order",
+		"method": "lsps1.get_order",
+		"params": { "order_id": order_id.0 },
+	});
+	let get_order_request = RawLSPSMessage { payload: request.to_string() };
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_order_request, &client_node_id)
+		.unwrap();
+	response_result(&get_lsps_message!(service_node, client_node_id))
+}
+
+fn get_msg_event(node: &Node) -> MessageSendEvent {
+	let mut msg_events = node.channel_manager.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	msg_events.pop().unwrap()
+}
+
+/// Completes the establishment of the channel the service started to open.
+fn fund_channel(service_node: &Node, client_node: &Node, channel_value_satoshis: u64) {
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	match get_msg_event(service_node) {
+		MessageSendEvent::SendOpenChannel { node_id, msg } => {
+			assert_eq!(node_id, client_node_id);
+			client_node.channel_manager.handle_open_channel(&service_node_id, &msg);
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+	match get_msg_event(client_node) {
+		MessageSendEvent::SendAcceptChannel { msg, .. } => {
+			service_node.channel_manager.handle_accept_channel(&client_node_id, &msg);
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+
+	let mut events = service_node.channel_manager.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events.pop().unwrap() {
+		LdkEvent::FundingGenerationReady {
+			temporary_channel_id,
+			channel_value_satoshis: value,
+			output_script,
+			..
+		} => {
+			assert_eq!(value, channel_value_satoshis);
+			let tx = Transaction {
+				version: 2,
+				lock_time: LockTime::ZERO,
+				input: Vec::new(),
+				output: vec![TxOut { value, script_pubkey: output_script }],
+			};
+			service_node
+				.channel_manager
+				.funding_transaction_generated(&temporary_channel_id, &client_node_id, tx)
+				.unwrap();
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+
+	match get_msg_event(service_node) {
+		MessageSendEvent::SendFundingCreated { msg, .. } => {
+			client_node.channel_manager.handle_funding_created(&service_node_id, &msg);
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+	match get_msg_event(client_node) {
+		MessageSendEvent::SendFundingSigned { msg, .. } => {
+			service_node.channel_manager.handle_funding_signed(&client_node_id, &msg);
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+}
+
+#[test]
+fn order_paid_opens_and_tracks_channel() {
+	let channel_open_config = UserConfig::default();
+	let (service_node, client_node) =
+		create_lsps1_nodes("order_paid_opens_and_tracks_channel", Some(channel_open_config));
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+
+	let order = place_order(&service_node, &client_node, order_params());
+
+	service_handler.order_paid(&client_node_id, order.order_id.clone()).unwrap();
+
+	// Paying twice neither opens a second channel, nor changes the order.
+	assert!(service_handler.order_paid(&client_node_id, order.order_id.clone()).is_err());
+	let channels = service_node.channel_manager.list_channels_with_counterparty(&client_node_id);
+	assert_eq!(channels.len(), 1);
+	assert_eq!(channels[0].channel_value_satoshis, 7_000_000);
+
+	// The client's status queries are answered without involving the LSP until the channel is
+	// funded.
+	let response = get_order(&service_node, &client_node, &order.order_id);
+	assert_eq!(response.order_state, OrderState::Created);
+	assert_eq!(response.channel, None);
+	assert!(service_node.liquidity_manager.get_and_clear_pending_events().is_empty());
+
+	fund_channel(&service_node, &client_node, 7_000_000);
+
+	let header = create_dummy_header(BlockHash::all_zeros(), 1_700_000_000);
+	service_node.liquidity_manager.best_block_updated(&header, 1);
+
+	let channels = service_node.channel_manager.list_channels_with_counterparty(&client_node_id);
+	let funding_outpoint = channels[0].funding_txo.unwrap().into_bitcoin_outpoint();
+	let response = get_order(&service_node, &client_node, &order.order_id);
+	assert_eq!(response.order_state, OrderState::Completed);
+	let channel = response.channel.unwrap();
+	assert_eq!(channel.funding_outpoint, funding_outpoint);
+	assert_eq!(channel.funded_at.timestamp(), 1_700_000_000);
+	assert_eq!(channel.expires_at, channel.funded_at + chrono::Duration::seconds(144 * 600));
+}
+
+#[test]
+fn order_paid_allows_retry_after_failed_open() {
+	let (service_node, client_node) = create_lsps1_nodes(
+		"order_paid_allows_retry_after_failed_open",
+		Some(UserConfig::default()),
+	);
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+
+	let order = place_order(&service_node, &client_node, order_params());
+
+	// Opening the channel fails while the client is disconnected, but can be retried.
+	service_node.channel_manager.peer_disconnected(&client_node_id);
+	assert!(service_handler.order_paid(&client_node_id, order.order_id.clone()).is_err());
+	assert!(service_node
+		.channel_manager
+		.list_channels_with_counterparty(&client_node_id)
+		.is_empty());
+
+	service_node
+		.channel_manager
+		.peer_connected(
+			&client_node_id,
+			&lightning::ln::msgs::Init {
+				features: client_node.channel_manager.init_features(),
+				networks: None,
+				remote_network_address: None,
+			},
+			true,
+		)
+		.unwrap();
+	service_handler.order_paid(&client_node_id, order.order_id).unwrap();
+	assert_eq!(
+		service_node.channel_manager.list_channels_with_counterparty(&client_node_id).len(),
+		1
+	);
+}
+
+#[test]
+fn refund_order_releases_locks_while_paying_out() {
+	let (service_node, client_node) =
+		create_lsps1_nodes("refund_order_releases_locks_while_paying_out", None);
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+
+	let mut order_params = order_params();
+	order_params.refund_onchain_address =
+		Some("bc1p5uvtaxzkjwvey2tfy49k5vtqfpjmrgm09cvs88ezyy8h2zv7jhas9tu4yr".parse().unwrap());
+	let order = place_order(&service_node, &client_node, order_params);
+	service_handler.order_paid(&client_node_id, order.order_id.clone()).unwrap();
+
+	let fee_policy = RefundFeePolicy::PaidByLsp { fee_rate: FeeRate::from_sat_per_vb(2).unwrap() };
+	let failing_wallet = TestWallet(|_| Err(()));
+
+	// A failed refund can be retried.
+	assert!(service_handler
+		.refund_order(&client_node_id, order.order_id.clone(), fee_policy, &failing_wallet)
+		.is_err());
+
+	// While the wallet pays out the refund, the order can be queried, but not refunded again.
+	let txid = Txid::all_zeros();
+	let wallet = TestWallet(|request: RefundRequest| {
+		assert_eq!(request.amount_sat, 2_008_888);
+		assert!(service_handler
+			.refund_order(&client_node_id, order.order_id.clone(), fee_policy, &failing_wallet)
+			.is_err());
+		let response = get_order(&service_node, &client_node, &order.order_id);
+		assert_eq!(response.order_state, OrderState::Failed);
+		Ok(txid)
+	});
+	assert_eq!(
+		service_handler.refund_order(&client_node_id, order.order_id.clone(), fee_policy, &wallet),
+		Ok(txid)
+	);
+
+	let response = get_order(&service_node, &client_node, &order.order_id);
+	assert_eq!(response.order_state, OrderState::Failed);
+	assert_eq!(response.payment.state, PaymentState::Refunded);
+	assert!(service_handler
+		.refund_order(&client_node_id, order.order_id, fee_policy, &failing_wallet)
+		.is_err());
+}
+
+#[test]
+fn order_paid_rejects_refunded_orders() {
+	let (service_node, client_node) =
+		create_lsps1_nodes("order_paid_rejects_refunded_orders", Some(UserConfig::default()));
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+
+	let mut order_params = order_params();
+	order_params.refund_onchain_address =
+		Some("bc1p5uvtaxzkjwvey2tfy49k5vtqfpjmrgm09cvs88ezyy8h2zv7jhas9tu4yr".parse().unwrap());
+	let order = place_order(&service_node, &client_node, order_params);
+
+	// The first attempt to open the channel fails, leaving the paid order refundable.
+	service_node.channel_manager.peer_disconnected(&client_node_id);
+	assert!(service_handler.order_paid(&client_node_id, order.order_id.clone()).is_err());
+
+	let fee_policy = RefundFeePolicy::PaidByLsp { fee_rate: FeeRate::from_sat_per_vb(2).unwrap() };
+	let txid = Txid::all_zeros();
+	let wallet = TestWallet(|request: RefundRequest| {
+		// The order can't be paid while its refund is being paid out.
+		assert!(service_handler.order_paid(&client_node_id, request.order_id).is_err());
+		Ok(txid)
+	});
+	assert_eq!(
+		service_handler.refund_order(&client_node_id, order.order_id.clone(), fee_policy, &wallet),
+		Ok(txid)
+	);
+
+	// Neither is a channel opened for a refunded order, nor is it marked as paid again.
+	service_node
+		.channel_manager
+		.peer_connected(
+			&client_node_id,
+			&lightning::ln::msgs::Init {
+				features: client_node.channel_manager.init_features(),
+				networks: None,
+				remote_network_address: None,
+			},
+			true,
+		)
+		.unwrap();
+	assert!(service_handler.order_paid(&client_node_id, order.order_id.clone()).is_err());
+	assert!(service_node
+		.channel_manager
+		.list_channels_with_counterparty(&client_node_id)
+		.is_empty());
+	let response = get_order(&service_node, &client_node, &order.order_id);
+	assert_eq!(response.order_state, OrderState::Failed);
+	assert_eq!(response.payment.state, PaymentState::Refunded);
+}
+
+#[test]
+fn order_paid_rejects_already_paid_orders() {
+	let (service_node, client_node) =
+		create_lsps1_nodes("order_paid_rejects_already_paid_orders", None);
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+
+	let order = place_order(&service_node, &client_node, order_params());
+	service_handler.order_paid(&client_node_id, order.order_id.clone()).unwrap();
+	assert!(service_handler.order_paid(&client_node_id, order.order_id).is_err());
+}
+
+#[test]
+fn update_options_supported_is_validated() {
+	let (service_node, client_node) =
+		create_lsps1_nodes("update_options_supported_is_validated", None);
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+	let service_handler = service_node.liquidity_manager.lsps1_service_handler().unwrap();
+	let client_handler = client_node.liquidity_manager.lsps1_client_handler().unwrap();
+
+	// Inconsistent options are rejected the same way they are on creation.
+	let mut invalid_options = options_supported();
+	invalid_options.min_channel_balance_sat = invalid_options.max_channel_balance_sat + 1;
+	assert!(service_handler.update_options_supported(invalid_options).is_err());
+
+	let mut updated_options = options_supported();
+	updated_options.max_channel_expiry_blocks = 1008;
+	service_handler.update_options_supported(updated_options.clone()).unwrap();
+
+	client_handler.send_get_info_request(service_node_id, 42);
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS1Client(LSPS1ClientEvent::GetInfoResponse { options_supported, .. }) => {
+			assert_eq!(options_supported, updated_options);
+		},
+		event => panic!("Unexpected event: {:?}", event),
+	}
+}
+
+#[test]
+fn lsp_discovery_leaves_no_channel_requests() {
+	let (service_node, client_node) =
+		create_lsps1_nodes("lsp_discovery_leaves_no_channel_requests", None);
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	client_node.liquidity_manager.query_lsp(service_node_id);
+	for _ in 0..2 {
+		let request = get_lsps_message!(client_node, service_node_id);
+		service_node.liquidity_manager.handle_custom_message(request, &client_node_id).unwrap();
+		let response = get_lsps_message!(service_node, client_node_id);
+		client_node.liquidity_manager.handle_custom_message(response, &service_node_id).unwrap();
+	}
+
+	let directory = client_node.liquidity_manager.lsp_directory();
+	let entry = directory.entry(&service_node_id).unwrap();
+	assert!(entry.protocols.as_ref().unwrap().contains(&1));
+	assert_eq!(entry.lsps1_options, Some(options_supported()));
+
+	// The LSPS1 options were queried without involving the client handler.
+	assert!(client_node.liquidity_manager.get_and_clear_pending_events().is_empty());
+	let client_handler = client_node.liquidity_manager.lsps1_client_handler().unwrap();
+	assert!(client_handler.list_channel_requests().is_empty());
+}
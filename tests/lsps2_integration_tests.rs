@@ -2,18 +2,26 @@
 
 mod common;
 
-use common::{create_service_and_client_nodes, get_lsps_message, Node};
+use common::{
+	connect_nodes, create_liquidity_node, create_service_and_client_nodes, create_usable_channel,
+	get_lsps_message, send_intercepted_htlc, Node,
+};
 
 use lightning_liquidity::events::Event;
-use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
+use lightning_liquidity::lsps2::client::{JITPaymentAction, LSPS2ClientConfig};
 use lightning_liquidity::lsps2::event::{LSPS2ClientEvent, LSPS2ServiceEvent};
 use lightning_liquidity::lsps2::msgs::RawOpeningFeeParams;
-use lightning_liquidity::lsps2::service::LSPS2ServiceConfig;
+use lightning_liquidity::lsps2::service::{
+	ExistingChannelPolicy, JITChannelDetails, JITChannelState, LSPS2ServiceConfig,
+	DEFAULT_MAX_HTLCS_PER_PAYMENT, DEFAULT_MAX_QUEUED_PAYMENTS,
+};
 use lightning_liquidity::lsps2::utils::is_valid_opening_fee_params;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityServiceConfig};
 
-use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA;
+use lightning::events::{ClosureReason, HTLCDestination};
+use lightning::ln::channelmanager::{InterceptId, MIN_FINAL_CLTV_EXPIRY_DELTA};
 use lightning::ln::peer_handler::CustomMessageHandler;
+use lightning::ln::{ChannelId, PaymentHash};
 use lightning::log_error;
 use lightning::routing::router::{RouteHint, RouteHintHop};
 use lightning::util::logger::Logger;
@@ -26,7 +34,8 @@ use bitcoin::Network;
 
 use chrono::DateTime;
 
-use std::time::Duration;
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 fn create_jit_invoice(
 	node: &Node, service_node_id: PublicKey, intercept_scid: u64, cltv_expiry_delta: u32,
@@ -83,11 +92,17 @@ fn create_jit_invoice(
 #[test]
 fn invoice_generation_flow() {
 	let promise_secret = [42; 32];
-	let lsps2_service_config = LSPS2ServiceConfig { promise_secret };
+	let lsps2_service_config = LSPS2ServiceConfig {
+		promise_secret,
+		existing_channel_policy: ExistingChannelPolicy::OpenNewChannel,
+		max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+		max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+	};
 	let service_config = LiquidityServiceConfig {
 		#[cfg(lsps1)]
 		lsps1_service_config: None,
 		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
 		advertise_service: true,
 	};
 
@@ -96,6 +111,7 @@ fn invoice_generation_flow() {
 		#[cfg(lsps1)]
 		lsps1_client_config: None,
 		lsps2_client_config: Some(lsps2_client_config),
+		lsps5_client_config: None,
 	};
 
 	let (service_node, client_node) =
@@ -107,7 +123,7 @@ fn invoice_generation_flow() {
 	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
 	let client_node_id = client_node.channel_manager.get_our_node_id();
 
-	let get_info_request_id = client_handler.request_opening_params(service_node_id, None);
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
 	let get_info_request = get_lsps_message!(client_node, service_node_id);
 
 	service_node
@@ -139,6 +155,15 @@ fn invoice_generation_flow() {
 		max_payment_size_msat: 100_000_000,
 	};
 
+	// A menu that doesn't fit into a message is rejected, but the request may still be answered.
+	assert!(service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id.clone(),
+			(0..1000).map(|_| RawOpeningFeeParams { ..raw_opening_params }).collect(),
+		)
+		.is_err());
+
 	service_handler
 		.opening_fee_params_generated(
 			&client_node_id,
@@ -212,6 +237,24 @@ fn invoice_generation_flow() {
 	let buy_response = get_lsps_message!(service_node, client_node_id);
 	client_node.liquidity_manager.handle_custom_message(buy_response, &service_node_id).unwrap();
 
+	let mut expected_details = JITChannelDetails {
+		counterparty_node_id: client_node_id,
+		intercept_scid,
+		user_channel_id,
+		channel_id: None,
+		state: JITChannelState::PendingInitialPayment,
+		opening_fee_params: opening_fee_params.clone(),
+		payment_size_msat,
+		opening_fee_msat: None,
+		num_queued_htlcs: 0,
+		queued_amount_msat: 0,
+	};
+	assert_eq!(
+		service_handler.jit_channel_by_intercept_scid(intercept_scid),
+		Some(expected_details.clone())
+	);
+	assert_eq!(service_handler.jit_channel_by_intercept_scid(intercept_scid + 1), None);
+
 	let invoice_params_event = client_node.liquidity_manager.next_event().unwrap();
 	match invoice_params_event {
 		Event::LSPS2Client(LSPS2ClientEvent::InvoiceParametersReady {
@@ -232,7 +275,7 @@ fn invoice_generation_flow() {
 
 	let description = "asdf";
 	let expiry_secs = 3600;
-	let _invoice = create_jit_invoice(
+	let invoice = create_jit_invoice(
 		&client_node,
 		service_node_id,
 		intercept_scid,
@@ -242,4 +285,1077 @@ fn invoice_generation_flow() {
 		expiry_secs,
 	)
 	.unwrap();
+
+	let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+	assert!(client_handler.register_jit_payment(&service_node_id, 42, payment_hash).is_err());
+	client_handler.register_jit_payment(&service_node_id, intercept_scid, payment_hash).unwrap();
+
+	// We agreed to an opening fee of max(100, 1_000_000 * 21 / 1_000_000) = 100 msat.
+	assert_eq!(
+		client_handler.check_skimmed_fee(&payment_hash, 999_899, 101),
+		JITPaymentAction::Fail { max_opening_fee_msat: 100, skimmed_fee_msat: 101 }
+	);
+	assert_eq!(
+		client_handler.check_skimmed_fee(&payment_hash, 999_900, 100),
+		JITPaymentAction::Claim
+	);
+	// Once claimed, the payment hash is no longer tracked.
+	assert_eq!(
+		client_handler.check_skimmed_fee(&payment_hash, 999_900, 100),
+		JITPaymentAction::Fail { max_opening_fee_msat: 0, skimmed_fee_msat: 100 }
+	);
+	// Payments not made via a JIT channel must not have a fee skimmed at all.
+	let other_payment_hash = PaymentHash([42; 32]);
+	assert_eq!(
+		client_handler.check_skimmed_fee(&other_payment_hash, 1_000_000, 0),
+		JITPaymentAction::Claim
+	);
+	assert_eq!(
+		client_handler.check_skimmed_fee(&other_payment_hash, 999_999, 1),
+		JITPaymentAction::Fail { max_opening_fee_msat: 0, skimmed_fee_msat: 1 }
+	);
+
+	// Once the payment arrives, the LSP opens the JIT channel to us. It needs to be large enough
+	// to carry the payment of 1_000_000 msat minus the opening fee of 100 msat.
+	assert!(client_handler.jit_channel_open_requested(&client_node_id, 1_000).is_none());
+	assert!(client_handler.jit_channel_open_requested(&service_node_id, 999).is_none());
+	let acceptance = client_handler.jit_channel_open_requested(&service_node_id, 1_000).unwrap();
+	assert_eq!(acceptance.request_id, buy_request_id);
+	assert_eq!(acceptance.intercept_scid, intercept_scid);
+	assert!(acceptance.channel_config(Default::default()).accept_underpaying_htlcs);
+	assert!(client_handler.jit_channel_open_requested(&service_node_id, 1_000).is_none());
+
+	let channel_id = ChannelId([42; 32]);
+	client_handler.channel_ready(acceptance.user_channel_id + 1, &channel_id, &service_node_id);
+	assert!(client_node.liquidity_manager.next_event().is_none());
+	client_handler.channel_ready(acceptance.user_channel_id, &channel_id, &service_node_id);
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::JITChannelOpened {
+			request_id,
+			counterparty_node_id,
+			intercept_scid: iscid,
+			channel_id: cid,
+			user_channel_id,
+		}) => {
+			assert_eq!(request_id, buy_request_id);
+			assert_eq!(counterparty_node_id, service_node_id);
+			assert_eq!(iscid, intercept_scid);
+			assert_eq!(cid, channel_id);
+			assert_eq!(user_channel_id, acceptance.user_channel_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Claiming a payment received over the opened channel completes the purchase.
+	let retried_payment_hash = PaymentHash([43; 32]);
+	client_handler
+		.register_jit_payment(&service_node_id, intercept_scid, retried_payment_hash)
+		.unwrap();
+	assert_eq!(client_handler.list_jit_channel_purchases().len(), 1);
+	assert_eq!(
+		client_handler.check_skimmed_fee(&retried_payment_hash, 999_900, 100),
+		JITPaymentAction::Claim
+	);
+	assert!(client_handler.list_jit_channel_purchases().is_empty());
+	assert_eq!(
+		client_handler.check_skimmed_fee(&retried_payment_hash, 999_900, 100),
+		JITPaymentAction::Fail { max_opening_fee_msat: 0, skimmed_fee_msat: 100 }
+	);
+
+	// If the LSP fails to open the channel, it gives up on the JIT channel.
+	let intercept_id = InterceptId([42; 32]);
+	service_handler
+		.htlc_intercepted(intercept_scid, intercept_id, 1_000_000, payment_hash)
+		.unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::OpenChannel {
+			their_network_key,
+			user_channel_id: ucid,
+			intercept_scid: iscid,
+			..
+		}) => {
+			assert_eq!(their_network_key, client_node_id);
+			assert_eq!(ucid, user_channel_id);
+			assert_eq!(iscid, intercept_scid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	expected_details.state = JITChannelState::PendingChannelOpen;
+	expected_details.opening_fee_msat = Some(100);
+	expected_details.num_queued_htlcs = 1;
+	expected_details.queued_amount_msat = 1_000_000;
+	assert_eq!(
+		service_handler.list_jit_channels_with_counterparty(&client_node_id),
+		vec![expected_details.clone()]
+	);
+	assert_eq!(service_handler.list_jit_channels(), vec![expected_details.clone()]);
+	assert_eq!(
+		service_handler.jit_channel_by_user_channel_id(&client_node_id, user_channel_id),
+		Some(expected_details)
+	);
+	assert!(service_handler.list_jit_channels_with_counterparty(&service_node_id).is_empty());
+
+	let reason = "Peer disconnected".to_string();
+	assert!(service_handler
+		.channel_open_failed(user_channel_id + 1, &client_node_id, reason.clone())
+		.is_err());
+	service_handler.channel_open_failed(user_channel_id, &client_node_id, reason.clone()).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::JITChannelFailed {
+			counterparty_node_id,
+			intercept_scid: iscid,
+			user_channel_id: ucid,
+			reason: r,
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(iscid, intercept_scid);
+			assert_eq!(ucid, user_channel_id);
+			assert_eq!(r, reason);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Further payments to the intercept scid are no longer recognized.
+	service_handler
+		.htlc_intercepted(intercept_scid, intercept_id, 1_000_000, payment_hash)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+	assert!(service_handler.channel_open_failed(user_channel_id, &client_node_id, reason).is_err());
+	assert!(service_handler.list_jit_channels().is_empty());
+	assert!(service_handler.cancel_jit_channel(&client_node_id, intercept_scid).is_err());
+}
+
+#[test]
+fn jit_channel_admin_flow() {
+	let promise_secret = [42; 32];
+	let lsps2_service_config = LSPS2ServiceConfig::new(promise_secret);
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+
+	let lsps2_client_config = LSPS2ClientConfig::default();
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: Some(lsps2_client_config),
+		lsps5_client_config: None,
+	};
+
+	let (service_node, client_node) =
+		create_service_and_client_nodes("jit_channel_admin_flow", service_config, client_config);
+
+	let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+
+	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 100,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 1,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	let opening_fee_params = match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+			opening_fee_params_menu,
+			..
+		}) => opening_fee_params_menu.first().unwrap().clone(),
+		_ => panic!("Unexpected event"),
+	};
+
+	// Buy two JIT channels.
+	let mut intercept_scids = Vec::new();
+	for user_channel_id in [42, 43] {
+		let buy_request_id = client_handler
+			.select_opening_params(service_node_id, None, opening_fee_params.clone())
+			.unwrap();
+		let buy_request = get_lsps_message!(client_node, service_node_id);
+		service_node.liquidity_manager.handle_custom_message(buy_request, &client_node_id).unwrap();
+		assert!(service_node.liquidity_manager.next_event().is_some());
+
+		let intercept_scid = service_node.channel_manager.get_intercept_scid();
+		service_handler
+			.invoice_parameters_generated(
+				&client_node_id,
+				buy_request_id,
+				intercept_scid,
+				144,
+				true,
+				user_channel_id,
+			)
+			.unwrap();
+		let _ = get_lsps_message!(service_node, client_node_id);
+		intercept_scids.push(intercept_scid);
+	}
+	assert_eq!(service_handler.list_jit_channels().len(), 2);
+
+	// Cancelling a JIT channel forgets about it and reports its failure.
+	assert!(service_handler.cancel_jit_channel(&service_node_id, intercept_scids[0]).is_err());
+	service_handler.cancel_jit_channel(&client_node_id, intercept_scids[0]).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::JITChannelFailed {
+			counterparty_node_id,
+			intercept_scid,
+			user_channel_id,
+			..
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(intercept_scid, intercept_scids[0]);
+			assert_eq!(user_channel_id, 42);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert_eq!(service_handler.jit_channel_by_intercept_scid(intercept_scids[0]), None);
+	assert!(service_handler.cancel_jit_channel(&client_node_id, intercept_scids[0]).is_err());
+
+	// Payments to the cancelled JIT channel are no longer recognized.
+	service_handler
+		.htlc_intercepted(
+			intercept_scids[0],
+			InterceptId([42; 32]),
+			1_000_000,
+			PaymentHash([42; 32]),
+		)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+
+	// Removing the client's state cancels its remaining JIT channel.
+	service_handler.remove_peer_state(&client_node_id).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::JITChannelFailed { intercept_scid, .. }) => {
+			assert_eq!(intercept_scid, intercept_scids[1]);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert!(service_handler.list_jit_channels().is_empty());
+	assert!(service_handler.remove_peer_state(&client_node_id).is_err());
+}
+
+#[test]
+fn htlc_intercepted_failure_cancels_jit_channel() {
+	let lsps2_service_config = LSPS2ServiceConfig {
+		promise_secret: [42; 32],
+		existing_channel_policy: ExistingChannelPolicy::OpenNewChannel,
+		max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+		max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+	};
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: Some(LSPS2ClientConfig::default()),
+		lsps5_client_config: None,
+	};
+
+	let (service_node, client_node) = create_service_and_client_nodes(
+		"htlc_intercepted_failure_cancels_jit_channel",
+		service_config,
+		client_config,
+	);
+
+	let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+
+	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 100,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 100_000,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	let opening_fee_params = match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+			opening_fee_params_menu,
+			..
+		}) => opening_fee_params_menu.first().unwrap().clone(),
+		_ => panic!("Unexpected event"),
+	};
+
+	// Buy a "no-MPP+var-invoice" JIT channel.
+	let buy_request_id =
+		client_handler.select_opening_params(service_node_id, None, opening_fee_params).unwrap();
+	let buy_request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(buy_request, &client_node_id).unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let intercept_scid = service_node.channel_manager.get_intercept_scid();
+	let user_channel_id = 42;
+	service_handler
+		.invoice_parameters_generated(
+			&client_node_id,
+			buy_request_id,
+			intercept_scid,
+			144,
+			true,
+			user_channel_id,
+		)
+		.unwrap();
+	let _ = get_lsps_message!(service_node, client_node_id);
+
+	// A payment below the minimum payment size can't be handled, which cancels the JIT channel.
+	assert!(service_handler
+		.htlc_intercepted(intercept_scid, InterceptId([42; 32]), 1_000, PaymentHash([42; 32]))
+		.is_err());
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::JITChannelFailed {
+			counterparty_node_id,
+			intercept_scid: failed_intercept_scid,
+			user_channel_id: failed_user_channel_id,
+			..
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(failed_intercept_scid, intercept_scid);
+			assert_eq!(failed_user_channel_id, user_channel_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	assert!(service_handler.list_jit_channels().is_empty());
+	assert_eq!(service_handler.jit_channel_by_intercept_scid(intercept_scid), None);
+	assert_eq!(
+		service_handler.jit_channel_by_user_channel_id(&client_node_id, user_channel_id),
+		None
+	);
+
+	// Further payments to the JIT channel are no longer recognized.
+	service_handler
+		.htlc_intercepted(intercept_scid, InterceptId([43; 32]), 1_000_000, PaymentHash([43; 32]))
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+}
+
+#[test]
+fn lsp_discovery_flow() {
+	let promise_secret = [42; 32];
+	let lsps2_service_config = LSPS2ServiceConfig {
+		promise_secret,
+		existing_channel_policy: ExistingChannelPolicy::OpenNewChannel,
+		max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+		max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+	};
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+
+	let lsps2_client_config = LSPS2ClientConfig::default();
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: Some(lsps2_client_config),
+		lsps5_client_config: None,
+	};
+
+	let (service_node, client_node) =
+		create_service_and_client_nodes("lsp_discovery_flow", service_config, client_config);
+
+	let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	client_node.liquidity_manager.query_lsp(service_node_id);
+	let directory = client_node.liquidity_manager.lsp_directory();
+	assert_eq!(directory.entry(&service_node_id).unwrap().protocols, None);
+
+	let list_protocols_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(list_protocols_request, &client_node_id)
+		.unwrap();
+	let list_protocols_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(list_protocols_response, &service_node_id)
+		.unwrap();
+
+	// Responses to the directory's queries aren't surfaced as events.
+	assert!(client_node.liquidity_manager.get_and_clear_pending_events().is_empty());
+	let directory = client_node.liquidity_manager.lsp_directory();
+	assert!(directory.entry(&service_node_id).unwrap().protocols.as_ref().unwrap().contains(&2));
+
+	// As the LSP supports LSPS2, we automatically asked for its fees.
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	let get_info_request_id = match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::GetInfo { request_id, .. }) => request_id,
+		_ => panic!("Unexpected event"),
+	};
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 100,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 1,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+
+	// Neither are they tracked by the client handler.
+	assert!(client_node.liquidity_manager.get_and_clear_pending_events().is_empty());
+	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+	assert!(client_handler.list_pending_get_info_requests().is_empty());
+
+	let now_secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+	let directory = client_node.liquidity_manager.lsp_directory();
+	let quotes = directory.ranked_lsps2_quotes(1_000_000, now_secs);
+	assert_eq!(quotes.len(), 1);
+	assert_eq!(quotes[0].node_id, service_node_id);
+	assert_eq!(quotes[0].opening_fee_msat, 100);
+
+	// Responses to requests we didn't issue for the directory don't change it.
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	service_node.liquidity_manager.next_event().unwrap();
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 200,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 1,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady { .. }) => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	let directory = client_node.liquidity_manager.lsp_directory();
+	assert_eq!(directory.ranked_lsps2_quotes(1_000_000, now_secs)[0].opening_fee_msat, 100);
+}
+
+#[test]
+fn htlc_intercepted_with_existing_channel() {
+	for existing_channel_policy in [
+		ExistingChannelPolicy::OpenNewChannel,
+		ExistingChannelPolicy::ReuseChargingFee,
+		ExistingChannelPolicy::ReuseWaivingFee,
+	] {
+		let lsps2_service_config = LSPS2ServiceConfig {
+			promise_secret: [42; 32],
+			existing_channel_policy,
+			max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+			max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+		};
+		let service_config = LiquidityServiceConfig {
+			#[cfg(lsps1)]
+			lsps1_service_config: None,
+			lsps2_service_config: Some(lsps2_service_config),
+			lsps5_service_config: None,
+			advertise_service: true,
+		};
+		let client_config = LiquidityClientConfig {
+			#[cfg(lsps1)]
+			lsps1_client_config: None,
+			lsps2_client_config: Some(LSPS2ClientConfig::default()),
+			lsps5_client_config: None,
+		};
+
+		let (mut service_node, mut client_node) = create_service_and_client_nodes(
+			&format!("htlc_intercepted_with_existing_channel_{:?}", existing_channel_policy),
+			service_config,
+			client_config,
+		);
+		let existing_channel_id =
+			create_usable_channel(&mut service_node, &mut client_node, 1_000_000);
+
+		let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+		let service_node_id = service_node.channel_manager.get_our_node_id();
+		let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+		let client_node_id = client_node.channel_manager.get_our_node_id();
+
+		let get_info_request_id =
+			client_handler.request_opening_params(service_node_id, None).unwrap();
+		let get_info_request = get_lsps_message!(client_node, service_node_id);
+		service_node
+			.liquidity_manager
+			.handle_custom_message(get_info_request, &client_node_id)
+			.unwrap();
+		assert!(service_node.liquidity_manager.next_event().is_some());
+
+		let raw_opening_params = RawOpeningFeeParams {
+			min_fee_msat: 100,
+			proportional: 21,
+			valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+			min_lifetime: 144,
+			max_client_to_self_delay: 128,
+			min_payment_size_msat: 1,
+			max_payment_size_msat: 100_000_000,
+		};
+		service_handler
+			.opening_fee_params_generated(
+				&client_node_id,
+				get_info_request_id,
+				vec![raw_opening_params],
+			)
+			.unwrap();
+		let get_info_response = get_lsps_message!(service_node, client_node_id);
+		client_node
+			.liquidity_manager
+			.handle_custom_message(get_info_response, &service_node_id)
+			.unwrap();
+		let opening_fee_params = match client_node.liquidity_manager.next_event().unwrap() {
+			Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+				opening_fee_params_menu,
+				..
+			}) => opening_fee_params_menu.first().unwrap().clone(),
+			_ => panic!("Unexpected event"),
+		};
+
+		let buy_request_id = client_handler
+			.select_opening_params(service_node_id, None, opening_fee_params)
+			.unwrap();
+		let buy_request = get_lsps_message!(client_node, service_node_id);
+		service_node.liquidity_manager.handle_custom_message(buy_request, &client_node_id).unwrap();
+		assert!(service_node.liquidity_manager.next_event().is_some());
+
+		let user_channel_id = 7;
+		let intercept_scid = service_node.channel_manager.get_intercept_scid();
+		service_handler
+			.invoice_parameters_generated(
+				&client_node_id,
+				buy_request_id,
+				intercept_scid,
+				144,
+				true,
+				user_channel_id,
+			)
+			.unwrap();
+		let _ = get_lsps_message!(service_node, client_node_id);
+
+		let payment_hash = PaymentHash([42; 32]);
+		let result = service_handler.htlc_intercepted(
+			intercept_scid,
+			InterceptId([42; 32]),
+			1_000_000,
+			payment_hash,
+		);
+		let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+		match existing_channel_policy {
+			ExistingChannelPolicy::OpenNewChannel => {
+				result.unwrap();
+				match service_node.liquidity_manager.next_event().unwrap() {
+					Event::LSPS2Service(LSPS2ServiceEvent::OpenChannel {
+						intercept_scid: iscid,
+						..
+					}) => assert_eq!(iscid, intercept_scid),
+					_ => panic!("Unexpected event"),
+				}
+				assert_eq!(details.state, JITChannelState::PendingChannelOpen);
+				assert_eq!(details.channel_id, None);
+			},
+			ExistingChannelPolicy::ReuseChargingFee => {
+				// We never actually intercepted the HTLC, so forwarding it fails, and we await
+				// another payment to forward over the existing channel.
+				assert!(result.is_err());
+				assert!(service_node.liquidity_manager.next_event().is_none());
+				assert_eq!(details.state, JITChannelState::PendingPayment);
+				assert_eq!(details.channel_id, Some(existing_channel_id));
+			},
+			ExistingChannelPolicy::ReuseWaivingFee => {
+				// We never actually intercepted the HTLC, so forwarding it fails, and we don't
+				// report the waived fee.
+				assert!(result.is_err());
+				assert!(service_node.liquidity_manager.next_event().is_none());
+				assert_eq!(details.state, JITChannelState::PaymentForwarded);
+				assert_eq!(details.channel_id, Some(existing_channel_id));
+			},
+		}
+
+		// Other forwards over the existing channel are not attributed to the JIT channel.
+		service_handler.payment_forwarded(existing_channel_id, None).unwrap();
+		assert!(service_node.liquidity_manager.next_event().is_none());
+		service_handler
+			.htlc_handling_failed(HTLCDestination::NextHopChannel {
+				node_id: Some(client_node_id),
+				channel_id: existing_channel_id,
+			})
+			.unwrap();
+		assert_eq!(
+			service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap().state,
+			details.state
+		);
+
+		// Closing the existing channel only affects the JIT channel if we reused it.
+		let existing_user_channel_id = 42;
+		service_handler.channel_closed(
+			existing_user_channel_id,
+			&existing_channel_id,
+			&client_node_id,
+			&ClosureReason::LegacyCooperativeClosure,
+		);
+		match existing_channel_policy {
+			ExistingChannelPolicy::OpenNewChannel => {
+				assert!(service_handler.jit_channel_by_intercept_scid(intercept_scid).is_some());
+			},
+			ExistingChannelPolicy::ReuseChargingFee | ExistingChannelPolicy::ReuseWaivingFee => {
+				assert!(service_handler.list_jit_channels().is_empty());
+			},
+		}
+	}
+}
+
+#[test]
+fn fee_charged_over_reused_channel() {
+	let lsps2_service_config = LSPS2ServiceConfig {
+		promise_secret: [42; 32],
+		existing_channel_policy: ExistingChannelPolicy::ReuseChargingFee,
+		max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+		max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+	};
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: Some(LSPS2ClientConfig::default()),
+		lsps5_client_config: None,
+	};
+
+	let persist_dir = "fee_charged_over_reused_channel";
+	let (mut service_node, mut client_node) =
+		create_service_and_client_nodes(persist_dir, service_config, client_config);
+	let persist_dir = env::temp_dir().join(persist_dir).to_string_lossy().to_string();
+	let mut payer_node = create_liquidity_node(3, &persist_dir, Network::Bitcoin, None, None);
+	connect_nodes(&payer_node, &service_node);
+	create_usable_channel(&mut payer_node, &mut service_node, 1_000_000);
+	let existing_channel_id = create_usable_channel(&mut service_node, &mut client_node, 1_000_000);
+
+	let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 100,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 1,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	let opening_fee_params = match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+			opening_fee_params_menu,
+			..
+		}) => opening_fee_params_menu.first().unwrap().clone(),
+		_ => panic!("Unexpected event"),
+	};
+
+	let buy_request_id =
+		client_handler.select_opening_params(service_node_id, None, opening_fee_params).unwrap();
+	let buy_request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(buy_request, &client_node_id).unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let user_channel_id = 7;
+	let intercept_scid = service_node.channel_manager.get_intercept_scid();
+	service_handler
+		.invoice_parameters_generated(
+			&client_node_id,
+			buy_request_id,
+			intercept_scid,
+			144,
+			true,
+			user_channel_id,
+		)
+		.unwrap();
+	let _ = get_lsps_message!(service_node, client_node_id);
+
+	// The payment is forwarded over the existing channel, without opening a JIT channel.
+	let paying_payment_hash = PaymentHash([42; 32]);
+	let (intercept_id, expected_outbound_amount_msat) = send_intercepted_htlc(
+		&payer_node,
+		&service_node,
+		intercept_scid,
+		client_node_id,
+		5_000_000,
+		paying_payment_hash,
+	);
+	service_handler
+		.htlc_intercepted(
+			intercept_scid,
+			intercept_id,
+			expected_outbound_amount_msat,
+			paying_payment_hash,
+		)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPaymentForward);
+	assert_eq!(details.channel_id, Some(existing_channel_id));
+
+	// Other forwards over the existing channel don't skim a fee, so they're not mistaken for the
+	// fee payment.
+	service_handler.payment_forwarded(existing_channel_id, None).unwrap();
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPaymentForward);
+
+	// The opening fee of max(100, 5_000_000 * 21 / 1_000_000) = 105 msat is collected as usual.
+	service_handler.payment_forwarded(existing_channel_id, Some(105)).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::OpeningFeeCollected {
+			counterparty_node_id,
+			intercept_scid: iscid,
+			user_channel_id: ucid,
+			channel_id,
+			payment_hash,
+			amount_msat,
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(iscid, intercept_scid);
+			assert_eq!(ucid, user_channel_id);
+			assert_eq!(channel_id, existing_channel_id);
+			assert_eq!(payment_hash, paying_payment_hash);
+			assert_eq!(amount_msat, 105);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PaymentForwarded);
+
+	// Later forwards over the existing channel leave the JIT channel alone.
+	service_handler.payment_forwarded(existing_channel_id, None).unwrap();
+	service_handler
+		.htlc_handling_failed(HTLCDestination::NextHopChannel {
+			node_id: Some(client_node_id),
+			channel_id: existing_channel_id,
+		})
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PaymentForwarded);
+}
+
+#[test]
+fn fee_payment_forwarding() {
+	let lsps2_service_config = LSPS2ServiceConfig {
+		promise_secret: [42; 32],
+		existing_channel_policy: ExistingChannelPolicy::OpenNewChannel,
+		max_htlcs_per_payment: DEFAULT_MAX_HTLCS_PER_PAYMENT,
+		max_queued_payments: DEFAULT_MAX_QUEUED_PAYMENTS,
+	};
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: Some(lsps2_service_config),
+		lsps5_service_config: None,
+		advertise_service: true,
+	};
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: Some(LSPS2ClientConfig::default()),
+		lsps5_client_config: None,
+	};
+
+	let persist_dir = "fee_payment_forwarding";
+	let (mut service_node, mut client_node) =
+		create_service_and_client_nodes(persist_dir, service_config, client_config);
+	let persist_dir = env::temp_dir().join(persist_dir).to_string_lossy().to_string();
+	let mut payer_node = create_liquidity_node(3, &persist_dir, Network::Bitcoin, None, None);
+	connect_nodes(&payer_node, &service_node);
+	create_usable_channel(&mut payer_node, &mut service_node, 1_000_000);
+	// We use this channel as the JIT channel, as if we opened it after intercepting the payment.
+	let channel_id = create_usable_channel(&mut service_node, &mut client_node, 1_000_000);
+
+	let service_handler = service_node.liquidity_manager.lsps2_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+	let client_handler = client_node.liquidity_manager.lsps2_client_handler().unwrap();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let get_info_request_id = client_handler.request_opening_params(service_node_id, None).unwrap();
+	let get_info_request = get_lsps_message!(client_node, service_node_id);
+	service_node
+		.liquidity_manager
+		.handle_custom_message(get_info_request, &client_node_id)
+		.unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let raw_opening_params = RawOpeningFeeParams {
+		min_fee_msat: 100,
+		proportional: 21,
+		valid_until: DateTime::parse_from_rfc3339("2035-05-20T08:30:45Z").unwrap().into(),
+		min_lifetime: 144,
+		max_client_to_self_delay: 128,
+		min_payment_size_msat: 1,
+		max_payment_size_msat: 100_000_000,
+	};
+	service_handler
+		.opening_fee_params_generated(
+			&client_node_id,
+			get_info_request_id,
+			vec![raw_opening_params],
+		)
+		.unwrap();
+	let get_info_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(get_info_response, &service_node_id)
+		.unwrap();
+	let opening_fee_params = match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Client(LSPS2ClientEvent::OpeningParametersReady {
+			opening_fee_params_menu,
+			..
+		}) => opening_fee_params_menu.first().unwrap().clone(),
+		_ => panic!("Unexpected event"),
+	};
+
+	let buy_request_id = client_handler
+		.select_opening_params(service_node_id, Some(2_000_000), opening_fee_params)
+		.unwrap();
+	let buy_request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(buy_request, &client_node_id).unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_some());
+
+	let user_channel_id = 7;
+	let intercept_scid = service_node.channel_manager.get_intercept_scid();
+	service_handler
+		.invoice_parameters_generated(
+			&client_node_id,
+			buy_request_id,
+			intercept_scid,
+			144,
+			true,
+			user_channel_id,
+		)
+		.unwrap();
+	let _ = get_lsps_message!(service_node, client_node_id);
+
+	// The initial payment consists of an HTLC we actually intercepted, and one the
+	// `ChannelManager` doesn't know about, so that only the former can be forwarded.
+	let payment_hash = PaymentHash([42; 32]);
+	let (intercept_id, expected_outbound_amount_msat) = send_intercepted_htlc(
+		&payer_node,
+		&service_node,
+		intercept_scid,
+		client_node_id,
+		1_000_000,
+		payment_hash,
+	);
+	service_handler
+		.htlc_intercepted(intercept_scid, intercept_id, expected_outbound_amount_msat, payment_hash)
+		.unwrap();
+	service_handler
+		.htlc_intercepted(intercept_scid, InterceptId([42; 32]), 1_000_000, payment_hash)
+		.unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::OpenChannel { intercept_scid: iscid, .. }) => {
+			assert_eq!(iscid, intercept_scid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// If only part of the fee payment is forwarded, we await the outcome of the forwarded HTLCs.
+	assert!(service_handler.channel_ready(user_channel_id, &channel_id, &client_node_id).is_err());
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::JITChannelOpened {
+			counterparty_node_id,
+			intercept_scid: iscid,
+			user_channel_id: ucid,
+			channel_id: cid,
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(iscid, intercept_scid);
+			assert_eq!(ucid, user_channel_id);
+			assert_eq!(cid, channel_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPaymentForward);
+	assert_eq!(details.channel_id, Some(channel_id));
+
+	// As the payment is incomplete, the forwarded HTLC is failed back, rolling the JIT channel back.
+	service_handler
+		.htlc_handling_failed(HTLCDestination::NextHopChannel {
+			node_id: Some(client_node_id),
+			channel_id,
+		})
+		.unwrap();
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPayment);
+
+	// If none of the fee payment can be forwarded, the JIT channel is rolled back right away.
+	let other_payment_hash = PaymentHash([43; 32]);
+	assert!(service_handler
+		.htlc_intercepted(intercept_scid, InterceptId([43; 32]), 2_000_000, other_payment_hash)
+		.is_err());
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPayment);
+	assert_eq!(details.num_queued_htlcs, 0);
+
+	// Once a payment can be forwarded in full, we await its settlement.
+	let paying_payment_hash = PaymentHash([44; 32]);
+	let (intercept_id, expected_outbound_amount_msat) = send_intercepted_htlc(
+		&payer_node,
+		&service_node,
+		intercept_scid,
+		client_node_id,
+		5_000_000,
+		paying_payment_hash,
+	);
+	service_handler
+		.htlc_intercepted(
+			intercept_scid,
+			intercept_id,
+			expected_outbound_amount_msat,
+			paying_payment_hash,
+		)
+		.unwrap();
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PendingPaymentForward);
+
+	// The opening fee of max(100, 5_000_000 * 21 / 1_000_000) = 105 msat is only reported as
+	// collected once the fees skimmed from the payment's HTLCs add up to it.
+	service_handler.payment_forwarded(channel_id, Some(60)).unwrap();
+	let details = service_handler.jit_channel_by_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(details.state, JITChannelState::PaymentForwarded);
+	assert!(service_node.liquidity_manager.next_event().is_none());
+	service_handler.payment_forwarded(channel_id, None).unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
+	service_handler.payment_forwarded(channel_id, Some(45)).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS2Service(LSPS2ServiceEvent::OpeningFeeCollected {
+			counterparty_node_id,
+			intercept_scid: iscid,
+			user_channel_id: ucid,
+			channel_id: cid,
+			payment_hash,
+			amount_msat,
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(iscid, intercept_scid);
+			assert_eq!(ucid, user_channel_id);
+			assert_eq!(cid, channel_id);
+			assert_eq!(payment_hash, paying_payment_hash);
+			assert_eq!(amount_msat, 105);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	service_handler.payment_forwarded(channel_id, Some(1)).unwrap();
+	assert!(service_node.liquidity_manager.next_event().is_none());
 }
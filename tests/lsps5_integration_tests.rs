@@ -0,0 +1,205 @@
+#![cfg(all(test, feature = "std"))]
+
+mod common;
+
+use common::{create_service_and_client_nodes, get_lsps_message};
+
+use lightning_liquidity::events::Event;
+use lightning_liquidity::lsps0::ser::RawLSPSMessage;
+use lightning_liquidity::lsps5::client::LSPS5ClientConfig;
+use lightning_liquidity::lsps5::event::{LSPS5ClientEvent, LSPS5ServiceEvent};
+use lightning_liquidity::lsps5::msgs::WebhookNotification;
+use lightning_liquidity::lsps5::service::LSPS5ServiceConfig;
+use lightning_liquidity::{LiquidityClientConfig, LiquidityServiceConfig};
+
+use lightning::ln::peer_handler::CustomMessageHandler;
+
+#[test]
+fn webhook_registration_flow() {
+	let lsps5_service_config = LSPS5ServiceConfig { max_webhooks_per_client: 1 };
+	let service_config = LiquidityServiceConfig {
+		#[cfg(lsps1)]
+		lsps1_service_config: None,
+		lsps2_service_config: None,
+		lsps5_service_config: Some(lsps5_service_config),
+		advertise_service: true,
+	};
+
+	let client_config = LiquidityClientConfig {
+		#[cfg(lsps1)]
+		lsps1_client_config: None,
+		lsps2_client_config: None,
+		lsps5_client_config: Some(LSPS5ClientConfig::default()),
+	};
+
+	let (service_node, client_node) =
+		create_service_and_client_nodes("webhook_registration_flow", service_config, client_config);
+
+	let service_handler = service_node.liquidity_manager.lsps5_service_handler().unwrap();
+	let service_node_id = service_node.channel_manager.get_our_node_id();
+
+	let client_handler = client_node.liquidity_manager.lsps5_client_handler().unwrap();
+	let client_node_id = client_node.channel_manager.get_our_node_id();
+
+	let app_name = "my-app".to_string();
+	let webhook = "https://www.example.org/push?l=1234567890".to_string();
+
+	// Registering a webhook emits a `webhook_registered` notification.
+	let set_webhook_request_id =
+		client_handler.set_webhook(service_node_id, app_name.clone(), webhook.clone()).unwrap();
+	let set_webhook_request = get_lsps_message!(client_node, service_node_id);
+
+	service_node
+		.liquidity_manager
+		.handle_custom_message(set_webhook_request, &client_node_id)
+		.unwrap();
+
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Service(LSPS5ServiceEvent::SendWebhookNotification {
+			counterparty_node_id,
+			app_name: event_app_name,
+			webhook: event_webhook,
+			notification,
+		}) => {
+			assert_eq!(counterparty_node_id, client_node_id);
+			assert_eq!(event_app_name, app_name);
+			assert_eq!(event_webhook, webhook);
+			assert_eq!(notification, WebhookNotification::WebhookRegistered);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	let set_webhook_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(set_webhook_response, &service_node_id)
+		.unwrap();
+
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Client(LSPS5ClientEvent::WebhookRegistered {
+			request_id,
+			counterparty_node_id,
+			num_webhooks,
+			max_webhooks,
+			no_change,
+			..
+		}) => {
+			assert_eq!(request_id, set_webhook_request_id);
+			assert_eq!(counterparty_node_id, service_node_id);
+			assert_eq!(num_webhooks, 1);
+			assert_eq!(max_webhooks, 1);
+			assert!(!no_change);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Registering another app exceeds the limit.
+	let other_request_id = client_handler
+		.set_webhook(service_node_id, "other-app".to_string(), webhook.clone())
+		.unwrap();
+	let set_webhook_request = get_lsps_message!(client_node, service_node_id);
+	assert!(service_node
+		.liquidity_manager
+		.handle_custom_message(set_webhook_request, &client_node_id)
+		.is_err());
+	assert!(service_node.liquidity_manager.next_event().is_none());
+
+	let set_webhook_response = get_lsps_message!(service_node, client_node_id);
+	client_node
+		.liquidity_manager
+		.handle_custom_message(set_webhook_response, &service_node_id)
+		.unwrap();
+
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Client(LSPS5ClientEvent::WebhookRegistrationFailed {
+			request_id,
+			error,
+			..
+		}) => {
+			assert_eq!(request_id, other_request_id);
+			assert_eq!(error.code, 503);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// The LSP can notify the client via the registered webhook.
+	service_handler.notify(&client_node_id, WebhookNotification::PaymentIncoming).unwrap();
+	match service_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Service(LSPS5ServiceEvent::SendWebhookNotification {
+			notification, ..
+		}) => {
+			assert_eq!(notification, WebhookNotification::PaymentIncoming);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Listing returns the registered app.
+	let list_request_id = client_handler.list_webhooks(service_node_id);
+	let list_request = get_lsps_message!(client_node, service_node_id);
+	service_node.liquidity_manager.handle_custom_message(list_request, &client_node_id).unwrap();
+	let list_response = get_lsps_message!(service_node, client_node_id);
+	client_node.liquidity_manager.handle_custom_message(list_response, &service_node_id).unwrap();
+
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Client(LSPS5ClientEvent::WebhooksListed { request_id, app_names, .. }) => {
+			assert_eq!(request_id, list_request_id);
+			assert_eq!(app_names, vec![app_name.clone()]);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// An error returned by the LSP is surfaced to the user.
+	let list_request_id = client_handler.list_webhooks(service_node_id);
+	let _list_request = get_lsps_message!(client_node, service_node_id);
+	let list_error = serde_json::json!({
+		"jsonrpc": "2.0",
+		"id": list_request_id.0,
+		"error": { "code": -32603, "message": "Internal error" },
+	});
+	client_node
+		.liquidity_manager
+		.handle_custom_message(RawLSPSMessage { payload: list_error.to_string() }, &service_node_id)
+		.unwrap();
+
+	match client_node.liquidity_manager.next_event().unwrap() {
+		Event::LSPS5Client(LSPS5ClientEvent::WebhooksListFailed { request_id, error, .. }) => {
+			assert_eq!(request_id, list_request_id);
+			assert_eq!(error.code, -32603);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Removing the webhook works once, after which the app is unknown.
+	for expect_success in [true, false] {
+		let remove_request_id =
+			client_handler.remove_webhook(service_node_id, app_name.clone()).unwrap();
+		let remove_request = get_lsps_message!(client_node, service_node_id);
+		let res =
+			service_node.liquidity_manager.handle_custom_message(remove_request, &client_node_id);
+		assert_eq!(res.is_ok(), expect_success);
+		let remove_response = get_lsps_message!(service_node, client_node_id);
+		client_node
+			.liquidity_manager
+			.handle_custom_message(remove_response, &service_node_id)
+			.unwrap();
+
+		match client_node.liquidity_manager.next_event().unwrap() {
+			Event::LSPS5Client(LSPS5ClientEvent::WebhookRemoved { request_id, .. }) => {
+				assert!(expect_success);
+				assert_eq!(request_id, remove_request_id);
+			},
+			Event::LSPS5Client(LSPS5ClientEvent::WebhookRemovalFailed {
+				request_id,
+				error,
+				..
+			}) => {
+				assert!(!expect_success);
+				assert_eq!(request_id, remove_request_id);
+				assert_eq!(error.code, 1010);
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+
+	assert!(service_handler.notify(&client_node_id, WebhookNotification::PaymentIncoming).is_err());
+}